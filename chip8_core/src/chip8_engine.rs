@@ -1,6 +1,6 @@
 pub mod chip8_engine {
     use crate::device::device::DisplayDevice;
-    use crate::proc::proc::Proc;
+    use crate::proc::proc::{Fault, Proc, BIG_FONT_ADDR};
     use crate::syscall::syscall::SyscallOutcome;
     use rand::Rng;
     use std::io::Error;
@@ -49,6 +49,39 @@ pub mod chip8_engine {
         };
     }
 
+    // guest stores go through here so a read-only page faults the proc
+    // instead of panicking; PC is left on the faulting instruction.
+    fn store_u8<D: DisplayDevice>(proc: &mut Proc<D>, addr: u32, value: u8) -> Result<(), SyscallOutcome> {
+        if proc.is_read_only(addr) {
+            return Err(proc.raise_fault(Fault::WriteProtected { addr }));
+        }
+        proc.write_u8(addr, value).unwrap();
+        Ok(())
+    }
+
+    /// true when some handler defines `instruction`. The handlers skip the
+    /// rest as no-ops; `Proc::trap_unknown` faults on them instead. 0nnn
+    /// outside the syscall range (legacy SYS calls) counts as unknown.
+    pub fn is_known_opcode(instruction: u16) -> bool {
+        let nnn = extract_nnn!(instruction);
+        let kk = extract_kk!(instruction);
+        let z = extract_z!(instruction);
+        match instruction >> 12 {
+            0x0 => {
+                matches!(nnn, 0x0e0 | 0x0ee | 0x0c0..=0x0cf | 0x0fb | 0x0fc | 0x0fe | 0x0ff)
+                    || (0x0100..0x0200).contains(&nnn)
+            },
+            0x5 | 0x9 => z == 0,
+            0x8 => matches!(z, 0x0..=0x7 | 0xE),
+            0xE => matches!(kk, 0x9E | 0xA1),
+            0xF => matches!(
+                kk,
+                0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65 | 0x75 | 0x85
+            ),
+            _ => true,
+        }
+    }
+
     pub fn opcode_0x0<F, D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16, mut dispatch_syscall: F) -> SyscallOutcome
     where
         F: FnMut(u16, &mut Proc<D>) -> Result<SyscallOutcome, Error>,
@@ -63,6 +96,9 @@ pub mod chip8_engine {
             },
             0x00ee => {
                 // stack grows downward; SP points to top of stack.
+                if proc.regs.SP as u32 + 2 > proc.stack_top as u32 {
+                    return proc.raise_fault(Fault::StackUnderflow { sp: proc.regs.SP });
+                }
                 let val1 = proc.read_u8(proc.regs.SP as u32).unwrap() as u16;
                 let val1 = val1 << 8;
 
@@ -72,6 +108,28 @@ pub mod chip8_engine {
                 proc.regs.SP = proc.regs.SP.wrapping_add(2);
                 SyscallOutcome::Completed
             },
+            // SUPER-CHIP scrolls: 00CN down N rows, 00FB/00FC 4 columns.
+            0x00c0..=0x00cf => {
+                proc.display.scroll_down(extract_z!(instruction));
+                proc.regs.PC += 2;
+                SyscallOutcome::Completed
+            },
+            0x00fb => {
+                proc.display.scroll_right();
+                proc.regs.PC += 2;
+                SyscallOutcome::Completed
+            },
+            0x00fc => {
+                proc.display.scroll_left();
+                proc.regs.PC += 2;
+                SyscallOutcome::Completed
+            },
+            // SUPER-CHIP resolution: 00FE lo-res (64x32), 00FF hi-res (128x64).
+            0x00fe | 0x00ff => {
+                proc.display.set_hires(instruction == 0x00ff);
+                proc.regs.PC += 2;
+                SyscallOutcome::Completed
+            },
             _ => {
                 if (0x0100..0x0200).contains(&nnn) {
                     // syscall range is reserved for the host dispatcher.
@@ -88,6 +146,7 @@ pub mod chip8_engine {
                             proc.regs.PC += 2;
                             SyscallOutcome::Blocked
                         },
+                        Ok(SyscallOutcome::Faulted) => SyscallOutcome::Faulted,
                         Err(_) => {
                             proc.regs.V[0xF] = 1;
                             proc.regs.V[0] = 0x01;
@@ -108,18 +167,26 @@ pub mod chip8_engine {
     }
 
     // stack uses virtual addresses; translation handles paging.
-    pub fn opcode_0x2<D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16) {
+    pub fn opcode_0x2<D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16) -> SyscallOutcome {
         // return address is stored as two bytes (hi/lo).
         let mut data: Vec<u8> = Vec::new();
         data.push(((proc.regs.PC + 2) >> 8) as u8);
         data.push((proc.regs.PC + 2) as u8);
         
         // write via virtual addresses to respect paging.
-        proc.regs.SP = proc.regs.SP.wrapping_sub(2);
-        proc.write_u8(proc.regs.SP as u32, data[0]).unwrap();
-        proc.write_u8((proc.regs.SP + 1) as u32, data[1]).unwrap();
+        let Some(sp) = proc.regs.SP.checked_sub(2).filter(|&sp| sp >= proc.stack_floor) else {
+            return proc.raise_fault(Fault::StackOverflow { sp: proc.regs.SP });
+        };
+        if let Err(outcome) = store_u8(proc, sp as u32, data[0]) {
+            return outcome;
+        }
+        if let Err(outcome) = store_u8(proc, (sp + 1) as u32, data[1]) {
+            return outcome;
+        }
+        proc.regs.SP = sp;
 
         proc.regs.PC = extract_nnn!(instruction);
+        SyscallOutcome::Completed
     }
 
     pub fn opcode_0x3<D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16) {
@@ -225,8 +292,10 @@ pub mod chip8_engine {
                 proc.regs.PC += 2;
             },
             0x06 => {
-                proc.regs.V[0xF] = proc.regs.V[var_x] & 1;
-                proc.regs.V[var_x] = proc.regs.V[var_x] >> 1;
+                let src = if proc.quirks.shift_uses_vy { var_y } else { var_x };
+                let val = proc.regs.V[src];
+                proc.regs.V[0xF] = val & 1;
+                proc.regs.V[var_x] = val >> 1;
                 proc.regs.PC += 2;
             },
             0x07 => {
@@ -244,8 +313,10 @@ pub mod chip8_engine {
                 proc.regs.PC += 2;
             },
             0x0E => {
-                proc.regs.V[0xF] = (proc.regs.V[var_x] & 0x80) >> 7;
-                proc.regs.V[var_x] = proc.regs.V[var_x] << 1;
+                let src = if proc.quirks.shift_uses_vy { var_y } else { var_x };
+                let val = proc.regs.V[src];
+                proc.regs.V[0xF] = (val & 0x80) >> 7;
+                proc.regs.V[var_x] = val << 1;
                 proc.regs.PC += 2;
             },
             _ => {},
@@ -269,7 +340,14 @@ pub mod chip8_engine {
     }
 
     pub fn opcode_0xB<D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16) {
-        proc.regs.PC = extract_nnn!(instruction) + proc.regs.V[0] as u16;
+        let nnn = extract_nnn!(instruction);
+        let offset = if proc.quirks.jump_uses_vx {
+            proc.regs.V[extract_x!(instruction) as usize]
+        } else {
+            proc.regs.V[0]
+        };
+        // PC is 12 bits; a large offset wraps instead of leaving the 4K space.
+        proc.regs.PC = (nnn + offset as u16) & 0x0FFF;
     }
 
     pub fn opcode_0xC<D: DisplayDevice>(proc: &mut Proc<D>, instruction: u16) {
@@ -292,7 +370,9 @@ pub mod chip8_engine {
         let y = proc.regs.V[var_y as usize] as u32;
 
         let addr = proc.regs.I as u32;
-        let sprite = proc.read_bytes(addr, var_z as usize).unwrap();
+        // SUPER-CHIP Dxy0 in hi-res is a 16x16 sprite: 32 bytes.
+        let len = if var_z == 0 && proc.display.hires() { 32 } else { var_z as usize };
+        let sprite = proc.read_bytes(addr, len).unwrap();
 
         proc.display.draw_sprite(&mut proc.regs, &sprite, x, y);
 
@@ -333,12 +413,20 @@ pub mod chip8_engine {
                 SyscallOutcome::Completed
             },
             0x0A => {
-                if let Some(key) = proc.last_key() {
-                    proc.regs.V[var_x as usize] = key;
-                    proc.regs.PC += 0x2;
-                    SyscallOutcome::Completed
-                } else {
-                    SyscallOutcome::Blocked
+                // like the original hardware, wait for a press and then its
+                // release, so a key held across frames is accepted once.
+                match proc.key_wait {
+                    None => {
+                        proc.key_wait = proc.last_key();
+                        SyscallOutcome::Blocked
+                    }
+                    Some(key) if proc.is_key_down(key) => SyscallOutcome::Blocked,
+                    Some(key) => {
+                        proc.key_wait = None;
+                        proc.regs.V[var_x as usize] = key;
+                        proc.regs.PC += 0x2;
+                        SyscallOutcome::Completed
+                    }
                 }
             },
             0x15 => {
@@ -362,23 +450,35 @@ pub mod chip8_engine {
                 proc.regs.PC += 0x2;
                 SyscallOutcome::Completed
             },
+            0x30 => {
+                let val = proc.regs.V[var_x as usize] as u16;
+                proc.regs.I = BIG_FONT_ADDR + val * 0xA;
+                proc.regs.PC += 0x2;
+                SyscallOutcome::Completed
+            },
             0x33 => {
                 let val = proc.regs.V[var_x as usize];
                 let hundreds = val / 100;
                 let tens = (val % 100) / 10;
                 let ones = val % 10;
-                proc.write_u8(proc.regs.I as u32, hundreds).unwrap();
-                proc.write_u8((proc.regs.I + 1) as u32, tens).unwrap();
-                proc.write_u8((proc.regs.I + 2) as u32, ones).unwrap();
+                for (idx, digit) in [hundreds, tens, ones].into_iter().enumerate() {
+                    if let Err(outcome) = store_u8(proc, (proc.regs.I + idx as u16) as u32, digit) {
+                        return outcome;
+                    }
+                }
                 proc.regs.PC += 0x2;
                 SyscallOutcome::Completed
             },
             0x55 => {
                 for i in 0..=var_x {
                     let offset = proc.regs.I.wrapping_add(i as u16);
-                    proc.write_u8(offset as u32, proc.regs.V[i as usize]).unwrap();
+                    if let Err(outcome) = store_u8(proc, offset as u32, proc.regs.V[i as usize]) {
+                        return outcome;
+                    }
+                }
+                if proc.quirks.load_store_increments_i {
+                    proc.regs.I = proc.regs.I.wrapping_add(var_x as u16 + 1);
                 }
-                proc.regs.I = proc.regs.I.wrapping_add(var_x as u16 + 1);
                 proc.regs.PC += 0x2;
                 SyscallOutcome::Completed
             },
@@ -387,7 +487,22 @@ pub mod chip8_engine {
                     let offset = proc.regs.I.wrapping_add(i as u16);
                     proc.regs.V[i as usize] = proc.read_u8(offset as u32).unwrap();
                 }
-                proc.regs.I = proc.regs.I.wrapping_add(var_x as u16 + 1);
+                if proc.quirks.load_store_increments_i {
+                    proc.regs.I = proc.regs.I.wrapping_add(var_x as u16 + 1);
+                }
+                proc.regs.PC += 0x2;
+                SyscallOutcome::Completed
+            },
+            // SUPER-CHIP RPL flags: only eight, so X is clamped to 7.
+            0x75 => {
+                let count = var_x.min(7) as usize + 1;
+                proc.flags[..count].copy_from_slice(&proc.regs.V[..count]);
+                proc.regs.PC += 0x2;
+                SyscallOutcome::Completed
+            },
+            0x85 => {
+                let count = var_x.min(7) as usize + 1;
+                proc.regs.V[..count].copy_from_slice(&proc.flags[..count]);
                 proc.regs.PC += 0x2;
                 SyscallOutcome::Completed
             },
@@ -11,7 +11,21 @@ pub mod device {
     pub trait DisplayDevice {
         fn poll_input(&mut self, capture_text: bool);
         fn clear_screen(&mut self);
+        /// XOR an 8-wide sprite, one byte per row; in hi-res a 32-byte
+        /// sprite is 16x16, two bytes per row.
         fn draw_sprite(&mut self, regs: &mut Registers, sprite: &[u8], x_pos: u32, y_pos: u32);
+        /// SUPER-CHIP scrolls, in display pixels; vacated rows/columns clear.
+        fn scroll_down(&mut self, rows: u8);
+        fn scroll_right(&mut self);
+        fn scroll_left(&mut self);
+        /// switch between 64x32 and SUPER-CHIP 128x64; clears the screen.
+        fn set_hires(&mut self, on: bool);
+        /// start/stop the tone the sound timer drives.
+        fn set_buzzer(&mut self, on: bool);
+        fn hires(&self) -> bool;
+        /// on/off state of every display pixel at the active resolution
+        /// (64x32, or 128x64 in hi-res), row-major and unscaled.
+        fn logical_pixels(&self) -> Vec<bool>;
         fn is_key_down(&self, key: u8) -> bool;
         fn last_key(&self) -> Option<u8>;
         fn drain_text_input(&mut self) -> Vec<u8>;
@@ -19,5 +33,17 @@ pub mod device {
         fn console_backspace(&mut self);
         fn set_mode(&mut self, mode: DisplayMode);
         fn mode(&self) -> DisplayMode;
+        /// set foreground/background colors as 0xRRGGBB.
+        fn set_palette(&mut self, fg: u32, bg: u32);
+        /// show everything drawn since the last call. Drawing only updates
+        /// the frame buffer; the scheduler presents once per 60Hz frame.
+        fn present(&mut self);
+    }
+
+    /// A peripheral mapped into a proc's virtual address space with
+    /// `Proc::map_mmio`. Offsets are relative to the start of the mapping.
+    pub trait MmioDevice {
+        fn read(&mut self, offset: u32) -> u8;
+        fn write(&mut self, offset: u32, value: u8);
     }
 }
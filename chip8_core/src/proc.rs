@@ -2,10 +2,11 @@ pub mod proc {
     use std::collections::VecDeque;
     use std::io::Error;
     use std::mem::size_of;
+    use std::ops::Range;
     use std::sync::{Arc, Mutex};
 
     use crate::chip8_engine::chip8_engine::*;
-    use crate::device::device::DisplayDevice;
+    use crate::device::device::{DisplayDevice, MmioDevice};
     use crate::syscall::syscall::SyscallOutcome;
     use crate::shared_memory;
     use crate::shared_memory::shared_memory::SharedMemory;
@@ -36,8 +37,32 @@ pub mod proc {
         0xF0, 0x80, 0xF0, 0x80, 0x80  // F
     ];
 
+    /// SUPER-CHIP big font (Fx30) base address, right after the small font.
+    pub const BIG_FONT_ADDR: u16 = 0x50;
+
+    // SUPER-CHIP big font sprites are 8x10 pixels, 10 bytes per glyph (0-F).
+    const CHIP8_BIG_SPRITES: [u8; 160] = [
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+    ];
+
 
     #[allow(non_snake_case)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct Registers {
         pub V: [u8; 16],
         pub DT: u8,
@@ -72,6 +97,82 @@ pub mod proc {
     pub enum ConsoleMode {
         Host,
         Display,
+        /// console I/O goes through kernel pipes (see SYS_SPAWN_PIPE).
+        Pipe,
+    }
+
+    // page_prot bit: guest writes into the page fault the proc.
+    pub const PROT_READ_ONLY: u8 = 0x01;
+
+    /// why a proc was stopped; PC is left on the faulting instruction.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Fault {
+        /// a guest store hit a page marked PROT_READ_ONLY.
+        WriteProtected { addr: u32 },
+        /// fetch from an odd PC while `require_aligned_pc` is set.
+        MisalignedPc { pc: u16 },
+        /// an opcode no handler defines, while `trap_unknown` is set.
+        IllegalOpcode { pc: u16, opcode: u16 },
+        /// a call would push the stack below `Proc::stack_floor` (or past 0).
+        StackOverflow { sp: u16 },
+        /// a return with the stack already at `Proc::stack_top`.
+        StackUnderflow { sp: u16 },
+    }
+
+    /// interpreter behaviors that CHIP-8 variants disagree on. The default
+    /// is the classic reading this engine has always used.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct Quirks {
+        /// 8XY6/8XYE shift VY into VX (COSMAC VIP) instead of shifting VX.
+        pub shift_uses_vy: bool,
+        /// FX55/FX65 leave I at I + X + 1; off, I is left unchanged (SCHIP).
+        pub load_store_increments_i: bool,
+        /// BNNN jumps to NNN + VX (X = high nibble of NNN) instead of NNN + V0.
+        pub jump_uses_vx: bool,
+    }
+
+    impl Default for Quirks {
+        fn default() -> Quirks {
+            Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+            }
+        }
+    }
+
+    // bitwise CRC-32 step (reflected IEEE polynomial); no table, since
+    // checksums are taken rarely and memory is at most a few pages.
+    fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+        crc
+    }
+
+    /// state needed to rewind one step: the register file before it ran and
+    /// the previous value of every byte it wrote, in write order.
+    struct UndoRecord {
+        regs: Registers,
+        writes: Vec<(u32, u8)>,
+    }
+
+    /// bounded ring of undo records; `open` collects writes for the step
+    /// currently executing.
+    struct UndoLog {
+        depth: usize,
+        records: VecDeque<UndoRecord>,
+        open: Option<UndoRecord>,
+    }
+
+    /// a virtual address range served by a device instead of memory.
+    struct MmioRegion {
+        range: Range<u32>,
+        device: Box<dyn MmioDevice>,
     }
 
     pub struct Proc<D: DisplayDevice> {
@@ -79,10 +180,34 @@ pub mod proc {
         pub mem: Arc<Mutex<SharedMemory>>,
         pub display: D,
         pub page_table: Vec<u32>,
+        pub page_prot: Vec<u8>,
         pub vm_size: u32,
         pub input_mode: InputMode,
         pub console_mode: ConsoleMode,
         pub console_input: VecDeque<u8>,
+        pub fault: Option<Fault>,
+        /// fault on fetch from an odd PC instead of decoding across opcodes.
+        pub require_aligned_pc: bool,
+        /// fault on an opcode no handler defines instead of skipping it.
+        pub trap_unknown: bool,
+        /// lowest address the stack may grow into; a call that would push
+        /// below it faults. 0 (the default) only catches wrapping past 0.
+        pub stack_floor: u16,
+        /// SP of an empty stack: where it started, at load, exec or thread
+        /// creation. A return with SP at or above it faults.
+        pub stack_top: u16,
+        /// bytes guest stores may not touch, independent of page bits: the
+        /// program image once `protect_program` has run.
+        pub protected_code: Option<Range<u32>>,
+        /// variant-specific opcode behaviors; see `Quirks`.
+        pub quirks: Quirks,
+        /// SUPER-CHIP RPL flags, saved/restored by FX75/FX85. They last for
+        /// the life of the proc; spawned procs and threads start zeroed.
+        pub flags: [u8; 8],
+        /// FX0A phase two: the key seen going down, awaiting its release.
+        pub(crate) key_wait: Option<u8>,
+        undo: Option<UndoLog>,
+        mmio: Vec<MmioRegion>,
     }
 
     impl<D: DisplayDevice> Proc<D> {
@@ -92,6 +217,12 @@ pub mod proc {
             display: D,
             pages: u16,
         ) -> Result<Proc<D>, Error> {
+            if pages == 0 {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "a process needs at least one page",
+                ));
+            }
             let page_table = mem
                 .lock()
                 .unwrap()
@@ -99,18 +230,127 @@ pub mod proc {
             let vm_size = pages as u32 * shared_memory::shared_memory::PAGE_SIZE as u32;
             let mut regs = Registers::default();
             regs.SP = vm_size.min(u16::MAX as u32) as u16;
+            let stack_top = regs.SP;
             Ok(Proc {
                 regs: regs,
                 mem: mem,
                 display: display,
+                page_prot: vec![0; pages as usize],
                 page_table: page_table,
                 vm_size: vm_size,
                 input_mode: InputMode::Line,
                 console_mode: ConsoleMode::Host,
                 console_input: VecDeque::new(),
+                fault: None,
+                require_aligned_pc: false,
+                trap_unknown: false,
+                stack_floor: 0,
+                stack_top,
+                protected_code: None,
+                quirks: Quirks::default(),
+                flags: [0; 8],
+                key_wait: None,
+                undo: None,
+                mmio: Vec::new(),
             })
         }
 
+        /// build a thread of `self`: a proc whose page table aliases the
+        /// same physical pages, with fresh registers starting at `entry`
+        /// and its stack at `sp`. No pages are allocated; the caller must
+        /// keep the pages mapped for as long as either proc runs. The page
+        /// protection bits and protected code range are copied, not shared.
+        pub fn new_thread<T: DisplayDevice>(&self, display: T, entry: u16, sp: u16) -> Proc<T> {
+            let regs = Registers {
+                PC: entry,
+                SP: sp,
+                ..Registers::default()
+            };
+            Proc {
+                regs,
+                mem: Arc::clone(&self.mem),
+                display,
+                page_table: self.page_table.clone(),
+                page_prot: self.page_prot.clone(),
+                vm_size: self.vm_size,
+                input_mode: InputMode::Line,
+                console_mode: ConsoleMode::Host,
+                console_input: VecDeque::new(),
+                fault: None,
+                require_aligned_pc: self.require_aligned_pc,
+                trap_unknown: self.trap_unknown,
+                stack_floor: 0,
+                stack_top: sp,
+                protected_code: self.protected_code.clone(),
+                quirks: self.quirks,
+                flags: [0; 8],
+                key_wait: None,
+                undo: None,
+                mmio: Vec::new(),
+            }
+        }
+
+        /// build a copy of `self` in freshly allocated pages: same page
+        /// count, contents, protections, registers and RPL flags. The copy
+        /// gets `display` and a new console, and no mmio mappings or undo
+        /// history, since devices and past steps belong to the original.
+        pub fn fork<T: DisplayDevice>(&self, display: T) -> Result<Proc<T>, Error> {
+            let page_table = {
+                let mut mem = self.mem.lock().unwrap();
+                let pages = mem.mmap(self.page_table.len() as u16)?;
+                for (&src, &dst) in self.page_table.iter().zip(&pages) {
+                    let src = src as usize;
+                    mem.phys_mem
+                        .copy_within(src..src + shared_memory::shared_memory::PAGE_SIZE, dst as usize);
+                }
+                pages
+            };
+            Ok(Proc {
+                regs: self.regs,
+                mem: Arc::clone(&self.mem),
+                display,
+                page_table,
+                page_prot: self.page_prot.clone(),
+                vm_size: self.vm_size,
+                input_mode: InputMode::Line,
+                console_mode: ConsoleMode::Host,
+                console_input: VecDeque::new(),
+                fault: None,
+                require_aligned_pc: self.require_aligned_pc,
+                trap_unknown: self.trap_unknown,
+                stack_floor: self.stack_floor,
+                stack_top: self.stack_top,
+                protected_code: self.protected_code.clone(),
+                quirks: self.quirks,
+                flags: self.flags,
+                key_wait: None,
+                undo: None,
+                mmio: Vec::new(),
+            })
+        }
+
+        /// map `pages` more pages onto the end of the address space and
+        /// return the new `vm_size`. The stack stays where it is. Growing
+        /// past the 64K a 16-bit address can reach is refused.
+        pub fn grow(&mut self, pages: u16) -> Result<u32, Error> {
+            let page_size = shared_memory::shared_memory::PAGE_SIZE as u32;
+            let new_size = self.vm_size + pages as u32 * page_size;
+            if new_size > u16::MAX as u32 + 1 {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "address space would exceed 64K",
+                ));
+            }
+            if pages == 0 {
+                return Ok(self.vm_size);
+            }
+            let added = self.mem.lock().unwrap().mmap(pages)?;
+            self.page_table.extend(added);
+            self.page_prot.resize(self.page_table.len(), 0);
+            self.vm_size = new_size;
+            Ok(new_size)
+        }
+
         // translate a virtual address into a physical address.
         pub fn translate(&self, vaddr: u32) -> Result<usize, Error> {
             if vaddr >= self.vm_size {
@@ -126,8 +366,40 @@ pub mod proc {
             Ok(phys_base + offset)
         }
 
+        /// route reads/writes of `range` to `device`. The range must be
+        /// non-empty, inside the proc, and not overlap an existing mapping.
+        /// Mapped bytes bypass physical memory, page protection and undo.
+        pub fn map_mmio<M>(&mut self, range: Range<u32>, device: M) -> Result<(), Error>
+        where
+            M: MmioDevice + 'static,
+        {
+            if range.is_empty() || range.end > self.vm_size {
+                return Err(Error::new(std::io::ErrorKind::InvalidInput, "mmio range outside the proc"));
+            }
+            if self
+                .mmio
+                .iter()
+                .any(|region| region.range.start < range.end && range.start < region.range.end)
+            {
+                return Err(Error::new(std::io::ErrorKind::AlreadyExists, "mmio range overlaps a mapping"));
+            }
+            self.mmio.push(MmioRegion {
+                range,
+                device: Box::new(device),
+            });
+            Ok(())
+        }
+
+        fn mmio_region(&mut self, vaddr: u32) -> Option<&mut MmioRegion> {
+            self.mmio.iter_mut().find(|region| region.range.contains(&vaddr))
+        }
+
         // read a single byte using virtual addressing.
         pub fn read_u8(&mut self, vaddr: u32) -> Result<u8, Error> {
+            if let Some(region) = self.mmio_region(vaddr) {
+                let offset = vaddr - region.range.start;
+                return Ok(region.device.read(offset));
+            }
             let phys = self.translate(vaddr)?;
             Ok(self.mem
                 .lock()
@@ -138,7 +410,21 @@ pub mod proc {
 
         // write a single byte using virtual addressing.
         pub fn write_u8(&mut self, vaddr: u32, value: u8) -> Result<(), Error> {
+            if let Some(region) = self.mmio_region(vaddr) {
+                let offset = vaddr - region.range.start;
+                region.device.write(offset, value);
+                return Ok(());
+            }
             let phys = self.translate(vaddr)?;
+            if self.is_read_only(vaddr) {
+                return Err(Error::new(std::io::ErrorKind::PermissionDenied, "write to read-only memory"));
+            }
+            if self.undo.as_ref().is_some_and(|log| log.open.is_some()) {
+                let old = self.mem.lock().unwrap().read(phys, size_of::<u8>())?[0];
+                if let Some(record) = self.undo.as_mut().and_then(|log| log.open.as_mut()) {
+                    record.writes.push((vaddr, old));
+                }
+            }
             let data = vec![value];
             self.mem
                 .lock()
@@ -169,6 +455,45 @@ pub mod proc {
             Ok(data)
         }
 
+        /// true if `vaddr` lies in a page marked PROT_READ_ONLY or in the
+        /// protected program image.
+        pub fn is_read_only(&self, vaddr: u32) -> bool {
+            if self.mmio.iter().any(|region| region.range.contains(&vaddr)) {
+                return false;
+            }
+            if self.protected_code.as_ref().is_some_and(|code| code.contains(&vaddr)) {
+                return true;
+            }
+            let page = (vaddr as usize) / shared_memory::shared_memory::PAGE_SIZE;
+            self.page_prot
+                .get(page)
+                .is_some_and(|prot| prot & PROT_READ_ONLY != 0)
+        }
+
+        /// set the protection bits for the page starting at `page_base`.
+        pub fn protect_page(&mut self, page_base: u32, prot: u8) -> Result<(), Error> {
+            let page_size = shared_memory::shared_memory::PAGE_SIZE as u32;
+            if !page_base.is_multiple_of(page_size) || page_base >= self.vm_size {
+                return Err(Error::new(std::io::ErrorKind::InvalidInput, "not a page base in this proc"));
+            }
+            self.page_prot[(page_base / page_size) as usize] = prot;
+            Ok(())
+        }
+
+        /// make the `len` bytes of a program loaded at 0x200 read-only,
+        /// byte for byte: the font area, the stack and data past the program
+        /// end stay writable even when they share a page with it.
+        pub fn protect_program(&mut self, len: usize) {
+            let end = (0x200 + len as u32).min(self.vm_size);
+            self.protected_code = Some(0x200..end);
+        }
+
+        /// record a fault; the kernel stops the proc when it sees Faulted.
+        pub fn raise_fault(&mut self, fault: Fault) -> SyscallOutcome {
+            self.fault = Some(fault);
+            SyscallOutcome::Faulted
+        }
+
         // read a 16-bit big-endian value using virtual addressing.
         pub fn read_u16(&mut self, vaddr: u32) -> Result<u16, Error> {
             let hi = self.read_u8(vaddr)? as u16;
@@ -190,6 +515,7 @@ pub mod proc {
             //self.mem[0x0..0x50].copy_from_slice(&chip8_sprites);
             let sprite_vec = CHIP8_SPRITES.to_vec();
             self.write_bytes(0x0, &sprite_vec)?;
+            self.write_bytes(BIG_FONT_ADDR as u32, &CHIP8_BIG_SPRITES)?;
 
             //copy program text into process memory
             //self.mem.lock().unwrap()[0x200..(0x200 + program_text.len())].copy_from_slice(&program_text);
@@ -198,6 +524,26 @@ pub mod proc {
             Ok(())
         }
 
+        /// replace the running program in place: reset the registers as for
+        /// a new proc, drop page and code protections and pending key waits,
+        /// then load `program` at 0x200. The page table is kept. Undo history
+        /// is discarded, since it describes the old image.
+        pub fn exec(&mut self, program: &[u8]) -> Result<(), Error> {
+            self.regs = Registers {
+                SP: self.vm_size.min(u16::MAX as u32) as u16,
+                ..Registers::default()
+            };
+            self.stack_top = self.regs.SP;
+            self.page_prot.fill(0);
+            self.protected_code = None;
+            self.fault = None;
+            self.key_wait = None;
+            if let Some(log) = self.undo.as_mut() {
+                log.records.clear();
+            }
+            self.load_program_bytes(program)
+        }
+
         /// This method is responsible for running the loaded ch8 program.
         /// It starts a loop that initially sets the program counter, grabs
         /// values relevant to the offset of the PC from the program's memory 
@@ -210,7 +556,81 @@ pub mod proc {
         /// conditions are expected to be integrated externally.
         /// `ticks` is the number of 60Hz timer ticks supplied by the kernel.
         // execute a single CHIP-8 instruction for test-driven stepping.
-        pub fn step<F>(&mut self, ticks: u32, mut dispatch_syscall: F) -> SyscallOutcome
+        pub fn step<F>(&mut self, ticks: u32, dispatch_syscall: F) -> SyscallOutcome
+        where
+            F: FnMut(u16, &mut Proc<D>) -> Result<SyscallOutcome, Error>,
+        {
+            let regs = self.regs;
+            if let Some(log) = self.undo.as_mut() {
+                log.open = Some(UndoRecord { regs, writes: Vec::new() });
+            }
+
+            let outcome = self.execute(ticks, dispatch_syscall);
+
+            if let Some(log) = self.undo.as_mut()
+                && let Some(record) = log.open.take()
+            {
+                if log.records.len() == log.depth {
+                    log.records.pop_front();
+                }
+                log.records.push_back(record);
+            }
+            outcome
+        }
+
+        /// keep undo records for the last `depth` steps (0 turns undo off).
+        /// Enabling again discards existing records.
+        pub fn enable_undo(&mut self, depth: usize) {
+            self.undo = (depth > 0).then(|| UndoLog {
+                depth,
+                records: VecDeque::with_capacity(depth),
+                open: None,
+            });
+        }
+
+        /// rewind the most recent recorded step: guest memory bytes it wrote
+        /// and the register file. Display output and kernel-side syscall
+        /// effects are not rewound. Returns false when there is nothing to undo.
+        pub fn step_back(&mut self) -> bool {
+            let Some(record) = self.undo.as_mut().and_then(|log| log.records.pop_back()) else {
+                return false;
+            };
+            for &(vaddr, old) in record.writes.iter().rev() {
+                // bypass write_u8: the byte was writable when the step ran,
+                // and the restore itself must not be recorded.
+                if let Ok(phys) = self.translate(vaddr) {
+                    let _ = self.mem.lock().unwrap().write(phys, &vec![old], 1);
+                }
+            }
+            self.regs = record.regs;
+            self.fault = None;
+            true
+        }
+
+        /// CRC-32 (IEEE) over every byte of guest memory followed by the
+        /// register file, for checking a saved or copied VM against the
+        /// original. Memory is read straight from the page table, so mmio
+        /// devices are not touched.
+        pub fn checksum(&self) -> u32 {
+            let mut crc = !0u32;
+            {
+                let mem = self.mem.lock().unwrap();
+                let page_size = shared_memory::shared_memory::PAGE_SIZE;
+                for &base in &self.page_table {
+                    let base = base as usize;
+                    crc = crc32_update(crc, &mem.phys_mem[base..base + page_size]);
+                }
+            }
+            let regs = &self.regs;
+            crc = crc32_update(crc, &regs.V);
+            crc = crc32_update(crc, &[regs.DT, regs.ST]);
+            for reg in [regs.I, regs.SP, regs.PC] {
+                crc = crc32_update(crc, &reg.to_be_bytes());
+            }
+            !crc
+        }
+
+        fn execute<F>(&mut self, ticks: u32, mut dispatch_syscall: F) -> SyscallOutcome
         where
             F: FnMut(u16, &mut Proc<D>) -> Result<SyscallOutcome, Error>,
         {
@@ -220,6 +640,9 @@ pub mod proc {
             self.tick_timers(ticks);
 
             let pc = self.regs.PC as usize;
+            if self.require_aligned_pc && !pc.is_multiple_of(2) {
+                return self.raise_fault(Fault::MisalignedPc { pc: self.regs.PC });
+            }
             
             // opcodes are big-endian in memory (hi byte then lo byte).
             let val1 = self.read_u8(pc as u32).unwrap() as u16;
@@ -228,6 +651,10 @@ pub mod proc {
             
             let instruction = val1 | val2;
             let opcode = extract_opcode!(instruction);
+            if self.trap_unknown && !is_known_opcode(instruction) {
+                let fault = Fault::IllegalOpcode { pc: self.regs.PC, opcode: instruction };
+                return self.raise_fault(fault);
+            }
 
             match opcode {
                 0x0 => {
@@ -237,7 +664,7 @@ pub mod proc {
                     opcode_0x1(self, instruction);
                 },
                 0x2 => {
-                    opcode_0x2(self, instruction);
+                    return opcode_0x2(self, instruction);
                 },
                 0x3 => {
                     opcode_0x3(self, instruction);
@@ -293,8 +720,8 @@ pub mod proc {
             self.display.last_key()
         }
 
-        /// decrement DT/ST by the number of 60Hz ticks supplied by the kernel.
-        fn tick_timers(&mut self, ticks: u32) {
+        /// count DT/ST down by `ticks` 60Hz ticks, saturating at zero.
+        pub fn tick_timers(&mut self, ticks: u32) {
             let dec = ticks.min(u8::MAX as u32) as u8;
             self.regs.DT = self.regs.DT.saturating_sub(dec);
             self.regs.ST = self.regs.ST.saturating_sub(dec);
@@ -2,16 +2,17 @@ pub mod shared_memory {
     use std::io::{Error, ErrorKind};
 
     pub const PAGE_SIZE: usize = 0x1000;
-    const PHYS_MEM_SIZE: usize = 0x100000;
-
-    const PHYS_PAGE_COUNT: usize = PHYS_MEM_SIZE / PAGE_SIZE;
+    // arena size `new` uses: 1MB, 256 pages.
+    const DEFAULT_PAGE_COUNT: usize = 256;
 
     /// Our initial strategy for memory allotment uses a page allocator over a
     /// shared physical memory arena. Each process can request multiple pages,
     /// which may map to non-contiguous physical locations.
     /// 
-    /// phys_bitmap tracks availability per physical page; it
-    /// does not yet support freeing.
+    /// phys_bitmap tracks availability per physical page; munmap clears
+    /// bits again so pages can be reused. Because page tables never need
+    /// physical contiguity, fragmentation cannot make mmap fail;
+    /// largest_free_run and fragmentation_ratio exist for monitoring.
 
     pub struct SharedMemory {
         pub phys_mem: Vec<u8>,
@@ -20,10 +21,19 @@ pub mod shared_memory {
 
     impl SharedMemory {
         pub fn new() -> Result<SharedMemory, std::io::Error> {
+            SharedMemory::with_capacity(DEFAULT_PAGE_COUNT)
+        }
+
+        /// build an arena of `pages` pages instead of the default 256; a
+        /// small one makes exhaustion easy to reach in tests.
+        pub fn with_capacity(pages: usize) -> Result<SharedMemory, std::io::Error> {
+            if pages == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, "arena needs at least one page"));
+            }
             Ok(
                 SharedMemory {
-                    phys_mem: vec![0; PHYS_MEM_SIZE],
-                    phys_bitmap: vec![false; PHYS_PAGE_COUNT],
+                    phys_mem: vec![0; pages * PAGE_SIZE],
+                    phys_bitmap: vec![false; pages],
                 }
             )
         }
@@ -31,7 +41,9 @@ pub mod shared_memory {
         /// mmap returns a list of physical page bases for a process page table.
         /// The returned pages form a contiguous virtual range, but may map to
         /// non-contiguous physical locations.
-        /// this allocator is first-fit and does not yet support freeing.
+        /// this allocator is first-fit; release pages with munmap.
+        /// Pages come back zeroed: munmap clears them too, but phys_mem is
+        /// public, so a free page is not guaranteed to still be clean.
         pub fn mmap(&mut self, pages: u16) -> Result<Vec<u32>, Error> {
             if pages == 0 {
                 return Err(Error::new(ErrorKind::InvalidInput, "page count must be > 0"));
@@ -54,13 +66,133 @@ pub mod shared_memory {
 
             let mut allocated: Vec<u32> = Vec::with_capacity(pages as usize);
             for idx in free_indices {
+                let base = idx * PAGE_SIZE;
                 self.phys_bitmap[idx] = true;
-                allocated.push((idx * PAGE_SIZE) as u32);
+                self.phys_mem[base..base + PAGE_SIZE].fill(0);
+                allocated.push(base as u32);
+            }
+
+            Ok(allocated)
+        }
+
+        /// like mmap, but the pages are physically adjacent, for callers that
+        /// need one contiguous block. Picks the smallest free run that fits
+        /// (best-fit) so large runs are kept for large requests, and fails
+        /// with OutOfMemory when no run is long enough, even if enough pages
+        /// are free in total. Pages come back zeroed and in address order.
+        pub fn mmap_contiguous(&mut self, pages: u16) -> Result<Vec<u32>, Error> {
+            if pages == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, "page count must be > 0"));
+            }
+            let want = pages as usize;
+
+            // (start, len) of the best run seen so far.
+            let mut best: Option<(usize, usize)> = None;
+            let mut idx = 0;
+            while idx < self.phys_bitmap.len() {
+                if self.phys_bitmap[idx] {
+                    idx += 1;
+                    continue;
+                }
+                let start = idx;
+                while idx < self.phys_bitmap.len() && !self.phys_bitmap[idx] {
+                    idx += 1;
+                }
+                let len = idx - start;
+                if len >= want && best.is_none_or(|(_, best_len)| len < best_len) {
+                    best = Some((start, len));
+                }
             }
 
+            let Some((start, _)) = best else {
+                return Err(Error::new(ErrorKind::OutOfMemory, "no contiguous run of free pages"));
+            };
+            let mut allocated: Vec<u32> = Vec::with_capacity(want);
+            for idx in start..start + want {
+                let base = idx * PAGE_SIZE;
+                self.phys_bitmap[idx] = true;
+                self.phys_mem[base..base + PAGE_SIZE].fill(0);
+                allocated.push(base as u32);
+            }
             Ok(allocated)
         }
 
+        /// munmap returns pages from a page table to the free pool and zeroes
+        /// them so the next owner never sees stale bytes. Every base is
+        /// checked before any page is freed, so a bad list frees nothing.
+        pub fn munmap(&mut self, pages: &[u32]) -> Result<(), Error> {
+            let mut free: Vec<String> = Vec::new();
+            for &base in pages {
+                let base = base as usize;
+                if !base.is_multiple_of(PAGE_SIZE) || base >= self.phys_mem.len() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("not a physical page base: {base:#x}"),
+                    ));
+                }
+                if !self.phys_bitmap[base / PAGE_SIZE] {
+                    free.push(format!("{base:#x}"));
+                }
+            }
+            // a free page here means a double free somewhere in the kernel;
+            // name every one so the report points at the bad table.
+            if !free.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("pages not allocated: {}", free.join(", ")),
+                ));
+            }
+
+            for &base in pages {
+                let base = base as usize;
+                self.phys_bitmap[base / PAGE_SIZE] = false;
+                self.phys_mem[base..base + PAGE_SIZE].fill(0);
+            }
+            Ok(())
+        }
+
+        /// number of physical pages currently handed out by mmap.
+        pub fn used_page_count(&self) -> usize {
+            self.phys_bitmap.iter().filter(|used| **used).count()
+        }
+
+        /// number of physical pages mmap could still hand out.
+        pub fn free_page_count(&self) -> usize {
+            self.total_pages() - self.used_page_count()
+        }
+
+        /// size of the arena in pages, used or not.
+        pub fn total_pages(&self) -> usize {
+            self.phys_bitmap.len()
+        }
+
+        /// length, in pages, of the longest run of physically adjacent free
+        /// pages. Diagnostic only: mmap never needs contiguous pages, so a
+        /// request succeeds whenever enough pages are free in total.
+        pub fn largest_free_run(&self) -> usize {
+            let mut longest = 0;
+            let mut run = 0;
+            for used in &self.phys_bitmap {
+                if *used {
+                    run = 0;
+                } else {
+                    run += 1;
+                    longest = longest.max(run);
+                }
+            }
+            longest
+        }
+
+        /// share of free pages lying outside the largest free run:
+        /// 0.0 when all free memory is one run (or none is free), approaching
+        /// 1.0 as free pages scatter into single-page holes.
+        pub fn fragmentation_ratio(&self) -> f64 {
+            let free = self.free_page_count();
+            if free == 0 {
+                return 0.0;
+            }
+            1.0 - self.largest_free_run() as f64 / free as f64
+        }
 
         /// write will be our primary function for writing data into memory
         /// it will take a mutable reference to the SharedMemory object of 
@@ -5,5 +5,7 @@ pub mod syscall {
         Completed,
         Blocked,
         Yielded,
+        /// the proc hit a fault (see `Proc::fault`) and must not run again.
+        Faulted,
     }
 }
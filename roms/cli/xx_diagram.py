@@ -18,7 +18,7 @@ import tempfile
 from pathlib import Path
 
 BASE_ADDR = 0x200
-CODE_END = 0x596
+CODE_END = 0x5de
 PAD_END = 0x800
 
 SYS_CALLS = {
@@ -206,8 +206,8 @@ def header_block() -> list[str]:
         *title_block(
             "Memory Map (ROM image)",
             [
-                "0x0200..0x0594  code (boot, repl, commands, helpers, syscalls)",
-                "0x0596..0x07FF  padding (00)",
+                "0x0200..0x05DC  code (boot, repl, commands, helpers, syscalls)",
+                "0x05DE..0x07FF  padding (00)",
                 "0x0800..0x0BE1  data (buffers + strings)",
             ],
         ),
@@ -250,34 +250,34 @@ def generate_xx(rom: bytes) -> list[str]:
         ),
         0x0388: title_block(
             "cmd_ls",
-            ["Lists directory entries using sys_fs_list."],
+            ["Lists directory entries using sys_fs_list, one page at a time."],
         ),
-        0x03d8: title_block(
+        0x03ec: title_block(
             "cmd_run",
             ["Spawns ROM via sys_spawn + waits."],
         ),
-        0x03fc: title_block(
+        0x040c: title_block(
             "cmd_cat",
             ["Open \u2192 read \u2192 write \u2192 close loop."],
         ),
-        0x043c: title_block("Error + Usage Paths"),
-        0x0450: title_block(
+        0x0450: title_block("Error + Usage Paths"),
+        0x0464: title_block(
             "Print Helpers",
             ["Each helper loads a string slice and calls sys_write."],
         ),
-        0x04bc: title_block(
+        0x04d0: title_block(
             "read_line",
             ["Reads from stdin into LINE_BUF using sys_read."],
         ),
-        0x04c8: title_block(
+        0x04dc: title_block(
             "Syscall Frame Builders",
-            ["frame1..frame4 write argc+args into FRAME buffer."],
+            ["frame1..frame5 write argc+args into FRAME buffer."],
         ),
-        0x0538: title_block(
+        0x057c: title_block(
             "Syscall Wrappers",
             ["sys_* wrappers issue 0x01xx SYS opcodes."],
         ),
-        0x0596: title_block(
+        0x05de: title_block(
             "Padding (00)",
             ["Zero fill up to data region at 0x0800."],
         ),
@@ -307,7 +307,7 @@ def generate_xx(rom: bytes) -> list[str]:
     buffer_blocks = {
         0x0800: ("LINE_BUF", 0x50),
         0x0850: ("FRAME", 0x10),
-        0x0900: ("DIR_BUF", 0x118),
+        0x0900: ("DIR_BUF", 0xEA),
         0x0A20: ("FILE_BUF", 0x40),
     }
 
@@ -0,0 +1,1922 @@
+pub mod asm {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// one `ADDR: mnemonic` line per opcode, loading at 0x200. Opcodes the
+    /// assembler has no single statement for become `word` with a comment.
+    pub fn disassemble(rom: &[u8]) -> String {
+        let mut out = String::new();
+        for (idx, chunk) in rom.chunks(2).enumerate() {
+            let addr = 0x200 + idx * 2;
+            let text = match chunk {
+                [hi, lo] => decode(u16::from_be_bytes([*hi, *lo])),
+                [byte] => format!("byte 0x{byte:02X}"),
+                _ => unreachable!(),
+            };
+            out.push_str(&format!("{addr:04X}: {text}\n"));
+        }
+        out
+    }
+
+    fn decode(op: u16) -> String {
+        let x = (op >> 8) & 0xF;
+        let y = (op >> 4) & 0xF;
+        let n = op & 0xF;
+        let kk = op & 0xFF;
+        let nnn = op & 0xFFF;
+        let raw = |note: String| format!("word 0x{op:04X}    ; {note}");
+        match op >> 12 {
+            0x0 if op == 0x00E0 => "clear".into(),
+            0x0 if op == 0x00EE => "return".into(),
+            0x0 => format!("sys 0x{nnn:03X}"),
+            0x1 => format!("jump 0x{nnn:03X}"),
+            0x2 => format!("call 0x{nnn:03X}"),
+            0x3 => raw(format!("skip if v{x:X} == 0x{kk:02X}")),
+            0x4 => raw(format!("skip if v{x:X} != 0x{kk:02X}")),
+            0x5 if n == 0 => raw(format!("skip if v{x:X} == v{y:X}")),
+            0x6 => format!("v{x:X} := 0x{kk:02X}"),
+            0x7 => format!("v{x:X} += 0x{kk:02X}"),
+            0x8 => match n {
+                0x0 => format!("v{x:X} := v{y:X}"),
+                0x1 => format!("v{x:X} |= v{y:X}"),
+                0x2 => format!("v{x:X} &= v{y:X}"),
+                0x3 => format!("v{x:X} ^= v{y:X}"),
+                0x4 => format!("v{x:X} += v{y:X}"),
+                0x5 => format!("v{x:X} -= v{y:X}"),
+                0x6 => format!("v{x:X} >>= v{y:X}"),
+                0x7 => format!("v{x:X} =- v{y:X}"),
+                0xE => format!("v{x:X} <<= v{y:X}"),
+                _ => raw("unknown".into()),
+            },
+            0x9 if n == 0 => raw(format!("skip if v{x:X} != v{y:X}")),
+            0xA => format!("i := 0x{nnn:03X}"),
+            0xB => raw(format!("jump v0 + 0x{nnn:03X}")),
+            0xC => format!("v{x:X} := random 0x{kk:02X}"),
+            0xD => format!("draw v{x:X}, v{y:X}, {n}"),
+            0xE if kk == 0x9E => raw(format!("skip if key v{x:X} is down")),
+            0xE if kk == 0xA1 => raw(format!("skip if key v{x:X} is up")),
+            0xF => match kk {
+                0x07 => format!("v{x:X} := delay"),
+                0x0A => raw(format!("v{x:X} := next key")),
+                0x15 => format!("delay := v{x:X}"),
+                0x18 => format!("buzzer := v{x:X}"),
+                0x1E => format!("i += v{x:X}"),
+                0x29 => format!("i := font v{x:X}"),
+                0x33 => format!("bcd v{x:X}"),
+                0x55 => format!("save v{x:X}"),
+                0x65 => format!("load v{x:X}"),
+                _ => raw("unknown".into()),
+            },
+            _ => raw("unknown".into()),
+        }
+    }
+
+    /// Intel HEX: 16-byte data records from the 0x200 load address, then EOF.
+    pub fn intel_hex(rom: &[u8]) -> String {
+        let mut out = String::new();
+        for (idx, chunk) in rom.chunks(16).enumerate() {
+            let addr = (0x200 + idx * 16) as u16;
+            let mut record = vec![chunk.len() as u8, (addr >> 8) as u8, addr as u8, 0x00];
+            record.extend_from_slice(chunk);
+            let sum = record.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+            record.push(sum.wrapping_neg());
+            out.push(':');
+            for byte in record {
+                out.push_str(&format!("{byte:02X}"));
+            }
+            out.push('\n');
+        }
+        out.push_str(":00000001FF\n");
+        out
+    }
+
+    #[derive(Debug, Clone)]
+    enum Token {
+        Ident(String),
+        Number(u16),
+        Str(String),
+        Sym(&'static str),
+        // `name + N` / `name - N`, folded by `tokenize`.
+        Offset(String, i32),
+    }
+
+    // a statement parse failure, optionally blaming the token it stopped at
+    // so the error can name its column.
+    struct ParseError<'a> {
+        msg: String,
+        token: Option<&'a Token>,
+    }
+
+    impl<'a> ParseError<'a> {
+        fn at(token: &'a Token, msg: &str) -> Self {
+            Self { msg: msg.to_string(), token: Some(token) }
+        }
+    }
+
+    impl From<&str> for ParseError<'_> {
+        fn from(msg: &str) -> Self {
+            Self { msg: msg.to_string(), token: None }
+        }
+    }
+
+    impl From<String> for ParseError<'_> {
+        fn from(msg: String) -> Self {
+            Self { msg, token: None }
+        }
+    }
+
+    // a tokenized line, for turning a `ParseError` into `line N, col M: ...`.
+    struct Located<'a> {
+        tokens: &'a [Token],
+        cols: &'a [usize],
+        line_no: usize,
+    }
+
+    impl Located<'_> {
+        fn describe(&self, err: ParseError) -> String {
+            let line_no = self.line_no;
+            let col = err.token.and_then(|token| {
+                let idx = self.tokens.iter().position(|other| std::ptr::eq(other, token))?;
+                self.cols.get(idx)
+            });
+            match col {
+                Some(col) => format!("line {line_no}, col {col}: {}", err.msg),
+                None => format!("line {line_no}: {}", err.msg),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Expr {
+        Num(u16),
+        Label(String),
+        Offset(String, i32),
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct Reg(u8);
+
+    #[derive(Debug, Clone)]
+    enum Operand {
+        Imm(Expr),
+        Reg(Reg),
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum CmpOp {
+        Eq,
+        Ne,
+    }
+
+    #[derive(Debug, Clone)]
+    enum Instr {
+        Clear,
+        Ret,
+        Jump(Expr),
+        Call(Expr),
+        LoadI(Expr),
+        AddI(Reg),
+        Load(Reg, Operand),
+        Add(Reg, Operand),
+        Sub(Reg, Operand),
+        Or(Reg, Reg),
+        And(Reg, Reg),
+        Xor(Reg, Reg),
+        SubRev(Reg, Reg),
+        Shr(Reg, Reg),
+        Shl(Reg, Reg),
+        Rand(Reg, Expr),
+        Font(Reg),
+        Bcd(Reg),
+        GetDelay(Reg),
+        SetDelay(Reg),
+        SetSound(Reg),
+        Draw { x: Reg, y: Reg, n: Expr },
+        Save(Reg),
+        LoadMem(Reg),
+        If {
+            left: Reg,
+            op: CmpOp,
+            right: Operand,
+            target: Expr,
+        },
+        IfKey {
+            reg: Reg,
+            pressed: bool,
+            target: Expr,
+        },
+    }
+
+    #[derive(Debug, Clone)]
+    enum Stmt {
+        Byte(Vec<Expr>),
+        Word(Vec<Expr>),
+        Zero(u16),
+        Ascii(Vec<u8>),
+        IncBin(Vec<u8>),
+        Sys(Expr),
+        Instr(Instr),
+    }
+
+    #[derive(Debug, Clone)]
+    struct StmtLine {
+        addr: u16,
+        line_no: usize,
+        stmt: Stmt,
+    }
+
+    #[derive(Debug, Clone)]
+    struct SectionState {
+        name: String,
+        pc: u16,
+    }
+
+    #[derive(Debug, Clone)]
+    enum BlockKind {
+        Section(String),
+        Label,
+        // body starts at this source index; `left` more passes after this one.
+        Repeat { body: usize, left: u16 },
+    }
+
+    // one open `.if`: whether its enclosing branch is live, whether a branch
+    // of it has been taken, and whether lines are currently assembled.
+    #[derive(Debug, Clone)]
+    struct Cond {
+        outer: bool,
+        taken: bool,
+        live: bool,
+        seen_else: bool,
+    }
+
+    // one line of the include-expanded source and where it came from.
+    #[derive(Debug, Clone)]
+    struct SourceLine {
+        // None for the top-level file.
+        file: Option<String>,
+        // directory of the file the line came from; incbin resolves against it.
+        dir: PathBuf,
+        line: usize,
+        text: String,
+    }
+
+    impl SourceLine {
+        fn loc(&self) -> String {
+            match &self.file {
+                Some(file) => format!("{file} line {}", self.line),
+                None => format!("line {}", self.line),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Macro {
+        params: Vec<String>,
+        body: Vec<String>,
+    }
+
+    // nesting limit for macro calls inside macro bodies.
+    const MAX_MACRO_DEPTH: usize = 16;
+
+    pub struct Assembler {
+        labels: HashMap<String, u16>,
+        section_end: HashMap<String, u16>,
+        lines: Vec<StmtLine>,
+        // `const NAME = expr` values with the line that defined them.
+        consts: HashMap<String, (u16, usize)>,
+        /// promote warnings to errors (--strict).
+        pub strict: bool,
+        /// warnings from the last `assemble`, already located.
+        pub warnings: Vec<String>,
+        /// top-level file; includes and incbin resolve against its directory.
+        pub path: Option<PathBuf>,
+        // expanded source; `line_no` everywhere else indexes this (1-based).
+        source: Vec<SourceLine>,
+    }
+
+    impl Default for Assembler {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Assembler {
+        pub fn new() -> Self {
+            Self {
+                labels: HashMap::new(),
+                section_end: HashMap::new(),
+                lines: Vec::new(),
+                consts: HashMap::new(),
+                strict: false,
+                warnings: Vec::new(),
+                path: None,
+                source: Vec::new(),
+            }
+        }
+
+        /// assemble `source` into a ROM image loaded at 0x200. Errors and
+        /// warnings are `line N: ...` strings.
+        pub fn assemble(&mut self, source: &str) -> Result<Vec<u8>, String> {
+            let mut chain = Vec::new();
+            if let Some(path) = &self.path
+                && let Ok(key) = fs::canonicalize(path)
+            {
+                chain.push(key);
+            }
+            let mut expanded = Vec::new();
+            let mut scan = CondScan::default();
+            expand_includes(source, self.path.as_deref(), None, &mut chain, &mut scan, &mut expanded)?;
+            self.source = expand_macros(expanded)?;
+
+            let rom = self.passes().map_err(|err| self.locate(err))?;
+            self.warnings = self.check_branch_alignment().map_err(|err| self.locate(err))?;
+            self.warnings = self.warnings.iter().map(|warning| self.locate(warning.clone())).collect();
+            if self.strict
+                && let Some(first) = self.warnings.first()
+            {
+                return Err(format!("{first} (--strict)"));
+            }
+            Ok(rom)
+        }
+
+        /// opcodes are two bytes, so a branch to an odd address desyncs every
+        /// later fetch; data or odd-sized sections usually push a label there.
+        fn check_branch_alignment(&self) -> Result<Vec<String>, String> {
+            let mut warnings = Vec::new();
+            for line in &self.lines {
+                let (kind, target) = match &line.stmt {
+                    Stmt::Instr(Instr::Jump(expr)) => ("jump", expr),
+                    Stmt::Instr(Instr::Call(expr)) => ("call", expr),
+                    Stmt::Instr(Instr::If { target, .. }) => ("if ... jump", target),
+                    Stmt::Instr(Instr::IfKey { target, .. }) => ("if ... jump", target),
+                    _ => continue,
+                };
+                let addr = self.resolve_addr(target, line.line_no)?;
+                if !addr.is_multiple_of(2) {
+                    warnings.push(format!(
+                        "line {}: {kind} target {} is at odd address {addr:#06x}",
+                        line.line_no,
+                        expr_name(target),
+                    ));
+                }
+            }
+            Ok(warnings)
+        }
+
+        fn passes(&mut self) -> Result<Vec<u8>, String> {
+            self.first_pass()?;
+            self.second_pass()
+        }
+
+        /// rewrite a leading `line N:` or `line N, col M:` (an index into the
+        /// expanded source) to the real line, prefixed with the file name for
+        /// included lines.
+        fn locate(&self, msg: String) -> String {
+            let Some(rest) = msg.strip_prefix("line ") else {
+                return msg;
+            };
+            let Some((num, tail)) = rest.split_once(':') else {
+                return msg;
+            };
+            let (num, col) = match num.split_once(", col ") {
+                Some((num, col)) => (num, format!(", col {col}")),
+                None => (num, String::new()),
+            };
+            let idx = num.parse::<usize>().unwrap_or(0);
+            let Some(src) = idx.checked_sub(1).and_then(|idx| self.source.get(idx)) else {
+                return msg;
+            };
+            match &src.file {
+                Some(file) => format!("{file} line {}{col}:{tail}", src.line),
+                None => format!("line {}{col}:{tail}", src.line),
+            }
+        }
+
+        /// render `ADDR: BYTES    ; source` for every statement, in source
+        /// order, from the addresses and bytes the passes already produced.
+        pub fn listing(&self) -> Result<String, String> {
+            let mut out = String::new();
+            for line in &self.lines {
+                let bytes = self.emit_stmt(&line.stmt, line.line_no)?;
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+                let text = self.source.get(line.line_no - 1).map_or("", |src| src.text.trim());
+                out.push_str(&format!("{:04X}: {}    ; {text}\n", line.addr, hex.join(" ")));
+            }
+            Ok(out)
+        }
+
+        /// `name 0xNNNN` per label and `section name 0xNNNN` (end address) per
+        /// section, sorted by address.
+        pub fn symbol_map(&self) -> String {
+            let mut symbols: Vec<(u16, String)> = self
+                .labels
+                .iter()
+                .map(|(name, addr)| (*addr, name.clone()))
+                .chain(
+                    self.section_end
+                        .iter()
+                        .map(|(name, addr)| (*addr, format!("section {name}"))),
+                )
+                .collect();
+            symbols.sort();
+            symbols
+                .iter()
+                .map(|(addr, name)| format!("{name} {addr:#06x}\n"))
+                .collect()
+        }
+
+        fn first_pass(&mut self) -> Result<(), String> {
+            let mut current: Option<SectionState> = None;
+            let mut block_stack: Vec<BlockKind> = Vec::new();
+            let mut conds: Vec<Cond> = Vec::new();
+            let source: Vec<String> = self.source.iter().map(|src| src.text.clone()).collect();
+
+            let mut next = 0;
+            while let Some(raw_line) = source.get(next) {
+                let idx = next;
+                next += 1;
+                let line_no = idx + 1;
+                let line = strip_comments(raw_line)?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                if let Some(cond) = cond_directive(line) {
+                    self.step_cond(&mut conds, cond, line_no)?;
+                    continue;
+                }
+                if !conds.last().is_none_or(|cond| cond.live) {
+                    continue;
+                }
+
+                // columns count from the raw line, before trimming.
+                let indent = raw_line.chars().take_while(|ch| ch.is_whitespace()).count();
+                let (tokens, mut cols) = tokenize(line).map_err(|(col, err)| {
+                    format!("line {line_no}, col {}: {err}", col + indent)
+                })?;
+                if tokens.is_empty() {
+                    continue;
+                }
+                cols.iter_mut().for_each(|col| *col += indent);
+                let at = Located { tokens: &tokens, cols: &cols, line_no };
+
+                if tokens.len() == 1 && matches!(tokens[0], Token::Sym("}")) {
+                    match block_stack.pop() {
+                        Some(BlockKind::Label) => {}
+                        Some(BlockKind::Repeat { body, left }) => {
+                            if left > 0 {
+                                block_stack.push(BlockKind::Repeat { body, left: left - 1 });
+                                next = body;
+                            }
+                        }
+                        Some(BlockKind::Section(name)) => {
+                            let Some(state) = current.take() else {
+                                return Err(format!("line {line_no}: stray section close"));
+                            };
+                            if state.name != name {
+                                return Err(format!("line {line_no}: mismatched section close"));
+                            }
+                            self.section_end.insert(state.name, state.pc);
+                        }
+                        None => return Err(format!("line {line_no}: unmatched '}}'")),
+                    }
+                    continue;
+                }
+
+                if let Token::Ident(keyword) = &tokens[0] {
+                    if keyword == "section" {
+                        let (name, addr) = parse_section_header(&tokens)
+                            .map_err(|err| format!("line {line_no}: {err}"))?;
+                        let addr = match addr {
+                            Some(value) => value,
+                            None => self
+                                .section_end
+                                .get(&name)
+                                .copied()
+                                .ok_or_else(|| {
+                                    format!("line {line_no}: section '{name}' has no prior address")
+                                })?,
+                        };
+
+                        current = Some(SectionState { name: name.clone(), pc: addr });
+                        block_stack.push(BlockKind::Section(name));
+                        continue;
+                    }
+
+                    if keyword == "repeat" {
+                        let count = match tokens.get(1) {
+                            Some(token) if matches!(tokens.get(2), Some(Token::Sym("{"))) => {
+                                let expr = parse_expr(token).map_err(|err| at.describe(err))?;
+                                self.resolve_expr(&expr, line_no)?
+                            }
+                            _ => return Err(format!("line {line_no}: repeat syntax is: repeat N {{")),
+                        };
+                        // one-line form: `repeat N { stmt }`.
+                        if tokens.len() > 3 {
+                            let Some((Token::Sym("}"), inner)) = tokens[3..].split_last() else {
+                                return Err(format!("line {line_no}: unclosed one-line repeat"));
+                            };
+                            let stmt = self.parse_stmt_at(inner, &at)?;
+                            let size = stmt_size(&stmt)?;
+                            let state = current.as_mut().ok_or_else(|| {
+                                format!("line {line_no}: statement outside of a section")
+                            })?;
+                            for _ in 0..count {
+                                let stmt = stmt.clone();
+                                self.lines.push(StmtLine { addr: state.pc, line_no, stmt });
+                                state.pc = checked_add(state.pc, size, line_no)?;
+                            }
+                            continue;
+                        }
+                        if count == 0 {
+                            next = block_end(&source, idx)
+                                .ok_or_else(|| format!("line {line_no}: repeat is never closed"))?
+                                + 1;
+                            continue;
+                        }
+                        block_stack.push(BlockKind::Repeat { body: next, left: count - 1 });
+                        continue;
+                    }
+
+                    if keyword == "org" {
+                        let [_, Token::Number(addr)] = tokens[..] else {
+                            return Err(format!("line {line_no}: org requires an address"));
+                        };
+                        let state = current.as_mut().ok_or_else(|| {
+                            format!("line {line_no}: org outside of a section")
+                        })?;
+                        // skipped bytes are zero-filled; moving back would overlap.
+                        if addr < state.pc {
+                            return Err(format!(
+                                "line {line_no}: org {addr:#06x} is behind the current address {:#06x}",
+                                state.pc
+                            ));
+                        }
+                        state.pc = addr;
+                        continue;
+                    }
+
+                    if keyword == "align" {
+                        let [_, Token::Number(align)] = tokens[..] else {
+                            return Err(format!("line {line_no}: align requires a number"));
+                        };
+                        if !align.is_power_of_two() {
+                            return Err(format!(
+                                "line {line_no}: align {align} is not a power of two"
+                            ));
+                        }
+                        let state = current.as_mut().ok_or_else(|| {
+                            format!("line {line_no}: align outside of a section")
+                        })?;
+                        // the padding is emitted as zero bytes, so it counts
+                        // toward overlap checks like any other statement.
+                        let pad = state.pc.next_multiple_of(align) - state.pc;
+                        if pad > 0 {
+                            let stmt = Stmt::Zero(pad);
+                            self.lines.push(StmtLine { addr: state.pc, line_no, stmt });
+                            state.pc = checked_add(state.pc, pad, line_no)?;
+                        }
+                        continue;
+                    }
+
+                    if keyword == "const" {
+                        self.insert_const(&tokens, line_no)?;
+                        continue;
+                    }
+
+                    if keyword == "label" {
+                        let name = parse_label_header(&tokens)
+                            .map_err(|err| format!("line {line_no}: {err}"))?;
+                        let state = current.as_ref().ok_or_else(|| {
+                            format!("line {line_no}: label outside of a section")
+                        })?;
+                        self.insert_label(&name, state.pc, line_no)?;
+                        block_stack.push(BlockKind::Label);
+                        continue;
+                    }
+                }
+
+                if let Some((label, rest)) = parse_inline_label(&tokens) {
+                    let state = current.as_ref().ok_or_else(|| {
+                        format!("line {line_no}: label outside of a section")
+                    })?;
+                    self.insert_label(&label, state.pc, line_no)?;
+                    if rest.is_empty() {
+                        continue;
+                    }
+                    let stmt = self.parse_stmt_at(rest, &at)?;
+                    let size = stmt_size(&stmt)?;
+                    let state = current.as_mut().expect("section state missing");
+                    self.lines.push(StmtLine { addr: state.pc, line_no, stmt });
+                    state.pc = checked_add(state.pc, size, line_no)?;
+                    continue;
+                }
+
+                let stmt = self.parse_stmt_at(&tokens, &at)?;
+                let size = stmt_size(&stmt)?;
+                let state = current.as_mut().ok_or_else(|| {
+                    format!("line {line_no}: statement outside of a section")
+                })?;
+                self.lines.push(StmtLine { addr: state.pc, line_no, stmt });
+                state.pc = checked_add(state.pc, size, line_no)?;
+            }
+
+            if !block_stack.is_empty() {
+                return Err("unclosed block(s) at end of file".into());
+            }
+            if !conds.is_empty() {
+                return Err("unclosed .if at end of file".into());
+            }
+
+            Ok(())
+        }
+
+        /// parse a statement, reading `incbin` files relative to the directory
+        /// of the file the line came from.
+        fn parse_stmt_at(&self, tokens: &[Token], at: &Located) -> Result<Stmt, String> {
+            let line_no = at.line_no;
+            let [Token::Ident(keyword), rest @ ..] = tokens else {
+                return parse_stmt(tokens).map_err(|err| at.describe(err));
+            };
+            if keyword != "incbin" {
+                return parse_stmt(tokens).map_err(|err| at.describe(err));
+            }
+            let [Token::Str(file)] = rest else {
+                return Err(format!("line {line_no}: incbin requires a quoted file name"));
+            };
+            let path = self.source[line_no - 1].dir.join(file);
+            let bytes = fs::read(&path)
+                .map_err(|err| format!("line {line_no}: failed to read {}: {err}", path.display()))?;
+            if bytes.len() > u16::MAX as usize {
+                return Err(format!("line {line_no}: {file} is too large to embed"));
+            }
+            Ok(Stmt::IncBin(bytes))
+        }
+
+        fn second_pass(&self) -> Result<Vec<u8>, String> {
+            let mut max_end = 0u16;
+            let mut min_addr = u16::MAX;
+
+            for line in &self.lines {
+                min_addr = min_addr.min(line.addr);
+                let size = stmt_size(&line.stmt)?;
+                let end = line.addr.checked_add(size).ok_or_else(|| {
+                    format!("line {}: address overflow", line.line_no)
+                })?;
+                max_end = max_end.max(end);
+            }
+
+            if min_addr == u16::MAX {
+                return Ok(Vec::new());
+            }
+
+            if min_addr < 0x200 {
+                return Err(format!(
+                    "ROM start {min_addr:#06x} is below 0x200; Chip-8 programs load at 0x200"
+                ));
+            }
+
+            let base = 0x200u16;
+            let total_len = max_end
+                .checked_sub(base)
+                .ok_or_else(|| "program ends before base address".to_string())? as usize;
+
+            let mut output = vec![0u8; total_len];
+            let mut written = vec![false; total_len];
+
+            for line in &self.lines {
+                let addr = line.addr;
+                let bytes = self.emit_stmt(&line.stmt, line.line_no)?;
+                let offset = addr
+                    .checked_sub(base)
+                    .ok_or_else(|| format!("line {}: address below base", line.line_no))? as usize;
+
+                for (idx, byte) in bytes.into_iter().enumerate() {
+                    let pos = offset + idx;
+                    if pos >= output.len() {
+                        return Err(format!(
+                            "line {}: write past end of ROM (offset {pos})",
+                            line.line_no
+                        ));
+                    }
+                    if written[pos] {
+                        return Err(format!(
+                            "line {}: overlapping data at {:#06x}",
+                            line.line_no,
+                            addr + idx as u16
+                        ));
+                    }
+                    output[pos] = byte;
+                    written[pos] = true;
+                }
+            }
+
+            Ok(output)
+        }
+
+        fn emit_stmt(&self, stmt: &Stmt, line_no: usize) -> Result<Vec<u8>, String> {
+            match stmt {
+                Stmt::Byte(values) => {
+                    let mut out = Vec::new();
+                    for expr in values {
+                        let val = self.resolve_expr(expr, line_no)?;
+                        if val > 0xFF {
+                            return Err(format!("line {line_no}: byte value {val:#06x} too large"));
+                        }
+                        out.push(val as u8);
+                    }
+                    Ok(out)
+                }
+                Stmt::Word(values) => {
+                    let mut out = Vec::new();
+                    for expr in values {
+                        let val = self.resolve_expr(expr, line_no)?;
+                        out.push((val >> 8) as u8);
+                        out.push((val & 0xFF) as u8);
+                    }
+                    Ok(out)
+                }
+                Stmt::Zero(count) => Ok(vec![0u8; *count as usize]),
+                Stmt::Ascii(bytes) | Stmt::IncBin(bytes) => Ok(bytes.clone()),
+                Stmt::Sys(expr) => {
+                    let val = self.resolve_expr(expr, line_no)?;
+                    if val & 0xF000 != 0 {
+                        return Err(format!("line {line_no}: sys value {val:#06x} exceeds 0x0FFF"));
+                    }
+                    Ok(vec![(val >> 8) as u8, (val & 0xFF) as u8])
+                }
+                Stmt::Instr(instr) => self.emit_instr(instr, line_no),
+            }
+        }
+
+        fn emit_instr(&self, instr: &Instr, line_no: usize) -> Result<Vec<u8>, String> {
+            match instr {
+                Instr::Clear => Ok(word(0x00E0)),
+                Instr::Ret => Ok(word(0x00EE)),
+                Instr::Jump(expr) => {
+                    let addr = self.resolve_addr(expr, line_no)?;
+                    Ok(word(0x1000 | addr))
+                }
+                Instr::Call(expr) => {
+                    let addr = self.resolve_addr(expr, line_no)?;
+                    Ok(word(0x2000 | addr))
+                }
+                Instr::LoadI(expr) => {
+                    let addr = self.resolve_addr(expr, line_no)?;
+                    Ok(word(0xA000 | addr))
+                }
+                Instr::AddI(reg) => Ok(word(0xF01E | ((reg.0 as u16) << 8))),
+                Instr::Load(reg, operand) => match operand {
+                    Operand::Imm(expr) => {
+                        let val = self.resolve_byte(expr, line_no)?;
+                        Ok(word(0x6000 | ((reg.0 as u16) << 8) | val as u16))
+                    }
+                    Operand::Reg(src) => Ok(word(
+                        0x8000 | ((reg.0 as u16) << 8) | ((src.0 as u16) << 4),
+                    )),
+                },
+                Instr::Add(reg, operand) => match operand {
+                    Operand::Imm(expr) => {
+                        let val = self.resolve_byte(expr, line_no)?;
+                        Ok(word(0x7000 | ((reg.0 as u16) << 8) | val as u16))
+                    }
+                    Operand::Reg(src) => Ok(word(
+                        0x8004 | ((reg.0 as u16) << 8) | ((src.0 as u16) << 4),
+                    )),
+                },
+                Instr::Sub(reg, operand) => match operand {
+                    // no subtract-immediate opcode: add the two's complement.
+                    Operand::Imm(expr) => {
+                        let val = self.resolve_byte(expr, line_no)?;
+                        let delta = (0x100u16 - val as u16) & 0xFF;
+                        Ok(word(0x7000 | ((reg.0 as u16) << 8) | delta))
+                    }
+                    Operand::Reg(src) => Ok(word(reg_pair(0x8005, reg, src))),
+                },
+                Instr::Rand(reg, expr) => {
+                    let mask = self.resolve_byte(expr, line_no)?;
+                    Ok(word(0xC000 | ((reg.0 as u16) << 8) | mask as u16))
+                }
+                Instr::Draw { x, y, n } => {
+                    let height = self.resolve_nibble(n, line_no)?;
+                    Ok(word(0xD000 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4) | height as u16))
+                }
+                Instr::Or(dst, src) => Ok(word(reg_pair(0x8001, dst, src))),
+                Instr::And(dst, src) => Ok(word(reg_pair(0x8002, dst, src))),
+                Instr::Xor(dst, src) => Ok(word(reg_pair(0x8003, dst, src))),
+                Instr::SubRev(dst, src) => Ok(word(reg_pair(0x8007, dst, src))),
+                // the engine shifts VX in place; Y is still encoded for
+                // interpreters that shift VY into VX.
+                Instr::Shr(dst, src) => Ok(word(reg_pair(0x8006, dst, src))),
+                Instr::Shl(dst, src) => Ok(word(reg_pair(0x800E, dst, src))),
+                Instr::Font(reg) => Ok(word(0xF029 | ((reg.0 as u16) << 8))),
+                Instr::Bcd(reg) => Ok(word(0xF033 | ((reg.0 as u16) << 8))),
+                Instr::GetDelay(reg) => Ok(word(0xF007 | ((reg.0 as u16) << 8))),
+                Instr::SetDelay(reg) => Ok(word(0xF015 | ((reg.0 as u16) << 8))),
+                Instr::SetSound(reg) => Ok(word(0xF018 | ((reg.0 as u16) << 8))),
+                Instr::Save(reg) => Ok(word(0xF055 | ((reg.0 as u16) << 8))),
+                Instr::LoadMem(reg) => Ok(word(0xF065 | ((reg.0 as u16) << 8))),
+                Instr::If { left, op, right, target } => {
+                    let skip = match (op, right) {
+                        (CmpOp::Eq, Operand::Imm(expr)) => {
+                            let val = self.resolve_byte(expr, line_no)?;
+                            0x4000 | ((left.0 as u16) << 8) | val as u16
+                        }
+                        (CmpOp::Ne, Operand::Imm(expr)) => {
+                            let val = self.resolve_byte(expr, line_no)?;
+                            0x3000 | ((left.0 as u16) << 8) | val as u16
+                        }
+                        (CmpOp::Eq, Operand::Reg(reg)) => {
+                            0x9000 | ((left.0 as u16) << 8) | ((reg.0 as u16) << 4)
+                        }
+                        (CmpOp::Ne, Operand::Reg(reg)) => {
+                            0x5000 | ((left.0 as u16) << 8) | ((reg.0 as u16) << 4)
+                        }
+                    };
+                    let addr = self.resolve_addr(target, line_no)?;
+                    let jump = 0x1000 | addr;
+                    Ok(vec![
+                        (skip >> 8) as u8,
+                        (skip & 0xFF) as u8,
+                        (jump >> 8) as u8,
+                        (jump & 0xFF) as u8,
+                    ])
+                }
+                Instr::IfKey { reg, pressed, target } => {
+                    // skip the jump unless the condition holds: ExA1 skips when
+                    // the key is up, Ex9E when it is down.
+                    let skip = if *pressed { 0xE0A1 } else { 0xE09E } | ((reg.0 as u16) << 8);
+                    let addr = self.resolve_addr(target, line_no)?;
+                    let jump = 0x1000 | addr;
+                    Ok(vec![
+                        (skip >> 8) as u8,
+                        (skip & 0xFF) as u8,
+                        (jump >> 8) as u8,
+                        (jump & 0xFF) as u8,
+                    ])
+                }
+            }
+        }
+
+        fn resolve_expr(&self, expr: &Expr, line_no: usize) -> Result<u16, String> {
+            match expr {
+                Expr::Num(val) => Ok(*val),
+                Expr::Label(name) => {
+                    if let Some(addr) = self.labels.get(name) {
+                        return Ok(*addr);
+                    }
+                    match self.consts.get(name) {
+                        Some(&(_, def)) if def > line_no => Err(format!(
+                            "line {line_no}: constant '{name}' used before its definition on line {def}"
+                        )),
+                        Some(&(val, _)) => Ok(val),
+                        None => Err(format!("line {line_no}: unknown label '{name}'")),
+                    }
+                }
+                Expr::Offset(name, delta) => {
+                    let base = self.resolve_expr(&Expr::Label(name.clone()), line_no)?;
+                    u16::try_from(base as i32 + delta).map_err(|_| {
+                        format!("line {line_no}: '{name}{delta:+}' is outside 0x0000..=0xFFFF")
+                    })
+                }
+            }
+        }
+
+        fn resolve_byte(&self, expr: &Expr, line_no: usize) -> Result<u8, String> {
+            let val = self.resolve_expr(expr, line_no)?;
+            if val > 0xFF {
+                return Err(format!("line {line_no}: value {val:#06x} does not fit in a byte"));
+            }
+            Ok(val as u8)
+        }
+
+        fn resolve_nibble(&self, expr: &Expr, line_no: usize) -> Result<u8, String> {
+            let val = self.resolve_expr(expr, line_no)?;
+            if val > 0xF {
+                return Err(format!(
+                    "line {line_no}: value {val:#06x} does not fit in a nibble (0..=15)"
+                ));
+            }
+            Ok(val as u8)
+        }
+
+        fn resolve_addr(&self, expr: &Expr, line_no: usize) -> Result<u16, String> {
+            let addr = self.resolve_expr(expr, line_no)?;
+            if addr > 0x0FFF {
+                return Err(format!("line {line_no}: address {addr:#06x} exceeds 0x0FFF"));
+            }
+            Ok(addr)
+        }
+
+        fn insert_label(&mut self, name: &str, addr: u16, line_no: usize) -> Result<(), String> {
+            if self.labels.contains_key(name) {
+                return Err(format!("line {line_no}: duplicate label '{name}'"));
+            }
+            if self.consts.contains_key(name) {
+                return Err(format!("line {line_no}: label '{name}' collides with a constant"));
+            }
+            self.labels.insert(name.to_string(), addr);
+            Ok(())
+        }
+
+        /// `const NAME = expr`: the value is a number or an earlier constant,
+        /// fixed at definition since label addresses are not known yet.
+        fn step_cond(
+            &self,
+            conds: &mut Vec<Cond>,
+            directive: CondDirective,
+            line_no: usize,
+        ) -> Result<(), String> {
+            match directive {
+                CondDirective::If(expr) => {
+                    let outer = conds.last().is_none_or(|cond| cond.live);
+                    // inactive branches are not evaluated, so they may name
+                    // constants that only exist in the other variant.
+                    let live = outer && self.eval_cond(expr, line_no)?;
+                    conds.push(Cond { outer, taken: live, live, seen_else: false });
+                }
+                CondDirective::Else => {
+                    let cond = conds
+                        .last_mut()
+                        .ok_or_else(|| format!("line {line_no}: .else without .if"))?;
+                    if cond.seen_else {
+                        return Err(format!("line {line_no}: duplicate .else"));
+                    }
+                    cond.seen_else = true;
+                    cond.live = cond.outer && !cond.taken;
+                    cond.taken |= cond.live;
+                }
+                CondDirective::EndIf => {
+                    conds
+                        .pop()
+                        .ok_or_else(|| format!("line {line_no}: .endif without .if"))?;
+                }
+            }
+            Ok(())
+        }
+
+        fn eval_cond(&self, expr: &str, line_no: usize) -> Result<bool, String> {
+            eval_cond(expr, line_no, |name| self.consts.get(name).map(|&(val, _)| val))
+        }
+
+        fn insert_const(&mut self, tokens: &[Token], line_no: usize) -> Result<(), String> {
+            let [_, Token::Ident(name), Token::Sym("="), value] = tokens else {
+                return Err(format!("line {line_no}: const syntax is: const NAME = value"));
+            };
+            if self.labels.contains_key(name) {
+                return Err(format!("line {line_no}: constant '{name}' collides with a label"));
+            }
+            if self.consts.contains_key(name) {
+                return Err(format!("line {line_no}: duplicate constant '{name}'"));
+            }
+            let val = match value {
+                Token::Number(val) => *val,
+                Token::Ident(other) => match self.consts.get(other) {
+                    Some(&(val, _)) => val,
+                    None => {
+                        return Err(format!(
+                            "line {line_no}: '{other}' is not an earlier constant"
+                        ));
+                    }
+                },
+                _ => return Err(format!("line {line_no}: constant value must be a number")),
+            };
+            self.consts.insert(name.clone(), (val, line_no));
+            Ok(())
+        }
+    }
+
+    // a conditional assembly directive, split off before tokenizing.
+    enum CondDirective<'a> {
+        If(&'a str),
+        Else,
+        EndIf,
+    }
+
+    fn cond_directive(line: &str) -> Option<CondDirective<'_>> {
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match word {
+            ".if" => Some(CondDirective::If(rest.trim())),
+            ".else" => Some(CondDirective::Else),
+            ".endif" => Some(CondDirective::EndIf),
+            _ => None,
+        }
+    }
+
+    // `.if NAME`, `.if 1`, or `.if NAME == value` / `!=` over constants,
+    // looked up through `lookup`.
+    fn eval_cond<F>(expr: &str, line_no: usize, lookup: F) -> Result<bool, String>
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        let (tokens, _) = tokenize(expr).map_err(|(_, err)| format!("line {line_no}: {err}"))?;
+        let value = |token: &Token| match token {
+            Token::Number(val) => Ok(*val),
+            Token::Ident(name) => lookup(name)
+                .ok_or_else(|| format!("line {line_no}: '{name}' is not an earlier constant")),
+            _ => Err(format!("line {line_no}: .if operands must be numbers or constants")),
+        };
+        match tokens.as_slice() {
+            [single] => Ok(value(single)? != 0),
+            [left, Token::Sym("=="), right] => Ok(value(left)? == value(right)?),
+            [left, Token::Sym("!="), right] => Ok(value(left)? != value(right)?),
+            _ => Err(format!("line {line_no}: .if syntax is: .if NAME or .if NAME == value")),
+        }
+    }
+
+    /// follows `.if` nesting ahead of the passes, so include and macro
+    /// expansion can leave inactive branches alone. A condition it cannot
+    /// decide yet (say, on a constant a macro defines) counts as live, and
+    /// malformed directives are left for the first pass to report.
+    #[derive(Default)]
+    struct CondScan {
+        consts: HashMap<String, u16>,
+        conds: Vec<Cond>,
+    }
+
+    impl CondScan {
+        fn live(&self) -> bool {
+            self.conds.last().is_none_or(|cond| cond.live)
+        }
+
+        // whether `text` sits in a live branch; directive lines count as live
+        // so they still reach the passes.
+        fn feed(&mut self, text: &str) -> bool {
+            let line = strip_comments(text).unwrap_or_default();
+            match cond_directive(line.trim()) {
+                Some(CondDirective::If(expr)) => {
+                    let outer = self.live();
+                    let live = outer
+                        && eval_cond(expr, 0, |name| self.consts.get(name).copied()).unwrap_or(true);
+                    self.conds.push(Cond { outer, taken: live, live, seen_else: false });
+                }
+                Some(CondDirective::Else) => {
+                    if let Some(cond) = self.conds.last_mut() {
+                        cond.live = cond.outer && !cond.taken;
+                        cond.taken |= cond.live;
+                    }
+                }
+                Some(CondDirective::EndIf) => {
+                    self.conds.pop();
+                }
+                None => {
+                    let live = self.live();
+                    if live
+                        && let [Token::Ident(keyword), Token::Ident(name), Token::Sym("="), value] =
+                            &line_tokens(&line)[..]
+                        && keyword == "const"
+                    {
+                        let val = match value {
+                            Token::Number(val) => Some(*val),
+                            Token::Ident(other) => self.consts.get(other).copied(),
+                            _ => None,
+                        };
+                        if let Some(val) = val {
+                            self.consts.entry(name.clone()).or_insert(val);
+                        }
+                    }
+                    return live;
+                }
+            }
+            true
+        }
+    }
+
+    // index of the `}` line closing the block opened on line `open`.
+    fn block_end(source: &[String], open: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (idx, text) in source.iter().enumerate().skip(open) {
+            let tokens = line_tokens(text);
+            if matches!(tokens.last(), Some(Token::Sym("{"))) {
+                depth += 1;
+            } else if matches!(tokens[..], [Token::Sym("}")]) {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+        }
+        None
+    }
+
+    /// flatten `include "file"` lines into `out`, depth first. Paths resolve
+    /// against the including file's directory; `chain` holds the files being
+    /// expanded so a cycle is reported instead of recursing forever. An
+    /// include in an inactive `.if` branch is passed through unread.
+    fn expand_includes(
+        source: &str,
+        path: Option<&Path>,
+        name: Option<&str>,
+        chain: &mut Vec<PathBuf>,
+        scan: &mut CondScan,
+        out: &mut Vec<SourceLine>,
+    ) -> Result<(), String> {
+        let here = |line: usize| match name {
+            Some(name) => format!("{name} line {line}"),
+            None => format!("line {line}"),
+        };
+        let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+        for (idx, text) in source.lines().enumerate() {
+            let line = idx + 1;
+            let live = scan.feed(text);
+            let include = match &line_tokens(text)[..] {
+                _ if !live => None,
+                [Token::Ident(keyword), Token::Str(file)] if keyword == "include" => Some(file.clone()),
+                _ => None,
+            };
+            let Some(file) = include else {
+                out.push(SourceLine {
+                    file: name.map(str::to_string),
+                    dir: dir.to_path_buf(),
+                    line,
+                    text: text.to_string(),
+                });
+                continue;
+            };
+
+            let target = dir.join(&file);
+            let failed = |err: std::io::Error| {
+                format!("{}: failed to include {}: {err}", here(line), target.display())
+            };
+            let key = fs::canonicalize(&target).map_err(failed)?;
+            if chain.contains(&key) {
+                return Err(format!("{}: include cycle through {file}", here(line)));
+            }
+            let text = fs::read_to_string(&key).map_err(failed)?;
+            chain.push(key);
+            expand_includes(&text, Some(&target), Some(&file), chain, scan, out)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+
+    /// collect `macro NAME p0 p1 {` ... `}` definitions and replace each
+    /// `NAME a0, a1` call with the body, parameters substituted word by word.
+    /// Expanded lines keep the call site's location for error messages.
+    /// Definitions and calls in inactive `.if` branches are left as they are.
+    fn expand_macros(source: Vec<SourceLine>) -> Result<Vec<SourceLine>, String> {
+        let mut macros: HashMap<String, Macro> = HashMap::new();
+        let mut plain = Vec::new();
+        let mut scan = CondScan::default();
+        let mut lines = source.into_iter();
+        while let Some(src) = lines.next() {
+            let live = scan.feed(&src.text);
+            let tokens = line_tokens(&src.text);
+            let [Token::Ident(keyword), Token::Ident(name), params @ .., Token::Sym("{")] = &tokens[..]
+            else {
+                plain.push((src, live));
+                continue;
+            };
+            if keyword != "macro" || !live {
+                plain.push((src, live));
+                continue;
+            }
+            let params = params
+                .iter()
+                .map(|token| match token {
+                    Token::Ident(param) => Ok(param.clone()),
+                    _ => Err(format!("{}: macro parameters must be names", src.loc())),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // the body ends at the `}` that balances the header's `{`.
+            let mut body = Vec::new();
+            let mut depth = 1;
+            loop {
+                let Some(line) = lines.next() else {
+                    return Err(format!("{}: macro '{name}' is never closed", src.loc()));
+                };
+                let tokens = line_tokens(&line.text);
+                if matches!(tokens.last(), Some(Token::Sym("{"))) {
+                    depth += 1;
+                } else if matches!(tokens[..], [Token::Sym("}")]) {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                body.push(line.text);
+            }
+            if macros.insert(name.clone(), Macro { params, body }).is_some() {
+                return Err(format!("{}: duplicate macro '{name}'", src.loc()));
+            }
+        }
+
+        let mut out = Vec::new();
+        for (src, live) in plain {
+            if live {
+                expand_line(&macros, src, 0, &mut out)?;
+            } else {
+                out.push(src);
+            }
+        }
+        Ok(out)
+    }
+
+    fn expand_line(
+        macros: &HashMap<String, Macro>,
+        src: SourceLine,
+        depth: usize,
+        out: &mut Vec<SourceLine>,
+    ) -> Result<(), String> {
+        let tokens = line_tokens(&src.text);
+        let Some(Token::Ident(name)) = tokens.first() else {
+            out.push(src);
+            return Ok(());
+        };
+        let Some(mac) = macros.get(name) else {
+            out.push(src);
+            return Ok(());
+        };
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(format!(
+                "{}: macro '{name}' nests deeper than {MAX_MACRO_DEPTH} (recursive macro?)",
+                src.loc()
+            ));
+        }
+
+        let text = strip_comments(&src.text).unwrap_or_default();
+        let args: Vec<&str> = text
+            .trim()
+            .split(|ch: char| ch == ',' || ch.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .skip(1)
+            .collect();
+        if args.len() != mac.params.len() {
+            return Err(format!(
+                "{}: macro '{name}' takes {} argument(s), got {}",
+                src.loc(),
+                mac.params.len(),
+                args.len()
+            ));
+        }
+        for body in &mac.body {
+            let line = SourceLine {
+                text: substitute(body, &mac.params, &args),
+                ..src.clone()
+            };
+            expand_line(macros, line, depth + 1, out)?;
+        }
+        Ok(())
+    }
+
+    // tokens of a source line, or none if it does not tokenize (the passes
+    // report that error with its location).
+    fn line_tokens(text: &str) -> Vec<Token> {
+        strip_comments(text)
+            .ok()
+            .and_then(|stripped| tokenize(stripped.trim()).ok())
+            .map(|(tokens, _)| tokens)
+            .unwrap_or_default()
+    }
+
+    // replace whole identifiers named in `params`, leaving string literals alone.
+    fn substitute(text: &str, params: &[String], args: &[&str]) -> String {
+        let mut out = String::new();
+        let mut ident = String::new();
+        let mut in_string = false;
+        let flush = |ident: &mut String, out: &mut String| {
+            match params.iter().position(|param| param == ident) {
+                Some(idx) => out.push_str(args[idx]),
+                None => out.push_str(ident),
+            }
+            ident.clear();
+        };
+        for ch in text.chars() {
+            if !in_string && (ch.is_ascii_alphanumeric() || ch == '_') {
+                ident.push(ch);
+                continue;
+            }
+            flush(&mut ident, &mut out);
+            if ch == '"' {
+                in_string = !in_string;
+            }
+            out.push(ch);
+        }
+        flush(&mut ident, &mut out);
+        out
+    }
+
+    fn strip_comments(line: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut chars = line.chars().peekable();
+        let mut in_string = false;
+
+        while let Some(ch) = chars.next() {
+            if ch == '"' {
+                out.push(ch);
+                in_string = !in_string;
+                continue;
+            }
+            if !in_string && ch == '\'' {
+                // copy a character literal whole so `';'` is not a comment.
+                out.push(ch);
+                if let Some(lit) = chars.next() {
+                    out.push(lit);
+                    if lit == '\\'
+                        && let Some(escaped) = chars.next()
+                    {
+                        out.push(escaped);
+                    }
+                }
+                if let Some(close) = chars.next() {
+                    out.push(close);
+                }
+                continue;
+            }
+            if !in_string && (ch == ';' || ch == '#') {
+                break;
+            }
+            out.push(ch);
+        }
+
+        if in_string {
+            return Err("unterminated string literal".into());
+        }
+
+        Ok(out)
+    }
+
+    /// split a line into tokens and the 1-based column each starts at.
+    /// Errors carry the column of the token being read.
+    fn tokenize(line: &str) -> Result<(Vec<Token>, Vec<usize>), (usize, String)> {
+        let mut tokens = Vec::new();
+        let mut start = 1;
+        lex(line, &mut tokens, &mut start).map_err(|err| (start, err))?;
+        Ok(fold_offsets(tokens).into_iter().unzip())
+    }
+
+    // `start` tracks the column of the token being read for error reports.
+    fn lex(line: &str, tokens: &mut Vec<(Token, usize)>, start: &mut usize) -> Result<(), String> {
+        let total = line.chars().count();
+        let mut chars = line.chars().peekable();
+
+        while let Some(&ch) = chars.peek() {
+            if ch.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            let col = total - chars.clone().count() + 1;
+            *start = col;
+
+            if ch.is_ascii_alphabetic() || ch == '_' {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push((Token::Ident(ident), col));
+                continue;
+            }
+
+            if ch.is_ascii_digit() {
+                let mut text = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_hexdigit() || c == 'x' || c == 'X' {
+                        text.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let value = parse_number(&text)?;
+                tokens.push((Token::Number(value), col));
+                continue;
+            }
+
+            if ch == '\'' {
+                chars.next();
+                let lit = match chars.next() {
+                    Some('\\') => {
+                        let Some(escaped) = chars.next() else {
+                            return Err("unterminated character escape".into());
+                        };
+                        unescape(escaped)?
+                    }
+                    Some(lit) if lit != '\'' => lit,
+                    _ => return Err("empty character literal".into()),
+                };
+                if chars.next() != Some('\'') {
+                    return Err("unterminated character literal".into());
+                }
+                let value = u8::try_from(lit)
+                    .map_err(|_| format!("character '{lit}' does not fit in a byte"))?;
+                tokens.push((Token::Number(value as u16), col));
+                continue;
+            }
+
+            if ch == '"' {
+                chars.next();
+                let mut buf = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        break;
+                    }
+                    if c == '\\' {
+                        let Some(escaped) = chars.next() else {
+                            return Err("unterminated string escape".into());
+                        };
+                        buf.push(unescape(escaped)?);
+                    } else {
+                        buf.push(c);
+                    }
+                }
+                tokens.push((Token::Str(buf), col));
+                continue;
+            }
+
+            let three: String = chars.clone().take(3).collect();
+            let sym = match three.as_str() {
+                ">>=" => Some(">>="),
+                "<<=" => Some("<<="),
+                _ => None,
+            };
+            if let Some(sym) = sym {
+                chars.nth(2);
+                tokens.push((Token::Sym(sym), col));
+                continue;
+            }
+
+            let two = {
+                let mut iter = chars.clone();
+                let first = iter.next();
+                let second = iter.next();
+                if let (Some(a), Some(b)) = (first, second) {
+                    let mut tmp = String::new();
+                    tmp.push(a);
+                    tmp.push(b);
+                    Some(tmp)
+                } else {
+                    None
+                }
+            };
+
+            if let Some(two) = two {
+                let sym = match two.as_str() {
+                    ":=" => Some(":="),
+                    "+=" => Some("+="),
+                    "-=" => Some("-="),
+                    "==" => Some("=="),
+                    "!=" => Some("!="),
+                    "|=" => Some("|="),
+                    "&=" => Some("&="),
+                    "^=" => Some("^="),
+                    "=-" => Some("=-"),
+                    _ => None,
+                };
+                if let Some(sym) = sym {
+                    chars.next();
+                    chars.next();
+                    tokens.push((Token::Sym(sym), col));
+                    continue;
+                }
+            }
+
+            let sym = match ch {
+                '{' => "{",
+                '}' => "}",
+                ':' => ":",
+                '@' => "@",
+                ',' => ",",
+                '-' => "-",
+                '+' => "+",
+                '=' => "=",
+                _ => return Err(format!("unexpected character '{ch}'")),
+            };
+            chars.next();
+            tokens.push((Token::Sym(sym), col));
+        }
+
+        Ok(())
+    }
+
+    // turn `name + N` / `name - N` into one token so operand positions still
+    // hold a single token.
+    fn fold_offsets(tokens: Vec<(Token, usize)>) -> Vec<(Token, usize)> {
+        let mut out = Vec::with_capacity(tokens.len());
+        let mut iter = tokens.into_iter().peekable();
+        while let Some((token, col)) = iter.next() {
+            if let Some((Token::Sym(op @ ("+" | "-")), _)) = iter.peek().cloned()
+                && let Token::Ident(name) = &token
+            {
+                let mut ahead = iter.clone();
+                ahead.next();
+                if let Some((Token::Number(val), _)) = ahead.next() {
+                    let delta = if op == "+" { val as i32 } else { -(val as i32) };
+                    out.push((Token::Offset(name.clone(), delta), col));
+                    iter = ahead;
+                    continue;
+                }
+            }
+            out.push((token, col));
+        }
+        out
+    }
+
+    // the escapes shared by string and character literals.
+    fn unescape(escaped: char) -> Result<char, String> {
+        match escaped {
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            '\\' => Ok('\\'),
+            other => Err(format!("unsupported escape \\{other}")),
+        }
+    }
+
+    fn parse_number(text: &str) -> Result<u16, String> {
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            u16::from_str_radix(hex, 16).map_err(|_| format!("invalid hex literal '{text}'"))
+        } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+            u16::from_str_radix(bin, 2).map_err(|_| format!("invalid binary literal '{text}'"))
+        } else {
+            text.parse::<u16>()
+                .map_err(|_| format!("invalid number '{text}'"))
+        }
+    }
+
+    fn parse_section_header(tokens: &[Token]) -> Result<(String, Option<u16>), String> {
+        if tokens.len() < 3 {
+            return Err("section header too short".into());
+        }
+        let name = match &tokens[1] {
+            Token::Ident(val) => val.clone(),
+            _ => return Err("expected section name".into()),
+        };
+
+        if matches!(tokens.get(2), Some(Token::Sym("{"))) {
+            return Ok((name, None));
+        }
+
+        if tokens.len() < 5 {
+            return Err("section header requires '@ <addr> {'".into());
+        }
+        if !matches!(tokens.get(2), Some(Token::Sym("@"))) {
+            return Err("expected '@' after section name".into());
+        }
+        let addr = match tokens.get(3) {
+            Some(Token::Number(val)) => *val,
+            _ => return Err("expected address after '@'".into()),
+        };
+        if !matches!(tokens.get(4), Some(Token::Sym("{"))) {
+            return Err("expected '{' after section header".into());
+        }
+        Ok((name, Some(addr)))
+    }
+
+    fn parse_label_header(tokens: &[Token]) -> Result<String, String> {
+        if tokens.len() != 3 {
+            return Err("label header must be 'label NAME {'".into());
+        }
+        let name = match &tokens[1] {
+            Token::Ident(val) => val.clone(),
+            _ => return Err("expected label name".into()),
+        };
+        if !matches!(tokens[2], Token::Sym("{")) {
+            return Err("expected '{' after label name".into());
+        }
+        Ok(name)
+    }
+
+    fn parse_inline_label(tokens: &[Token]) -> Option<(String, &[Token])> {
+        if tokens.len() >= 2 {
+            if let (Token::Ident(name), Token::Sym(":")) = (&tokens[0], &tokens[1]) {
+                return Some((name.clone(), &tokens[2..]));
+            }
+        }
+        None
+    }
+
+    fn parse_stmt(tokens: &[Token]) -> Result<Stmt, ParseError<'_>> {
+        if tokens.is_empty() {
+            return Err("empty statement".into());
+        }
+
+        match &tokens[0] {
+            Token::Ident(keyword) if keyword == "byte" => {
+                if tokens.len() < 2 {
+                    return Err("byte requires at least one value".into());
+                }
+                let values = parse_expr_list(&tokens[1..])?;
+                Ok(Stmt::Byte(values))
+            }
+            Token::Ident(keyword) if keyword == "word" => {
+                if tokens.len() < 2 {
+                    return Err("word requires at least one value".into());
+                }
+                let values = parse_expr_list(&tokens[1..])?;
+                Ok(Stmt::Word(values))
+            }
+            Token::Ident(keyword) if keyword == "zero" => {
+                if tokens.len() != 2 {
+                    return Err("zero requires a single count".into());
+                }
+                let count = match &tokens[1] {
+                    Token::Number(val) => *val,
+                    _ => return Err(ParseError::at(&tokens[1], "zero requires a numeric count")),
+                };
+                Ok(Stmt::Zero(count))
+            }
+            Token::Ident(keyword) if keyword == "ascii" => {
+                if tokens.len() != 2 {
+                    return Err("ascii requires a single string literal".into());
+                }
+                let bytes = match &tokens[1] {
+                    Token::Str(val) => val.as_bytes().to_vec(),
+                    _ => return Err(ParseError::at(&tokens[1], "ascii requires a string literal")),
+                };
+                Ok(Stmt::Ascii(bytes))
+            }
+            Token::Ident(keyword) if keyword == "sys" => {
+                if tokens.len() != 2 {
+                    return Err("sys requires a single value".into());
+                }
+                let expr = parse_expr(&tokens[1])?;
+                Ok(Stmt::Sys(expr))
+            }
+            _ => Ok(Stmt::Instr(parse_instr(tokens)?)),
+        }
+    }
+
+    fn parse_expr_list(tokens: &[Token]) -> Result<Vec<Expr>, ParseError<'_>> {
+        let mut out = Vec::new();
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let token = &tokens[idx];
+            if matches!(token, Token::Sym(",")) {
+                idx += 1;
+                continue;
+            }
+            out.push(parse_expr(token)?);
+            idx += 1;
+        }
+        Ok(out)
+    }
+
+    fn parse_expr(token: &Token) -> Result<Expr, ParseError<'_>> {
+        match token {
+            Token::Number(val) => Ok(Expr::Num(*val)),
+            Token::Ident(name) => Ok(Expr::Label(name.clone())),
+            Token::Offset(name, delta) => Ok(Expr::Offset(name.clone(), *delta)),
+            _ => Err(ParseError::at(token, "expected number or label")),
+        }
+    }
+
+    fn parse_instr(tokens: &[Token]) -> Result<Instr, ParseError<'_>> {
+        match &tokens[0] {
+            Token::Ident(keyword) if keyword == "clear" => {
+                if tokens.len() != 1 {
+                    return Err("clear takes no operands".into());
+                }
+                Ok(Instr::Clear)
+            }
+            Token::Ident(keyword) if keyword == "return" => Ok(Instr::Ret),
+            Token::Ident(keyword) if keyword == "draw" => match tokens {
+                [_, x, Token::Sym(","), y, Token::Sym(","), n] => Ok(Instr::Draw {
+                    x: parse_reg(x)?,
+                    y: parse_reg(y)?,
+                    n: parse_expr(n)?,
+                }),
+                _ => Err("draw requires vX, vY, height".into()),
+            },
+            Token::Ident(keyword) if keyword == "jump" => {
+                if tokens.len() != 2 {
+                    return Err("jump requires a target".into());
+                }
+                Ok(Instr::Jump(parse_expr(&tokens[1])?))
+            }
+            Token::Ident(keyword) if keyword == "call" => {
+                if tokens.len() != 2 {
+                    return Err("call requires a target".into());
+                }
+                Ok(Instr::Call(parse_expr(&tokens[1])?))
+            }
+            Token::Ident(keyword) if keyword == "save" => {
+                if tokens.len() != 2 {
+                    return Err("save requires a register".into());
+                }
+                Ok(Instr::Save(parse_reg(&tokens[1])?))
+            }
+            Token::Ident(keyword) if keyword == "load" => {
+                if tokens.len() != 2 {
+                    return Err("load requires a register".into());
+                }
+                Ok(Instr::LoadMem(parse_reg(&tokens[1])?))
+            }
+            Token::Ident(keyword) if keyword == "bcd" => {
+                if tokens.len() != 2 {
+                    return Err("bcd requires a register".into());
+                }
+                Ok(Instr::Bcd(parse_reg(&tokens[1])?))
+            }
+            Token::Ident(keyword) if keyword == "if" => parse_if(tokens),
+            Token::Ident(keyword) if keyword == "i" => parse_i_assign(tokens),
+            Token::Ident(keyword) if keyword == "delay" || keyword == "buzzer" => {
+                parse_timer_assign(tokens)
+            }
+            Token::Ident(_) => parse_reg_instr(tokens),
+            _ => Err(ParseError::at(&tokens[0], "unrecognized instruction")),
+        }
+    }
+
+    fn parse_if(tokens: &[Token]) -> Result<Instr, ParseError<'_>> {
+        match tokens {
+            [_, Token::Ident(word), rest @ ..] if word == "key" => {
+                return parse_if_key(rest, true);
+            }
+            [_, Token::Sym("-"), Token::Ident(word), rest @ ..] if word == "key" => {
+                return parse_if_key(rest, false);
+            }
+            _ => {}
+        }
+        if tokens.len() != 7 {
+            return Err("if syntax is: if vX == vY/0xNN then jump label".into());
+        }
+
+        let left = parse_reg(&tokens[1])?;
+        let op = match &tokens[2] {
+            Token::Sym("==") => CmpOp::Eq,
+            Token::Sym("!=") => CmpOp::Ne,
+            _ => return Err(ParseError::at(&tokens[2], "if requires '==' or '!='")),
+        };
+
+        let right = match &tokens[3] {
+            Token::Ident(name) if is_reg(name) => Operand::Reg(parse_reg(&tokens[3])?),
+            _ => Operand::Imm(parse_expr(&tokens[3])?),
+        };
+
+        if !matches!(&tokens[4], Token::Ident(word) if word == "then") {
+            return Err(ParseError::at(&tokens[4], "if requires 'then'"));
+        }
+        if !matches!(&tokens[5], Token::Ident(word) if word == "jump") {
+            return Err(ParseError::at(&tokens[5], "if only supports 'then jump'"));
+        }
+
+        let target = parse_expr(&tokens[6])?;
+        Ok(Instr::If { left, op, right, target })
+    }
+
+    // `vX then jump label`, after `if key` / `if -key`.
+    fn parse_if_key(tokens: &[Token], pressed: bool) -> Result<Instr, ParseError<'_>> {
+        let [reg, Token::Ident(then), Token::Ident(jump), target] = tokens else {
+            return Err("if syntax is: if key/-key vX then jump label".into());
+        };
+        if then != "then" || jump != "jump" {
+            return Err("if only supports 'then jump'".into());
+        }
+        Ok(Instr::IfKey {
+            reg: parse_reg(reg)?,
+            pressed,
+            target: parse_expr(target)?,
+        })
+    }
+
+    fn parse_i_assign(tokens: &[Token]) -> Result<Instr, ParseError<'_>> {
+        if let [_, Token::Sym(":="), Token::Ident(keyword), rest @ ..] = tokens
+            && keyword == "font"
+        {
+            let [reg] = rest else {
+                return Err("font syntax is: i := font vX".into());
+            };
+            return Ok(Instr::Font(parse_reg(reg)?));
+        }
+        if tokens.len() != 3 {
+            return Err("i assignment requires i := expr or i += vX".into());
+        }
+        match &tokens[1] {
+            Token::Sym(":=") => Ok(Instr::LoadI(parse_expr(&tokens[2])?)),
+            Token::Sym("+=") => Ok(Instr::AddI(parse_reg(&tokens[2])?)),
+            _ => Err(ParseError::at(&tokens[1], "i supports := or +=")),
+        }
+    }
+
+    fn parse_timer_assign(tokens: &[Token]) -> Result<Instr, ParseError<'_>> {
+        let [Token::Ident(timer), Token::Sym(":="), src] = tokens else {
+            return Err("timer syntax is: delay/buzzer := vX".into());
+        };
+        let reg = parse_reg(src)?;
+        Ok(if timer == "delay" {
+            Instr::SetDelay(reg)
+        } else {
+            Instr::SetSound(reg)
+        })
+    }
+
+    fn parse_reg_instr(tokens: &[Token]) -> Result<Instr, ParseError<'_>> {
+        if let [dst, Token::Sym(":="), Token::Ident(keyword), mask] = tokens
+            && keyword == "random"
+        {
+            return Ok(Instr::Rand(parse_reg(dst)?, parse_expr(mask)?));
+        }
+        if let [dst, Token::Sym(":="), Token::Ident(keyword)] = tokens
+            && keyword == "delay"
+        {
+            return Ok(Instr::GetDelay(parse_reg(dst)?));
+        }
+        if tokens.len() != 3 {
+            return Err("register instruction must be 3 tokens".into());
+        }
+        let dst = parse_reg(&tokens[0])?;
+        match &tokens[1] {
+            Token::Sym(":=") => {
+                let operand = if matches!(&tokens[2], Token::Ident(name) if is_reg(name)) {
+                    Operand::Reg(parse_reg(&tokens[2])?)
+                } else {
+                    Operand::Imm(parse_expr(&tokens[2])?)
+                };
+                Ok(Instr::Load(dst, operand))
+            }
+            Token::Sym("+=") => {
+                let operand = if matches!(&tokens[2], Token::Ident(name) if is_reg(name)) {
+                    Operand::Reg(parse_reg(&tokens[2])?)
+                } else {
+                    Operand::Imm(parse_expr(&tokens[2])?)
+                };
+                Ok(Instr::Add(dst, operand))
+            }
+            Token::Sym("-=") => {
+                let operand = if matches!(&tokens[2], Token::Ident(name) if is_reg(name)) {
+                    Operand::Reg(parse_reg(&tokens[2])?)
+                } else {
+                    Operand::Imm(parse_expr(&tokens[2])?)
+                };
+                Ok(Instr::Sub(dst, operand))
+            }
+            Token::Sym("|=") => Ok(Instr::Or(dst, parse_reg(&tokens[2])?)),
+            Token::Sym("&=") => Ok(Instr::And(dst, parse_reg(&tokens[2])?)),
+            Token::Sym("^=") => Ok(Instr::Xor(dst, parse_reg(&tokens[2])?)),
+            Token::Sym("=-") => Ok(Instr::SubRev(dst, parse_reg(&tokens[2])?)),
+            Token::Sym(">>=") => Ok(Instr::Shr(dst, parse_reg(&tokens[2])?)),
+            Token::Sym("<<=") => Ok(Instr::Shl(dst, parse_reg(&tokens[2])?)),
+            _ => Err(ParseError::at(&tokens[1], "unsupported register operator")),
+        }
+    }
+
+    fn parse_reg(token: &Token) -> Result<Reg, ParseError<'_>> {
+        match token {
+            Token::Ident(name) if is_reg(name) => {
+                let digit = name.chars().nth(1).unwrap();
+                let value = digit
+                    .to_digit(16)
+                    .ok_or_else(|| ParseError::at(token, "invalid register"))? as u8;
+                Ok(Reg(value))
+            }
+            _ => Err(ParseError::at(token, "expected register like v0..vF")),
+        }
+    }
+
+    fn is_reg(name: &str) -> bool {
+        if name.len() != 2 {
+            return false;
+        }
+        let mut chars = name.chars();
+        let v = chars.next().unwrap();
+        let digit = chars.next().unwrap();
+        v == 'v' && matches!(digit, '0'..='9' | 'A'..='F')
+    }
+
+    fn stmt_size(stmt: &Stmt) -> Result<u16, String> {
+        match stmt {
+            Stmt::Byte(values) => Ok(values.len() as u16),
+            Stmt::Word(values) => Ok(values.len() as u16 * 2),
+            Stmt::Zero(count) => Ok(*count),
+            Stmt::Ascii(bytes) | Stmt::IncBin(bytes) => Ok(bytes.len() as u16),
+            Stmt::Sys(_) => Ok(2),
+            Stmt::Instr(instr) => Ok(match instr {
+                Instr::If { .. } | Instr::IfKey { .. } => 4,
+                _ => 2,
+            }),
+        }
+    }
+
+    fn expr_name(expr: &Expr) -> String {
+        match expr {
+            Expr::Num(val) => format!("{val:#06x}"),
+            Expr::Label(name) => format!("'{name}'"),
+            Expr::Offset(name, delta) => format!("'{name}{delta:+}'"),
+        }
+    }
+
+    fn checked_add(base: u16, size: u16, line_no: usize) -> Result<u16, String> {
+        base.checked_add(size)
+            .ok_or_else(|| format!("line {line_no}: address overflow"))
+    }
+
+    // fill the X and Y nibbles of an `8XYN`-shaped opcode.
+    fn reg_pair(op: u16, x: &Reg, y: &Reg) -> u16 {
+        op | ((x.0 as u16) << 8) | ((y.0 as u16) << 4)
+    }
+
+    fn word(op: u16) -> Vec<u8> {
+        vec![(op >> 8) as u8, (op & 0xFF) as u8]
+    }
+}
@@ -7,23 +7,29 @@ pub mod display {
     pub use chip8_core::device::device::DisplayMode;
     use crate::proc::proc::Registers;
 
+    // default palette: lit pixels / glyphs in WHITE on a BLACK background.
     const WHITE: u32 = 0xFFFFFF;
     const BLACK: u32 = 0x000000;
+    // debug overlay tints: pixels lit / cleared since the last present.
+    const OVERLAY_LIT: u32 = 0x00FF00;
+    const OVERLAY_CLEARED: u32 = 0xFF0000;
 
     const CHIP8_WIDTH: usize = 64;
     const CHIP8_HEIGHT: usize = 32;
+    const HIRES_WIDTH: usize = 128;
+    const HIRES_HEIGHT: usize = 64;
     const CONSOLE_WIDTH: usize = 640;
     const CONSOLE_HEIGHT: usize = 320;
+    // default window scale: window pixels per side of one console pixel.
     pub const SCALE: usize = 2;
+    #[allow(dead_code)]
     pub const CHIP8_PIXEL_SCALE: usize = CONSOLE_WIDTH / CHIP8_WIDTH;
 
-    const WINDOW_WIDTH: usize = CONSOLE_WIDTH * SCALE;
-    const WINDOW_HEIGHT: usize = CONSOLE_HEIGHT * SCALE;
-
     const CELL_W: usize = 8;
     const CELL_H: usize = 8;
     const TEXT_COLS: usize = CONSOLE_WIDTH / CELL_W;
     const TEXT_ROWS: usize = CONSOLE_HEIGHT / CELL_H;
+    pub const DEFAULT_TAB_WIDTH: usize = 8;
 
     #[derive(Clone)]
     struct Console {
@@ -31,6 +37,7 @@ pub mod display {
         rows: usize,
         cursor_x: usize,
         cursor_y: usize,
+        tab_width: usize,
         cells: Vec<u8>,
     }
 
@@ -41,6 +48,7 @@ pub mod display {
                 rows,
                 cursor_x: 0,
                 cursor_y: 0,
+                tab_width: DEFAULT_TAB_WIDTH,
                 cells: vec![b' '; cols * rows],
             }
         }
@@ -75,6 +83,17 @@ pub mod display {
                 return;
             }
 
+            if ch == b'\t' {
+                self.tab();
+                return;
+            }
+
+            // remaining control bytes have no glyph; drop them instead of
+            // rendering the fallback box so stray codes don't shift columns.
+            if ch < 0x20 || ch == 0x7F {
+                return;
+            }
+
             let idx = self.index(self.cursor_x, self.cursor_y);
             self.cells[idx] = ch;
             self.advance();
@@ -94,6 +113,16 @@ pub mod display {
             self.cells[idx] = b' ';
         }
 
+        // move to the next tab stop; a stop past the last column wraps.
+        fn tab(&mut self) {
+            let next = (self.cursor_x / self.tab_width + 1) * self.tab_width;
+            if next >= self.cols {
+                self.put_char(b'\n');
+            } else {
+                self.cursor_x = next;
+            }
+        }
+
         fn advance(&mut self) {
             self.cursor_x += 1;
             if self.cursor_x >= self.cols {
@@ -122,6 +151,21 @@ pub mod display {
         }
     }
 
+    /// construction-time look of a display: window scale and palette.
+    /// `Default` is the classic 2x white-on-black window.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct DisplayConfig {
+        pub scale: usize,
+        pub fg: u32,
+        pub bg: u32,
+    }
+
+    impl Default for DisplayConfig {
+        fn default() -> Self {
+            DisplayConfig { scale: SCALE, fg: WHITE, bg: BLACK }
+        }
+    }
+
     pub struct DisplayWindow {
         pub window: Option<Window>,
         pub buf: Vec<u32>,
@@ -131,55 +175,83 @@ pub mod display {
         text_input: VecDeque<u8>,
         console: Console,
         mode: DisplayMode,
+        // SUPER-CHIP 128x64; sprite pixels are then half as large.
+        hires: bool,
+        // sound timer tone; minifb has no audio, so this is state only.
+        buzzer: bool,
+        // lit state per logical (unscaled) pixel; colors come from fg/bg so
+        // collision detection does not depend on the palette.
+        pixels: Vec<bool>,
+        // window pixels per console pixel; `buf` is CONSOLE_WIDTH * scale wide.
+        scale: usize,
+        fg: u32,
+        bg: u32,
+        // debug overlay: `prev_pixels` is the grid as of the last `present`.
+        debug_overlay: bool,
+        prev_pixels: Vec<bool>,
+        // `buf` has changes the window has not shown yet (see `present`).
+        dirty: bool,
     }
 
     impl DisplayWindow {
-        // buffer is pre-scaled (WINDOW_WIDTH x WINDOW_HEIGHT).
-        pub fn new() -> Result<DisplayWindow, Error> {
+        // buffer is pre-scaled (CONSOLE_WIDTH * scale x CONSOLE_HEIGHT * scale).
+        pub fn new(config: DisplayConfig) -> Result<DisplayWindow, Error> {
+            let mut display = DisplayWindow::headless_with_config(config);
+            let (width, height) = display.window_size();
             let mut window = Window::new(
                 "Chip8 Process",
-                WINDOW_WIDTH,
-                WINDOW_HEIGHT,
+                width,
+                height,
                 WindowOptions::default()
             ).unwrap();
 
-            let buf: Vec<u32> = vec![0; WINDOW_WIDTH * WINDOW_HEIGHT];
             window
-                .update_with_buffer(&buf, WINDOW_WIDTH, WINDOW_HEIGHT)
+                .update_with_buffer(&display.buf, width, height)
                 .unwrap();
-            
-            Ok(DisplayWindow {
-                window: Some(window),
-                buf: buf,
-                key_state: 0xFF,
-                key_down: [false; 16],
-                last_key: None,
-                text_input: VecDeque::new(),
-                console: Console::new(TEXT_COLS, TEXT_ROWS),
-                mode: DisplayMode::Chip8,
-            })
+            display.window = Some(window);
+            Ok(display)
         }
 
         // headless display for tests or non-GUI runs.
         pub fn headless() -> DisplayWindow {
+            DisplayWindow::headless_with_config(DisplayConfig::default())
+        }
+
+        pub fn headless_with_config(config: DisplayConfig) -> DisplayWindow {
+            let scale = config.scale.max(1);
+            let buf_len = CONSOLE_WIDTH * scale * CONSOLE_HEIGHT * scale;
             DisplayWindow {
                 window: None,
-                buf: vec![0; WINDOW_WIDTH * WINDOW_HEIGHT],
+                buf: vec![0; buf_len],
                 key_state: 0xFF,
                 key_down: [false; 16],
                 last_key: None,
                 text_input: VecDeque::new(),
                 console: Console::new(TEXT_COLS, TEXT_ROWS),
                 mode: DisplayMode::Chip8,
+                hires: false,
+                buzzer: false,
+                pixels: vec![false; CONSOLE_WIDTH * CONSOLE_HEIGHT],
+                scale,
+                fg: config.fg & 0xFFFFFF,
+                bg: config.bg & 0xFFFFFF,
+                debug_overlay: false,
+                prev_pixels: vec![false; CONSOLE_WIDTH * CONSOLE_HEIGHT],
+                dirty: false,
             }
         }
 
+        /// size of the scaled buffer (and of the window, when there is one).
+        pub fn window_size(&self) -> (usize, usize) {
+            (CONSOLE_WIDTH * self.scale, CONSOLE_HEIGHT * self.scale)
+        }
+
         // build a display based on CHIP8_HEADLESS env var.
         pub fn from_env() -> Result<DisplayWindow, Error> {
             if std::env::var("CHIP8_HEADLESS").is_ok() {
                 Ok(DisplayWindow::headless())
             } else {
-                DisplayWindow::new()
+                DisplayWindow::new(DisplayConfig::default())
             }
         }
 
@@ -190,10 +262,8 @@ pub mod display {
                     self.render_console();
                 }
                 DisplayMode::Chip8 => {
-                    self.buf.iter_mut().for_each(|x| *x = 0);
-                    if let Some(window) = self.window.as_mut() {
-                        let _ = window.update_with_buffer(&self.buf, WINDOW_WIDTH, WINDOW_HEIGHT);
-                    }
+                    self.pixels.fill(false);
+                    self.render_pixels();
                 }
             }
         }
@@ -210,14 +280,65 @@ pub mod display {
                     self.render_console();
                 }
                 DisplayMode::Chip8 => {
-                    self.buf.iter_mut().for_each(|x| *x = 0);
-                    if let Some(window) = self.window.as_mut() {
-                        let _ = window.update_with_buffer(&self.buf, WINDOW_WIDTH, WINDOW_HEIGHT);
-                    }
+                    self.pixels.fill(false);
+                    self.render_pixels();
                 }
             }
         }
 
+        /// switch the sprite grid between 64x32 and 128x64 and clear it.
+        pub fn set_hires(&mut self, on: bool) {
+            self.hires = on;
+            if self.mode == DisplayMode::Chip8 {
+                self.clear_screen();
+            }
+        }
+
+        #[allow(dead_code)]
+        /// true while the 128x64 SUPER-CHIP grid is active.
+        pub fn hires(&self) -> bool {
+            self.hires
+        }
+
+        /// record whether the sound timer tone is playing. There is no audio
+        /// backend yet, so this only tracks state (see `buzzer`).
+        pub fn set_buzzer(&mut self, on: bool) {
+            self.buzzer = on;
+        }
+
+        #[allow(dead_code)]
+        /// true while the sound timer is running.
+        pub fn buzzer(&self) -> bool {
+            self.buzzer
+        }
+
+        /// active sprite resolution as (width, height) in display pixels.
+        pub fn resolution(&self) -> (usize, usize) {
+            if self.hires {
+                (HIRES_WIDTH, HIRES_HEIGHT)
+            } else {
+                (CHIP8_WIDTH, CHIP8_HEIGHT)
+            }
+        }
+
+        /// lit state of each display pixel at the active resolution,
+        /// row-major. Independent of the window scale and of how many console
+        /// pixels a display pixel covers, so tests can assert on the CHIP-8
+        /// grid.
+        pub fn logical_pixels(&self) -> Vec<bool> {
+            let (width, height) = self.resolution();
+            let scale = self.pixel_scale();
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| self.pixels[y * scale * CONSOLE_WIDTH + x * scale])
+                .collect()
+        }
+
+        // logical (console) pixels per side of one display pixel.
+        fn pixel_scale(&self) -> usize {
+            CONSOLE_WIDTH / self.resolution().0
+        }
+
         // poll_input captures the current pressed state of all 16 CHIP-8 keys.
         // example - if keys 0x3 and 0xA are both down, key_down[0x3] and
         // key_down[0xA] are true, and last_key becomes the first one found in the scan order.
@@ -283,6 +404,60 @@ pub mod display {
             self.render_console();
         }
 
+        /// set foreground/background colors (0xRRGGBB) and repaint.
+        pub fn set_palette(&mut self, fg: u32, bg: u32) {
+            self.fg = fg & 0xFFFFFF;
+            self.bg = bg & 0xFFFFFF;
+            match self.mode {
+                DisplayMode::Console => self.render_console(),
+                DisplayMode::Chip8 => self.render_pixels(),
+            }
+        }
+
+        #[allow(dead_code)]
+        /// current (foreground, background) colors.
+        pub fn palette(&self) -> (u32, u32) {
+            (self.fg, self.bg)
+        }
+
+        #[allow(dead_code)]
+        /// set the console tab-stop interval in columns (clamped to >= 1).
+        pub fn set_tab_width(&mut self, width: usize) {
+            self.console.tab_width = width.max(1);
+        }
+
+        #[allow(dead_code)]
+        /// read the character stored at a console grid cell.
+        pub fn console_cell(&self, col: usize, row: usize) -> Option<u8> {
+            if col >= self.console.cols || row >= self.console.rows {
+                return None;
+            }
+            Some(self.console.cells[self.console.index(col, row)])
+        }
+
+        #[allow(dead_code)]
+        /// current console cursor as (column, row).
+        pub fn console_cursor(&self) -> (usize, usize) {
+            (self.console.cursor_x, self.console.cursor_y)
+        }
+
+        #[allow(dead_code)]
+        /// console grid size as (columns, rows).
+        pub fn console_dimensions(&self) -> (usize, usize) {
+            (self.console.cols, self.console.rows)
+        }
+
+        /// move the console cursor to (column, row); false when the cell is
+        /// outside `console_dimensions`, leaving the cursor where it was.
+        pub fn console_goto(&mut self, col: usize, row: usize) -> bool {
+            if col >= self.console.cols || row >= self.console.rows {
+                return false;
+            }
+            self.console.cursor_x = col;
+            self.console.cursor_y = row;
+            true
+        }
+
         pub fn console_backspace(&mut self) {
             if self.mode != DisplayMode::Console {
                 return;
@@ -291,59 +466,149 @@ pub mod display {
             self.render_console();
         }
 
-        // draw_sprite XORs sprite bits and sets VF on collision.
+        // draw_sprite XORs sprite bits and sets VF on collision. In hi-res, a
+        // 32-byte sprite is drawn 16x16 (Dxy0).
         pub fn draw_sprite(&mut self, regs: &mut Registers, sprite: &[u8], x_pos: u32, y_pos: u32) {
             if self.mode == DisplayMode::Console {
                 return;
             }
 
             regs.V[0xF] = 0;
+            let (width, height) = self.resolution();
+            let scale = self.pixel_scale();
+            // a 32-byte sprite in hi-res is SUPER-CHIP 16x16: two bytes per row.
+            let row_bytes = if self.hires && sprite.len() == 32 { 2 } else { 1 };
+            let row_width = row_bytes * 8;
 
-            for (byte_index, byte) in sprite.iter().enumerate() {
-                let byte = *byte;
+            for (row_index, row) in sprite.chunks(row_bytes).enumerate() {
+                let bits = row.iter().fold(0u16, |acc, &byte| (acc << 8) | byte as u16);
 
-                for bit_index in 0..8 {
-                    let sprite_pixel = (byte >> (7 - bit_index)) & 1;
+                for bit_index in 0..row_width {
+                    let sprite_pixel = (bits >> (row_width - 1 - bit_index)) & 1;
                     if sprite_pixel == 0 {
                         continue;
                     }
 
-                    let chip_x = ((x_pos as usize) + bit_index) % CHIP8_WIDTH;
-                    let chip_y = ((y_pos as usize) + byte_index) % CHIP8_HEIGHT;
-                    let base_x = chip_x * CHIP8_PIXEL_SCALE;
-                    let base_y = chip_y * CHIP8_PIXEL_SCALE;
+                    let chip_x = ((x_pos as usize) + bit_index) % width;
+                    let chip_y = ((y_pos as usize) + row_index) % height;
+                    let base_x = chip_x * scale;
+                    let base_y = chip_y * scale;
 
-                    for dy in 0..CHIP8_PIXEL_SCALE {
-                        for dx in 0..CHIP8_PIXEL_SCALE {
+                    for dy in 0..scale {
+                        for dx in 0..scale {
                             self.toggle_pixel(regs, base_x + dx, base_y + dy);
                         }
                     }
                 }
             }
 
+            if self.debug_overlay {
+                self.render_pixels();
+            }
+            self.dirty = true;
+        }
+
+        /// blit `buf` to the window if anything was drawn since the last
+        /// present. Sprite and pixel drawing only touch the buffer, so a frame
+        /// of many sprites costs one window update. This is also the frame
+        /// boundary the debug overlay diffs against.
+        pub fn present(&mut self) {
+            if !self.dirty {
+                return;
+            }
+            self.dirty = false;
+            let (width, height) = self.window_size();
             if let Some(window) = self.window.as_mut() {
-                let _ = window.update_with_buffer(&self.buf, WINDOW_WIDTH, WINDOW_HEIGHT);
+                let _ = window.update_with_buffer(&self.buf, width, height);
+            }
+            if self.debug_overlay {
+                // start the next frame and repaint without this frame's tints.
+                self.prev_pixels.copy_from_slice(&self.pixels);
+                if self.mode == DisplayMode::Chip8 {
+                    self.render_pixels();
+                }
+            }
+        }
+
+        #[allow(dead_code)]
+        /// true when drawing has happened that `present` has not shown yet.
+        pub fn needs_present(&self) -> bool {
+            self.dirty
+        }
+
+        /// shift the sprite grid by (dx, dy) display pixels, clearing what
+        /// scrolls in. Sprite mode only.
+        pub fn scroll(&mut self, dx: isize, dy: isize) {
+            if self.mode == DisplayMode::Console {
+                return;
+            }
+            let scale = self.pixel_scale() as isize;
+            let (dx, dy) = (dx * scale, dy * scale);
+            let prev = self.pixels.clone();
+            for y in 0..CONSOLE_HEIGHT {
+                for x in 0..CONSOLE_WIDTH {
+                    let src_x = x as isize - dx;
+                    let src_y = y as isize - dy;
+                    let inside = (0..CONSOLE_WIDTH as isize).contains(&src_x)
+                        && (0..CONSOLE_HEIGHT as isize).contains(&src_y);
+                    self.pixels[y * CONSOLE_WIDTH + x] =
+                        inside && prev[src_y as usize * CONSOLE_WIDTH + src_x as usize];
+                }
             }
+            self.render_pixels();
+        }
+
+        #[allow(dead_code)]
+        /// tint pixels changed since the last `present` (lit: green, cleared:
+        /// red) so it is obvious what a ROM draws each frame. Sprite mode
+        /// only; turning it off repaints with the normal palette.
+        pub fn set_debug_overlay(&mut self, on: bool) {
+            self.debug_overlay = on;
+            self.prev_pixels.copy_from_slice(&self.pixels);
+            if self.mode == DisplayMode::Chip8 {
+                self.render_pixels();
+            }
+        }
+
+        #[allow(dead_code)]
+        /// display (x, y) pixels, at the active resolution, the overlay marks
+        /// as changed since the last `present`; empty while the overlay is off.
+        pub fn overlay_changes(&self) -> Vec<(usize, usize)> {
+            if !self.debug_overlay {
+                return Vec::new();
+            }
+            let (width, height) = self.resolution();
+            let scale = self.pixel_scale();
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| {
+                    let pos = y * scale * CONSOLE_WIDTH + x * scale;
+                    self.pixels[pos] != self.prev_pixels[pos]
+                })
+                .collect()
         }
 
         fn toggle_pixel(&mut self, regs: &mut Registers, logical_x: usize, logical_y: usize) {
-            let phys_x = logical_x * SCALE;
-            let phys_y = logical_y * SCALE;
-            let pos = phys_y * WINDOW_WIDTH + phys_x;
+            let scale = self.scale;
+            let window_width = self.window_size().0;
+            let phys_x = logical_x * scale;
+            let phys_y = logical_y * scale;
+            let pos = logical_y * CONSOLE_WIDTH + logical_x;
 
-            let current_pixel = if self.buf[pos] == WHITE { 1 } else { 0 };
-            let new_pixel = current_pixel ^ 1;
+            let current_pixel = self.pixels[pos];
+            let new_pixel = !current_pixel;
+            self.pixels[pos] = new_pixel;
 
-            if current_pixel == 1 && new_pixel == 0 {
+            if current_pixel && !new_pixel {
                 regs.V[0xF] = 1;
             }
 
-            let color = if new_pixel == 1 { WHITE } else { BLACK };
-            for dy in 0..SCALE {
-                for dx in 0..SCALE {
+            let color = if new_pixel { self.fg } else { self.bg };
+            for dy in 0..scale {
+                for dx in 0..scale {
                     let scaled_x = phys_x + dx;
                     let scaled_y = phys_y + dy;
-                    let idx = scaled_y * WINDOW_WIDTH + scaled_x;
+                    let idx = scaled_y * window_width + scaled_x;
                     self.buf[idx] = color;
                 }
             }
@@ -351,8 +616,31 @@ pub mod display {
     }
 
     impl DisplayWindow {
+        // repaint the scaled buffer from the logical pixel grid.
+        fn render_pixels(&mut self) {
+            let scale = self.scale;
+            let window_width = self.window_size().0;
+            for (pos, lit) in self.pixels.iter().enumerate() {
+                let changed = self.debug_overlay && *lit != self.prev_pixels[pos];
+                let color = match (*lit, changed) {
+                    (true, true) => OVERLAY_LIT,
+                    (false, true) => OVERLAY_CLEARED,
+                    (true, false) => self.fg,
+                    (false, false) => self.bg,
+                };
+                let phys_x = (pos % CONSOLE_WIDTH) * scale;
+                let phys_y = (pos / CONSOLE_WIDTH) * scale;
+                for dy in 0..scale {
+                    let row = (phys_y + dy) * window_width;
+                    self.buf[row + phys_x..row + phys_x + scale].fill(color);
+                }
+            }
+            self.dirty = true;
+        }
+
         fn render_console(&mut self) {
-            self.buf.iter_mut().for_each(|px| *px = BLACK);
+            let bg = self.bg;
+            self.buf.iter_mut().for_each(|px| *px = bg);
             for row in 0..self.console.rows {
                 for col in 0..self.console.cols {
                     let idx = self.console.index(col, row);
@@ -361,8 +649,9 @@ pub mod display {
                 }
             }
 
+            let (width, height) = self.window_size();
             if let Some(window) = self.window.as_mut() {
-                let _ = window.update_with_buffer(&self.buf, WINDOW_WIDTH, WINDOW_HEIGHT);
+                let _ = window.update_with_buffer(&self.buf, width, height);
                 let text_bytes = collect_text_input(window);
                 if !text_bytes.is_empty() {
                     self.text_input.extend(text_bytes);
@@ -372,20 +661,22 @@ pub mod display {
 
         fn draw_glyph(&mut self, col: usize, row: usize, ch: u8) {
             let glyph = glyph_for(ch);
+            let scale = self.scale;
+            let window_width = self.window_size().0;
             let base_x = col * CELL_W;
             let base_y = row * CELL_H;
 
             for (y, row_bits) in glyph.iter().enumerate() {
                 for x in 0..CELL_W {
                     let bit = (row_bits >> (7 - x)) & 1;
-                    let color = if bit == 1 { WHITE } else { BLACK };
+                    let color = if bit == 1 { self.fg } else { self.bg };
                     let px = base_x + x;
                     let py = base_y + y;
-                    for dy in 0..SCALE {
-                        for dx in 0..SCALE {
-                            let scaled_x = px * SCALE + dx;
-                            let scaled_y = py * SCALE + dy;
-                            let pos = scaled_y * WINDOW_WIDTH + scaled_x;
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let scaled_x = px * scale + dx;
+                            let scaled_y = py * scale + dy;
+                            let pos = scaled_y * window_width + scaled_x;
                             self.buf[pos] = color;
                         }
                     }
@@ -407,6 +698,34 @@ pub mod display {
             DisplayWindow::draw_sprite(self, regs, sprite, x_pos, y_pos);
         }
 
+        fn scroll_down(&mut self, rows: u8) {
+            DisplayWindow::scroll(self, 0, rows as isize);
+        }
+
+        fn scroll_right(&mut self) {
+            DisplayWindow::scroll(self, 4, 0);
+        }
+
+        fn scroll_left(&mut self) {
+            DisplayWindow::scroll(self, -4, 0);
+        }
+
+        fn set_hires(&mut self, on: bool) {
+            DisplayWindow::set_hires(self, on);
+        }
+
+        fn hires(&self) -> bool {
+            self.hires
+        }
+
+        fn logical_pixels(&self) -> Vec<bool> {
+            DisplayWindow::logical_pixels(self)
+        }
+
+        fn set_buzzer(&mut self, on: bool) {
+            DisplayWindow::set_buzzer(self, on);
+        }
+
         fn is_key_down(&self, key: u8) -> bool {
             self.key_down
                 .get(key as usize)
@@ -437,6 +756,14 @@ pub mod display {
         fn mode(&self) -> DisplayMode {
             self.mode
         }
+
+        fn set_palette(&mut self, fg: u32, bg: u32) {
+            DisplayWindow::set_palette(self, fg, bg);
+        }
+
+        fn present(&mut self) {
+            DisplayWindow::present(self);
+        }
     }
 
     fn glyph_for(ch: u8) -> [u8; 8] {
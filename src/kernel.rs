@@ -1,14 +1,16 @@
 pub mod kernel {
     use std::collections::{HashMap, VecDeque};
     use std::fs;
-    use std::io::{self, Error, ErrorKind, Read, Write};
+    use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
     use std::path::{Component, Path, PathBuf};
     use std::sync::{Arc, Mutex};
     use std::thread;
-    use std::time::{Duration, Instant};
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use rand::Rng;
 
     use crate::display::display::{DisplayMode, DisplayWindow};
-    use crate::proc::proc::{ConsoleMode, InputMode, Proc};
+    use crate::proc::proc::{ConsoleMode, InputMode, Proc, PROT_READ_ONLY};
     use crate::shared_memory::shared_memory::SharedMemory;
 
     pub use chip8_core::syscall::syscall::SyscallOutcome;
@@ -17,14 +19,44 @@ pub mod kernel {
     const SYS_EXIT: u16 = 0x0102;
     const SYS_WAIT: u16 = 0x0103;
     const SYS_YIELD: u16 = 0x0104;
+    const SYS_SLEEP: u16 = 0x0105;
+    const SYS_GETPPID: u16 = 0x0106;
+    const SYS_EXEC: u16 = 0x0107;
+    const SYS_PS: u16 = 0x0108;
+    const SYS_FORK: u16 = 0x0109;
+    const SYS_SBRK: u16 = 0x010A;
     const SYS_WRITE: u16 = 0x0110;
     const SYS_READ: u16 = 0x0111;
     const SYS_INPUT_MODE: u16 = 0x0112;
     const SYS_CONSOLE_MODE: u16 = 0x0113;
+    const SYS_READLINE_INTO: u16 = 0x0114;
+    const SYS_CONSOLE_GOTO: u16 = 0x011F;
     const SYS_FS_LIST: u16 = 0x0120;
     const SYS_FS_OPEN: u16 = 0x0121;
     const SYS_FS_READ: u16 = 0x0122;
     const SYS_FS_CLOSE: u16 = 0x0123;
+    const SYS_FS_WRITE: u16 = 0x0124;
+    const SYS_FS_CREATE: u16 = 0x0125;
+    const SYS_FS_DELETE: u16 = 0x0126;
+    const SYS_FS_MKDIR: u16 = 0x0127;
+    const SYS_FS_SEEK: u16 = 0x0128;
+    const SYS_FS_STAT: u16 = 0x0129;
+    const SYS_FS_PREAD: u16 = 0x012A;
+    const SYS_CHDIR: u16 = 0x012B;
+    const SYS_GETCWD: u16 = 0x012C;
+    const SYS_FS_OPENDIR: u16 = 0x0136;
+    const SYS_FS_READDIR: u16 = 0x0137;
+    const SYS_FS_CLOSEDIR: u16 = 0x0138;
+    const SYS_FS_CLOSEALL: u16 = 0x0139;
+    const SYS_FS_COUNT: u16 = 0x013A;
+    const SYS_MPROTECT: u16 = 0x015C;
+    const SYS_DEBUG_PRINT: u16 = 0x015D;
+    const SYS_RANDOM: u16 = 0x015E;
+    const SYS_KILLPG: u16 = 0x0167;
+    const SYS_SPAWN_PIPE: u16 = 0x016A;
+    const SYS_PIPE: u16 = 0x016B;
+    const SYS_DUP: u16 = 0x016C;
+    const SYS_SET_PALETTE: u16 = 0x0173;
 
     const ERR_INVALID: u8 = 0x02;
     const ERR_IO: u8 = 0x03;
@@ -34,12 +66,49 @@ pub mod kernel {
     const ERR_NAME_TOO_LONG: u8 = 0x07;
     const ERR_TOO_MANY_OPEN: u8 = 0x08;
     const ERR_PATH: u8 = 0x09;
+    const ERR_PERMISSION: u8 = 0x0D;
+    const ERR_TRUNCATED: u8 = 0x0E;
+
+    // exit code recorded for a proc stopped by a fault.
+    const FAULT_EXIT_CODE: u16 = 0xFF;
+    // exit code recorded for a proc terminated by another proc.
+    const KILLED_EXIT_CODE: u16 = 0xFE;
+
+    // spawn flag: start the child in a new process group led by itself.
+    const SPAWN_NEW_GROUP: u16 = 0x0001;
+    // spawn flag: run a thread sharing the caller's pages instead of a ROM.
+    const SPAWN_THREAD: u16 = 0x0002;
+    // stack bytes left to the spawner above a thread's default stack top.
+    const THREAD_STACK_GAP: u32 = 0x40;
+    // longest argument block spawn copies into a child; V0 carries its length.
+    const MAX_SPAWN_ARGS: u16 = 0xFF;
+
+    // readline_into flag: drop the rest of an over-long line through its newline.
+    const READLINE_DISCARD: u16 = 0x0001;
+    // fs_open flag: open for writing, truncating the file.
+    const FS_OPEN_WRITE: u16 = 0x0001;
+    // fs_open flag: open for writing at the end, keeping the contents.
+    const FS_OPEN_APPEND: u16 = 0x0002;
+    // fs_seek whence values.
+    const SEEK_START: u16 = 0;
+    const SEEK_CURRENT: u16 = 1;
+    const SEEK_END: u16 = 2;
 
     const MAX_FILENAME_LEN: usize = 64;
     const MAX_DIR_ENTRIES: usize = 256;
     const MAX_FILE_SIZE: u64 = 64 * 1024;
-    const MAX_OPEN_FILES: usize = 32;
-    const DIR_ENTRY_SIZE: usize = 1 + MAX_FILENAME_LEN + 1 + 4;
+    // default cap on a proc's open fds (see `set_max_open_files`).
+    const DEFAULT_MAX_OPEN_FILES: usize = 32;
+    // each open directory pins a host directory handle, so keep this small.
+    const MAX_OPEN_DIRS: usize = 8;
+    const DIR_ENTRY_SIZE: usize = 1 + MAX_FILENAME_LEN + 1 + 4 + 8;
+    // ps record: pid (u16 BE), state, exit code (u16 BE).
+    const PS_ENTRY_SIZE: usize = 5;
+    // default scheduling slice, in instructions.
+    const DEFAULT_INSTRUCTIONS_PER_SLICE: u32 = 10_000;
+
+    /// receives one formatted kernel log line (no trailing newline).
+    pub type LogSink = Box<dyn FnMut(&str) + Send>;
 
     pub type SyscallHandler =
         Arc<dyn Fn(&mut Kernel, u32, &mut Proc) -> SyscallOutcome + Send + Sync>;
@@ -85,19 +154,62 @@ pub mod kernel {
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     enum WaitTarget {
         Pid(u32),
+        // wait(0): the first of the waiter's children to exit.
+        AnyChild,
         Read { buf: u16, len: u16, mode: InputMode },
+        ReadLine { buf: u16, max: u16, flags: u16 },
+        PipeRead { pipe: u32, buf: u16, len: u16 },
+        // asleep until the kernel clock reaches `wake_at` ticks.
+        Timer { wake_at: u64 },
     }
 
     struct ProcEntry {
         proc: Proc,
         state: ProcState,
-        exit_code: Option<u8>,
+        exit_code: Option<u16>,
         waiting_for: Option<WaitTarget>,
+        // last buzzer state sent to the display.
+        buzzing: bool,
+    }
+
+    impl ProcEntry {
+        // switch the display's buzzer when the sound timer starts or stops.
+        fn sync_buzzer(&mut self) {
+            let on = self.proc.regs.ST > 0;
+            if on != self.buzzing {
+                self.buzzing = on;
+                self.proc.display.set_buzzer(on);
+            }
+        }
+    }
+
+    /// what an fd refers to: a host file or one end of a kernel pipe.
+    enum FdEntry {
+        File { file: fs::File, writable: bool },
+        PipeRead(u32),
+        PipeWrite(u32),
+    }
+
+    /// in-kernel byte channel. `readers`/`writers` count open ends, so a
+    /// drained pipe whose writers are all closed reads as end-of-file.
+    struct PipeBuffer {
+        data: VecDeque<u8>,
+        readers: usize,
+        writers: usize,
+    }
+
+    /// pipes standing in for a proc's console in ConsoleMode::Pipe.
+    #[derive(Copy, Clone)]
+    struct ConsolePipes {
+        stdin: u32,
+        stdout: u32,
     }
 
     struct FdTable {
-        fds: HashMap<u8, fs::File>,
+        fds: HashMap<u8, FdEntry>,
         next_fd: u8,
+        dirs: HashMap<u8, fs::ReadDir>,
+        next_dir: u8,
     }
 
     pub struct Kernel {
@@ -105,12 +217,42 @@ pub mod kernel {
         syscalls: SyscallTable,
         procs: HashMap<u32, ProcEntry>,
         fd_tables: HashMap<u32, FdTable>,
+        // kept beside `procs` so a syscall can read its caller's group.
+        pgids: HashMap<u32, u32>,
+        // spawner of each proc another proc spawned; host-spawned procs have
+        // no entry. Kept beside `procs` for the same reason as `pgids`.
+        parents: HashMap<u32, u32>,
+        // working directory of each proc that has changed it, relative to
+        // `root_dir`; no entry means the root.
+        cwds: HashMap<u32, PathBuf>,
+        // owner of the pages each thread aliases, by thread pid. Kept beside
+        // `procs` so a thread spawning a thread can find the real owner.
+        page_owners: HashMap<u32, u32>,
+        // page tables of released owners whose threads still run; unmapped
+        // when the last thread goes.
+        orphaned_pages: HashMap<u32, Vec<u32>>,
+        pipes: HashMap<u32, PipeBuffer>,
+        next_pipe: u32,
+        console_pipes: HashMap<u32, ConsolePipes>,
         next_pid: u32,
         root_dir: PathBuf,
         input: VecDeque<u8>,
-        pending_exit: HashMap<u32, u8>,
+        pending_exit: HashMap<u32, u16>,
+        // program images to load once the exec syscall has returned.
+        pending_exec: HashMap<u32, Vec<u8>>,
         pending_block: HashMap<u32, WaitTarget>,
         last_timer_tick: Instant,
+        // simulated time not yet turned into ticks (see `advance_clock`).
+        clock_skew: Duration,
+        // 60Hz ticks counted since boot; sleep deadlines are measured in it.
+        clock_ticks: u64,
+        // steps a proc may run before the scheduler moves on.
+        max_instructions_per_slice: u32,
+        // open fds (files and pipe ends) each proc may hold.
+        max_open_files: usize,
+        // mark program pages read-only when a ROM is loaded or exec'd.
+        protect_code: bool,
+        log_sink: Option<LogSink>,
     }
 
     impl Kernel {
@@ -125,12 +267,27 @@ pub mod kernel {
                 syscalls: SyscallTable::new(),
                 procs: HashMap::new(),
                 fd_tables: HashMap::new(),
+                pgids: HashMap::new(),
+                parents: HashMap::new(),
+                cwds: HashMap::new(),
+                page_owners: HashMap::new(),
+                orphaned_pages: HashMap::new(),
+                pipes: HashMap::new(),
+                next_pipe: 1,
+                console_pipes: HashMap::new(),
                 next_pid: 1,
                 root_dir: root,
                 input: VecDeque::new(),
                 pending_exit: HashMap::new(),
+                pending_exec: HashMap::new(),
                 pending_block: HashMap::new(),
                 last_timer_tick: Instant::now(),
+                clock_skew: Duration::ZERO,
+                clock_ticks: 0,
+                max_instructions_per_slice: DEFAULT_INSTRUCTIONS_PER_SLICE,
+                max_open_files: DEFAULT_MAX_OPEN_FILES,
+                protect_code: false,
+                log_sink: None,
             })
         }
 
@@ -140,14 +297,44 @@ pub mod kernel {
             self.register_syscall(SYS_EXIT, sys_exit)?;
             self.register_syscall(SYS_WAIT, sys_wait)?;
             self.register_syscall(SYS_YIELD, sys_yield)?;
+            self.register_syscall(SYS_SLEEP, sys_sleep)?;
+            self.register_syscall(SYS_GETPPID, sys_getppid)?;
+            self.register_syscall(SYS_EXEC, sys_exec)?;
+            self.register_syscall(SYS_PS, sys_ps)?;
+            self.register_syscall(SYS_FORK, sys_fork)?;
+            self.register_syscall(SYS_SBRK, sys_sbrk)?;
             self.register_syscall(SYS_WRITE, sys_write)?;
             self.register_syscall(SYS_READ, sys_read)?;
             self.register_syscall(SYS_INPUT_MODE, sys_input_mode)?;
             self.register_syscall(SYS_CONSOLE_MODE, sys_console_mode)?;
+            self.register_syscall(SYS_READLINE_INTO, sys_readline_into)?;
+            self.register_syscall(SYS_CONSOLE_GOTO, sys_console_goto)?;
             self.register_syscall(SYS_FS_LIST, sys_fs_list)?;
             self.register_syscall(SYS_FS_OPEN, sys_fs_open)?;
             self.register_syscall(SYS_FS_READ, sys_fs_read)?;
             self.register_syscall(SYS_FS_CLOSE, sys_fs_close)?;
+            self.register_syscall(SYS_FS_WRITE, sys_fs_write)?;
+            self.register_syscall(SYS_FS_CREATE, sys_fs_create)?;
+            self.register_syscall(SYS_FS_DELETE, sys_fs_delete)?;
+            self.register_syscall(SYS_FS_MKDIR, sys_fs_mkdir)?;
+            self.register_syscall(SYS_FS_SEEK, sys_fs_seek)?;
+            self.register_syscall(SYS_FS_STAT, sys_fs_stat)?;
+            self.register_syscall(SYS_FS_PREAD, sys_fs_pread)?;
+            self.register_syscall(SYS_CHDIR, sys_chdir)?;
+            self.register_syscall(SYS_GETCWD, sys_getcwd)?;
+            self.register_syscall(SYS_FS_OPENDIR, sys_fs_opendir)?;
+            self.register_syscall(SYS_FS_READDIR, sys_fs_readdir)?;
+            self.register_syscall(SYS_FS_CLOSEDIR, sys_fs_closedir)?;
+            self.register_syscall(SYS_FS_CLOSEALL, sys_fs_closeall)?;
+            self.register_syscall(SYS_FS_COUNT, sys_fs_count)?;
+            self.register_syscall(SYS_MPROTECT, sys_mprotect)?;
+            self.register_syscall(SYS_DEBUG_PRINT, sys_debug_print)?;
+            self.register_syscall(SYS_RANDOM, sys_random)?;
+            self.register_syscall(SYS_KILLPG, sys_killpg)?;
+            self.register_syscall(SYS_SPAWN_PIPE, sys_spawn_pipe)?;
+            self.register_syscall(SYS_PIPE, sys_pipe)?;
+            self.register_syscall(SYS_DUP, sys_dup)?;
+            self.register_syscall(SYS_SET_PALETTE, sys_set_palette)?;
             Ok(())
         }
 
@@ -159,17 +346,68 @@ pub mod kernel {
             self.syscalls.register(id, handler)
         }
 
-        /// create a new Proc bound to this kernel's shared memory.
+        /// create a new Proc bound to this kernel's shared memory. The proc
+        /// leads a new process group; `sys_spawn` moves children into the
+        /// spawner's group.
         pub fn spawn_proc(&mut self, display: DisplayWindow, pages: u16) -> Result<u32, Error> {
-            let pid = self.next_pid;
-            self.next_pid = self.next_pid.wrapping_add(1);
-
+            // build the proc first so a rejected spawn does not consume a pid.
             let proc = Proc::new_with_display_and_pages(
                 Arc::clone(&self.mem),
                 display,
                 pages,
             )?;
 
+            Ok(self.insert_proc(proc))
+        }
+
+        /// create a thread of `parent` (pid `parent_pid`) starting at
+        /// `entry`. The thread aliases the parent's pages rather than owning
+        /// any, joins the parent's process group and gets its own copy of
+        /// the parent's pipe fds.
+        pub fn spawn_thread(
+            &mut self,
+            parent_pid: u32,
+            parent: &Proc,
+            display: DisplayWindow,
+            entry: u16,
+            sp: u16,
+        ) -> u32 {
+            let proc = parent.new_thread(display, entry, sp);
+            let pid = self.insert_proc(proc);
+            let owner = self.page_owners.get(&parent_pid).copied().unwrap_or(parent_pid);
+            self.page_owners.insert(pid, owner);
+            self.parents.insert(pid, parent_pid);
+            self.inherit_cwd(parent_pid, pid);
+            self.share_pipe_fds(parent_pid, pid);
+            let pgid = self.pgids.get(&parent_pid).copied().unwrap_or(parent_pid);
+            self.pgids.insert(pid, pgid);
+            pid
+        }
+
+        /// create a child of `parent` (pid `parent_pid`) holding a copy of
+        /// its pages and registers (see `Proc::fork`). Like a thread it joins
+        /// the parent's process group, working directory and pipe fds.
+        pub fn fork_proc(
+            &mut self,
+            parent_pid: u32,
+            parent: &Proc,
+            display: DisplayWindow,
+        ) -> Result<u32, Error> {
+            let proc = parent.fork(display)?;
+            let pid = self.insert_proc(proc);
+            self.parents.insert(pid, parent_pid);
+            self.inherit_cwd(parent_pid, pid);
+            self.share_pipe_fds(parent_pid, pid);
+            let pgid = self.pgids.get(&parent_pid).copied().unwrap_or(parent_pid);
+            self.pgids.insert(pid, pgid);
+            Ok(pid)
+        }
+
+        // assign a pid and empty fd table to a built proc; it leads its own group.
+        fn insert_proc(&mut self, proc: Proc) -> u32 {
+            let pid = self.next_pid;
+            self.next_pid = self.next_pid.wrapping_add(1);
+
             self.procs.insert(
                 pid,
                 ProcEntry {
@@ -177,6 +415,7 @@ pub mod kernel {
                     state: ProcState::Running,
                     exit_code: None,
                     waiting_for: None,
+                    buzzing: false,
                 },
             );
             self.fd_tables.insert(
@@ -184,9 +423,12 @@ pub mod kernel {
                 FdTable {
                     fds: HashMap::new(),
                     next_fd: 1,
+                    dirs: HashMap::new(),
+                    next_dir: 1,
                 },
             );
-            Ok(pid)
+            self.pgids.insert(pid, pid);
+            pid
         }
 
         /// spawn and load a ROM in one step.
@@ -197,10 +439,71 @@ pub mod kernel {
             rom_path: &Path,
         ) -> Result<u32, Error> {
             let pid = self.spawn_proc(display, pages)?;
-            self.load_rom(pid, rom_path)?;
+            if let Err(err) = self.load_rom(pid, rom_path) {
+                // the proc never ran; return its pages. Pids are never reused.
+                self.release_proc(pid);
+                return Err(err);
+            }
             Ok(pid)
         }
 
+        /// drop a proc entry and its fd table, returning its pages to the pool.
+        fn release_proc(&mut self, pid: u32) {
+            self.close_fds(pid);
+            self.pgids.remove(&pid);
+            self.parents.remove(&pid);
+            self.cwds.remove(&pid);
+            if let Some(entry) = self.procs.remove(&pid) {
+                self.unmap_entry(pid, &entry);
+            }
+        }
+
+        // return a proc's pages to the allocator. Threads only alias their
+        // owner's pages, so an owner released while threads still run keeps
+        // its pages mapped until the last of those threads is released.
+        fn unmap_entry(&mut self, pid: u32, entry: &ProcEntry) {
+            let Some(owner) = self.page_owners.remove(&pid) else {
+                if self.page_owners.values().any(|&owner| owner == pid) {
+                    self.orphaned_pages.insert(pid, entry.proc.page_table.clone());
+                } else {
+                    self.unmap_pages(&entry.proc.page_table);
+                }
+                return;
+            };
+            if !self.page_owners.values().any(|&other| other == owner)
+                && let Some(pages) = self.orphaned_pages.remove(&owner)
+            {
+                self.unmap_pages(&pages);
+            }
+        }
+
+        /// true when `pid` is a thread, or owns pages a live thread aliases.
+        fn shares_pages(&self, pid: u32) -> bool {
+            self.page_owners.contains_key(&pid)
+                || self.page_owners.values().any(|&owner| owner == pid)
+        }
+
+        fn unmap_pages(&mut self, pages: &[u32]) {
+            let result = self.mem.lock().unwrap().munmap(pages);
+            if let Err(err) = result {
+                self.log(&format!("kernel: munmap failed: {err}"));
+            }
+        }
+
+        /// drop every exited proc from the table and free its pages. Exit
+        /// codes nobody waited for are lost.
+        pub fn reap_exited(&mut self) {
+            let exited: Vec<u32> = self
+                .procs
+                .iter()
+                .filter(|(_, entry)| entry.state == ProcState::Exited)
+                .map(|(&pid, _)| pid)
+                .collect();
+            for pid in exited {
+                self.release_proc(pid);
+            }
+        }
+
         /// spawn a ROM by name, resolved relative to the kernel root.
         pub fn spawn_proc_from_name(
             &mut self,
@@ -224,6 +527,12 @@ pub mod kernel {
             self.procs.get_mut(&pid).map(|entry| &mut entry.proc)
         }
 
+        #[allow(dead_code)]
+        /// read the process group of a pid.
+        pub fn pgid(&self, pid: u32) -> Option<u32> {
+            self.pgids.get(&pid).copied()
+        }
+
         #[allow(dead_code)]
         /// read the current scheduler state for a pid.
         pub fn proc_state(&self, pid: u32) -> Option<ProcState> {
@@ -243,13 +552,18 @@ pub mod kernel {
                 return Ok(SyscallOutcome::Completed);
             }
 
-            let ticks = self.timer_ticks();
+            let ticks = self.advance_timers();
             let outcome = entry
                 .proc
                 .step(ticks, |id, proc| self.dispatch_syscall(pid, proc, id));
+            entry.sync_buzzer();
+            if ticks > 0 {
+                entry.proc.display.present();
+            }
 
-            self.apply_pending(pid, &mut entry, outcome);
-            self.procs.insert(pid, entry);
+            if !self.apply_pending(pid, &mut entry, outcome) {
+                self.procs.insert(pid, entry);
+            }
             Ok(outcome)
         }
 
@@ -260,24 +574,18 @@ pub mod kernel {
                 .get_mut(&pid)
                 .ok_or_else(|| Error::new(ErrorKind::NotFound, "pid not found"))?;
             let rom_bytes = fs::read(rom_path)?;
-            entry.proc.load_program_bytes(&rom_bytes)
+            entry.proc.load_program_bytes(&rom_bytes)?;
+            if self.protect_code {
+                entry.proc.protect_program(rom_bytes.len());
+            }
+            Ok(())
         }
 
         /// run the cooperative scheduler until no runnable procs remain.
         pub fn run(&mut self) -> Result<(), Error> {
             loop {
                 self.poll_console_input();
-                let mut ran_any = false;
-                let pids: Vec<u32> = self.procs.keys().copied().collect();
-                for pid in pids {
-                    if !self.is_runnable(pid) {
-                        continue;
-                    }
-                    ran_any = true;
-                    self.run_proc_until_yield_or_block(pid)?;
-                }
-
-                if ran_any {
+                if self.run_round()? {
                     continue;
                 }
 
@@ -292,15 +600,73 @@ pub mod kernel {
                 }
 
                 if self.any_blocked() {
-                    thread::sleep(Duration::from_millis(1));
+                    thread::sleep(self.idle_sleep());
                     continue;
                 }
 
                 break;
             }
+            self.reap_exited();
             Ok(())
         }
 
+        /// give every runnable proc one slice: until it yields, blocks, exits
+        /// or uses up `max_instructions_per_slice`. Returns false when no
+        /// proc was runnable.
+        pub fn run_round(&mut self) -> Result<bool, Error> {
+            // wake sleepers even when nothing else is stepping the clock.
+            self.advance_timers();
+            let mut ran_any = false;
+            let pids: Vec<u32> = self.procs.keys().copied().collect();
+            for pid in pids {
+                if !self.is_runnable(pid) {
+                    continue;
+                }
+                ran_any = true;
+                self.run_proc_until_yield_or_block(pid)?;
+            }
+            Ok(ran_any)
+        }
+
+        #[allow(dead_code)]
+        /// cap how many instructions a proc runs per scheduling slice (at
+        /// least 1), so a tight loop with no syscalls cannot starve others.
+        pub fn set_max_instructions_per_slice(&mut self, max: u32) {
+            self.max_instructions_per_slice = max.max(1);
+        }
+
+        #[allow(dead_code)]
+        /// make every later ROM load and exec mark the program's bytes
+        /// read-only (see `Proc::protect_program`), so a ROM that overwrites
+        /// its own code faults. Off by default.
+        pub fn set_protect_code(&mut self, on: bool) {
+            self.protect_code = on;
+        }
+
+        #[allow(dead_code)]
+        /// cap how many fds each proc may hold open (1 to 255, the fd range);
+        /// opens past it fail with ERR_TOO_MANY_OPEN. Defaults to 32.
+        pub fn set_max_open_files(&mut self, max: usize) {
+            self.max_open_files = max.clamp(1, u8::MAX as usize);
+        }
+
+        #[allow(dead_code)]
+        /// route kernel log lines to `sink` instead of host stderr.
+        pub fn set_logger<F>(&mut self, sink: F)
+        where
+            F: FnMut(&str) + Send + 'static,
+        {
+            self.log_sink = Some(Box::new(sink));
+        }
+
+        /// emit one log line to the configured sink, or stderr by default.
+        fn log(&mut self, line: &str) {
+            match self.log_sink.as_mut() {
+                Some(sink) => sink(line),
+                None => eprintln!("{line}"),
+            }
+        }
+
         /// inject host input into the kernel and wake blocked readers.
         pub fn push_input(&mut self, data: &[u8]) {
             self.input.extend(data);
@@ -357,7 +723,8 @@ pub mod kernel {
         }
 
         fn run_proc_until_yield_or_block(&mut self, pid: u32) -> Result<(), Error> {
-            loop {
+            // an exhausted budget ends the slice as if the proc had yielded.
+            for _ in 0..self.max_instructions_per_slice {
                 let mut entry = self
                     .procs
                     .remove(&pid)
@@ -367,12 +734,18 @@ pub mod kernel {
                     return Ok(());
                 }
 
-                let ticks = self.timer_ticks();
+                let ticks = self.advance_timers();
                 let outcome = entry
                     .proc
                     .step(ticks, |id, proc| self.dispatch_syscall(pid, proc, id));
+                entry.sync_buzzer();
+                if ticks > 0 {
+                    entry.proc.display.present();
+                }
 
-                self.apply_pending(pid, &mut entry, outcome);
+                if self.apply_pending(pid, &mut entry, outcome) {
+                    break;
+                }
                 let should_break = matches!(outcome, SyscallOutcome::Blocked | SyscallOutcome::Yielded);
                 self.procs.insert(pid, entry);
                 if should_break {
@@ -390,33 +763,125 @@ pub mod kernel {
             Ok(handler(self, pid, proc))
         }
 
-        fn apply_pending(&mut self, pid: u32, entry: &mut ProcEntry, outcome: SyscallOutcome) {
-            if let Some(code) = self.pending_exit.remove(&pid) {
+        /// apply deferred syscall effects to the caller. Returns true when the
+        /// caller exited and a waiter already collected it, so the entry must
+        /// not be put back. A fault, or an exec whose image fails to load, is
+        /// treated as an exit with FAULT_EXIT_CODE.
+        fn apply_pending(&mut self, pid: u32, entry: &mut ProcEntry, outcome: SyscallOutcome) -> bool {
+            let exec_failed = self.pending_exec.remove(&pid).is_some_and(|program| {
+                let failed = entry.proc.exec(&program).is_err();
+                if !failed && self.protect_code {
+                    entry.proc.protect_program(program.len());
+                }
+                failed
+            });
+            let exit_code = match self.pending_exit.remove(&pid) {
+                Some(code) => Some(code),
+                None if outcome == SyscallOutcome::Faulted || exec_failed => Some(FAULT_EXIT_CODE),
+                None => None,
+            };
+            if let Some(code) = exit_code {
+                self.pending_block.remove(&pid);
                 entry.state = ProcState::Exited;
                 entry.exit_code = Some(code);
                 entry.waiting_for = None;
-                self.unblock_waiters(pid, code);
-                self.fd_tables.remove(&pid);
+                self.close_fds(pid);
+                let reaped = self.unblock_waiters(pid, code);
+                if reaped {
+                    self.pgids.remove(&pid);
+                    self.parents.remove(&pid);
+                    self.cwds.remove(&pid);
+                    self.unmap_entry(pid, entry);
+                }
+                return reaped;
             } else if let Some(wait) = self.pending_block.remove(&pid) {
                 entry.state = ProcState::Blocked;
                 entry.waiting_for = Some(wait);
             } else if outcome == SyscallOutcome::Blocked {
                 entry.state = ProcState::Blocked;
             }
+            false
         }
 
         fn timer_ticks(&mut self) -> u32 {
             let tick = Duration::from_micros(1_000_000 / 60);
-            let elapsed = self.last_timer_tick.elapsed();
+            let elapsed = self.last_timer_tick.elapsed() + self.clock_skew;
             if elapsed < tick {
                 return 0;
             }
 
             let ticks = (elapsed.as_nanos() / tick.as_nanos()) as u32;
-            self.last_timer_tick = self.last_timer_tick + (tick * ticks);
+            let consumed = tick * ticks;
+            let from_skew = consumed.min(self.clock_skew);
+            self.clock_skew -= from_skew;
+            self.last_timer_tick += consumed - from_skew;
+            ticks
+        }
+
+        /// take the 60Hz ticks elapsed since the last call and count down the
+        /// timers of every proc in the table, so procs that are blocked or
+        /// waiting their turn keep time too. A tick is also a display frame:
+        /// each of those procs presents what it drew. The caller has the
+        /// stepping proc out of the table, passes the returned ticks to its
+        /// `step` and presents it afterwards.
+        fn advance_timers(&mut self) -> u32 {
+            let ticks = self.timer_ticks();
+            if ticks > 0 {
+                for entry in self.procs.values_mut() {
+                    entry.proc.tick_timers(ticks);
+                    entry.sync_buzzer();
+                    entry.proc.display.present();
+                }
+                self.clock_ticks += ticks as u64;
+                self.wake_sleepers();
+            }
             ticks
         }
 
+        /// make runnable every sleeper whose deadline the clock has reached.
+        fn wake_sleepers(&mut self) {
+            for entry in self.procs.values_mut() {
+                if entry.state == ProcState::Blocked
+                    && let Some(WaitTarget::Timer { wake_at }) = entry.waiting_for
+                    && wake_at <= self.clock_ticks
+                {
+                    entry.state = ProcState::Running;
+                    entry.waiting_for = None;
+                }
+            }
+        }
+
+        /// how long run() may sleep while every proc is blocked: until the
+        /// nearest sleep deadline when only sleepers are blocked, else 1ms so
+        /// console input and other wakeups are picked up promptly.
+        fn idle_sleep(&self) -> Duration {
+            let tick = Duration::from_micros(1_000_000 / 60);
+            let mut nearest: Option<u64> = None;
+            for entry in self.procs.values() {
+                if entry.state != ProcState::Blocked {
+                    continue;
+                }
+                match entry.waiting_for {
+                    Some(WaitTarget::Timer { wake_at }) => {
+                        nearest = Some(nearest.map_or(wake_at, |at| at.min(wake_at)));
+                    }
+                    _ => return Duration::from_millis(1),
+                }
+            }
+            let Some(wake_at) = nearest else {
+                return Duration::from_millis(1);
+            };
+            let due = tick * wake_at.saturating_sub(self.clock_ticks) as u32;
+            due.saturating_sub(self.last_timer_tick.elapsed() + self.clock_skew)
+        }
+
+        #[allow(dead_code)]
+        /// pretend `elapsed` more wall-clock time has passed, so tests can
+        /// drive the 60Hz timers without sleeping.
+        pub fn advance_clock(&mut self, elapsed: Duration) {
+            self.clock_skew += elapsed;
+        }
+
         fn is_runnable(&self, pid: u32) -> bool {
             self.procs
                 .get(&pid)
@@ -432,7 +897,8 @@ pub mod kernel {
 
         fn any_blocked_on_read_line_host(&self) -> bool {
             self.procs.values().any(|entry| match entry.waiting_for {
-                Some(WaitTarget::Read { mode: InputMode::Line, .. }) => {
+                Some(WaitTarget::Read { mode: InputMode::Line, .. })
+                | Some(WaitTarget::ReadLine { .. }) => {
                     entry.proc.console_mode == ConsoleMode::Host
                 }
                 _ => false,
@@ -448,20 +914,284 @@ pub mod kernel {
             })
         }
 
-        fn unblock_waiters(&mut self, waited_pid: u32, code: u8) {
-            for entry in self.procs.values_mut() {
+        /// drop a proc's fd table and console pipes, closing any pipe ends
+        /// they held.
+        fn close_fds(&mut self, pid: u32) {
+            let mut ends = Vec::new();
+            if let Some(table) = self.fd_tables.remove(&pid) {
+                ends.extend(table.fds.into_values());
+            }
+            if let Some(console) = self.console_pipes.remove(&pid) {
+                ends.push(FdEntry::PipeRead(console.stdin));
+                ends.push(FdEntry::PipeWrite(console.stdout));
+            }
+            for end in ends {
+                self.close_pipe_end(&end);
+            }
+        }
+
+        /// close every fd and directory handle `pid` holds, keeping the table
+        /// (and any console pipes) in place.
+        fn clear_fds(&mut self, pid: u32) {
+            let Some(table) = self.fd_tables.get_mut(&pid) else {
+                return;
+            };
+            let ends: Vec<FdEntry> = table.fds.drain().map(|(_, entry)| entry).collect();
+            table.next_fd = 1;
+            table.dirs.clear();
+            table.next_dir = 1;
+            for end in ends {
+                self.close_pipe_end(&end);
+            }
+        }
+
+        /// open every pipe end `from` holds in `to`'s fd table under the same
+        /// fd numbers. Each copy is an extra reader or writer, so a pipe only
+        /// reaches end-of-file once both procs have closed their write ends.
+        fn share_pipe_fds(&mut self, from: u32, to: u32) {
+            let Some(table) = self.fd_tables.get(&from) else {
+                return;
+            };
+            let next_fd = table.next_fd;
+            let ends: Vec<(u8, u32, bool)> = table
+                .fds
+                .iter()
+                .filter_map(|(&fd, entry)| match entry {
+                    FdEntry::PipeRead(id) => Some((fd, *id, false)),
+                    FdEntry::PipeWrite(id) => Some((fd, *id, true)),
+                    FdEntry::File { .. } => None,
+                })
+                .collect();
+            let Some(target) = self.fd_tables.get_mut(&to) else {
+                return;
+            };
+            for (fd, id, write_end) in ends {
+                let Some(pipe) = self.pipes.get_mut(&id) else {
+                    continue;
+                };
+                if write_end {
+                    pipe.writers += 1;
+                    target.fds.insert(fd, FdEntry::PipeWrite(id));
+                } else {
+                    pipe.readers += 1;
+                    target.fds.insert(fd, FdEntry::PipeRead(id));
+                }
+            }
+            target.next_fd = next_fd;
+        }
+
+        /// start `to` in `from`'s working directory.
+        fn inherit_cwd(&mut self, from: u32, to: u32) {
+            if let Some(cwd) = self.cwds.get(&from).cloned() {
+                self.cwds.insert(to, cwd);
+            }
+        }
+
+        /// create a pipe with one reader and one writer open.
+        fn new_pipe(&mut self) -> u32 {
+            let id = self.next_pipe;
+            self.next_pipe = self.next_pipe.wrapping_add(1);
+            self.pipes.insert(
+                id,
+                PipeBuffer {
+                    data: VecDeque::new(),
+                    readers: 1,
+                    writers: 1,
+                },
+            );
+            id
+        }
+
+        /// release the pipe end behind `entry` (files are ignored). A pipe
+        /// with no ends left is freed; closing the last writer wakes readers
+        /// so they see end-of-file.
+        fn close_pipe_end(&mut self, entry: &FdEntry) {
+            let (id, write_end) = match entry {
+                FdEntry::File { .. } => return,
+                FdEntry::PipeRead(id) => (*id, false),
+                FdEntry::PipeWrite(id) => (*id, true),
+            };
+            let Some(pipe) = self.pipes.get_mut(&id) else {
+                return;
+            };
+            if write_end {
+                pipe.writers = pipe.writers.saturating_sub(1);
+            } else {
+                pipe.readers = pipe.readers.saturating_sub(1);
+            }
+            if pipe.readers == 0 && pipe.writers == 0 {
+                self.pipes.remove(&id);
+            }
+            if write_end {
+                self.unblock_pipe_readers();
+            }
+        }
+
+        /// bytes a read parked as `wait` may take from `pipe` now; None means
+        /// keep waiting. Once every writer is gone, line reads take the rest
+        /// of the pipe without a newline and an empty pipe reads as 0 bytes.
+        /// The bool is the readline_into truncation flag.
+        fn pipe_take(pipe: &mut PipeBuffer, wait: WaitTarget) -> Option<(Vec<u8>, bool)> {
+            let eof = pipe.writers == 0;
+            let newline_idx = Self::find_newline_in(&pipe.data);
+            match wait {
+                WaitTarget::Read { len, mode: InputMode::Line, .. } => {
+                    let end = match newline_idx {
+                        Some(idx) => idx + 1,
+                        None if eof => pipe.data.len(),
+                        None => return None,
+                    };
+                    let count = (len as usize).min(end);
+                    Some((Self::pop_input(&mut pipe.data, count), false))
+                }
+                WaitTarget::Read { len, mode: InputMode::Byte, .. } => {
+                    if pipe.data.is_empty() && !eof {
+                        return None;
+                    }
+                    let count = (len as usize).min(pipe.data.len());
+                    Some((Self::pop_input(&mut pipe.data, count), false))
+                }
+                WaitTarget::PipeRead { len, .. } => {
+                    if pipe.data.is_empty() && !eof {
+                        return None;
+                    }
+                    let count = (len as usize).min(0xFF).min(pipe.data.len());
+                    Some((Self::pop_input(&mut pipe.data, count), false))
+                }
+                WaitTarget::ReadLine { max, flags, .. } => match newline_idx {
+                    Some(idx) => Some(Self::take_line(&mut pipe.data, idx, max, flags)),
+                    None if eof && pipe.data.is_empty() => Some((Vec::new(), false)),
+                    None if eof => {
+                        let last = pipe.data.len() - 1;
+                        Some(Self::take_line(&mut pipe.data, last, max, flags))
+                    }
+                    None => None,
+                },
+                WaitTarget::Pid(_) | WaitTarget::AnyChild | WaitTarget::Timer { .. } => None,
+            }
+        }
+
+        /// copy bytes taken for a pipe read into the proc and set V0/VF.
+        fn finish_pipe_read(proc: &mut Proc, wait: WaitTarget, data: &[u8], truncated: bool) {
+            match wait {
+                WaitTarget::ReadLine { buf, flags, .. } => {
+                    Self::finish_line_into(proc, buf, data, truncated, flags);
+                }
+                WaitTarget::Read { buf, .. } | WaitTarget::PipeRead { buf, .. } => {
+                    if proc.write_bytes(buf as u32, data).is_err() {
+                        proc.regs.V[0] = ERR_INVALID;
+                        proc.regs.V[0xF] = 1;
+                    } else {
+                        proc.regs.V[0] = data.len().min(0xFF) as u8;
+                        proc.regs.V[0xF] = 0;
+                    }
+                }
+                WaitTarget::Pid(_) | WaitTarget::AnyChild | WaitTarget::Timer { .. } => {}
+            }
+        }
+
+        /// serve a read from a pipe now, or park the caller until the pipe
+        /// has data or loses its last writer. Console reads use the caller's
+        /// stdin pipe.
+        fn pipe_read(&mut self, pid: u32, proc: &mut Proc, wait: WaitTarget) -> SyscallOutcome {
+            let id = match wait {
+                WaitTarget::PipeRead { pipe, .. } => Some(pipe),
+                _ => self.console_pipes.get(&pid).map(|console| console.stdin),
+            };
+            let Some(pipe) = id.and_then(|id| self.pipes.get_mut(&id)) else {
+                return syscall_error(proc, ERR_IO);
+            };
+            match Self::pipe_take(pipe, wait) {
+                Some((data, truncated)) => {
+                    Self::finish_pipe_read(proc, wait, &data, truncated);
+                    SyscallOutcome::Completed
+                }
+                None => {
+                    self.pending_block.insert(pid, wait);
+                    SyscallOutcome::Blocked
+                }
+            }
+        }
+
+        /// wake procs parked on a pipe that can now satisfy them.
+        fn unblock_pipe_readers(&mut self) {
+            for (&pid, entry) in self.procs.iter_mut() {
+                if entry.state != ProcState::Blocked {
+                    continue;
+                }
+                let Some(wait) = entry.waiting_for else {
+                    continue;
+                };
+                let id = match wait {
+                    WaitTarget::PipeRead { pipe, .. } => pipe,
+                    WaitTarget::Read { .. } | WaitTarget::ReadLine { .. }
+                        if entry.proc.console_mode == ConsoleMode::Pipe =>
+                    {
+                        match self.console_pipes.get(&pid) {
+                            Some(console) => console.stdin,
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                let Some(pipe) = self.pipes.get_mut(&id) else {
+                    continue;
+                };
+                let Some((data, truncated)) = Self::pipe_take(pipe, wait) else {
+                    continue;
+                };
+                Self::finish_pipe_read(&mut entry.proc, wait, &data, truncated);
+                entry.state = ProcState::Running;
+                entry.waiting_for = None;
+            }
+        }
+
+        /// terminate another proc as if it exited with `code`. Returns false
+        /// when the pid is unknown or has already exited.
+        fn kill_proc(&mut self, pid: u32, code: u16) -> bool {
+            let Some(entry) = self.procs.get_mut(&pid) else {
+                return false;
+            };
+            if entry.state == ProcState::Exited {
+                return false;
+            }
+            entry.state = ProcState::Exited;
+            entry.exit_code = Some(code);
+            entry.waiting_for = None;
+            self.pending_block.remove(&pid);
+            self.close_fds(pid);
+            if self.unblock_waiters(pid, code) {
+                self.release_proc(pid);
+            }
+            true
+        }
+
+        /// wake procs waiting on `waited_pid`, and its parent if that waits
+        /// on any child; returns true if any did, meaning the exited proc has
+        /// been reaped.
+        fn unblock_waiters(&mut self, waited_pid: u32, code: u16) -> bool {
+            let parent = self.parents.get(&waited_pid).copied();
+            let mut reaped = false;
+            for (&pid, entry) in self.procs.iter_mut() {
                 if entry.state != ProcState::Blocked {
                     continue;
                 }
-                if let Some(WaitTarget::Pid(pid)) = entry.waiting_for {
-                    if pid == waited_pid {
-                        entry.proc.regs.V[0] = code;
-                        entry.proc.regs.V[0xF] = 0;
-                        entry.state = ProcState::Running;
-                        entry.waiting_for = None;
+                let woken = match entry.waiting_for {
+                    Some(WaitTarget::Pid(target)) => target == waited_pid,
+                    Some(WaitTarget::AnyChild) if parent == Some(pid) => {
+                        entry.proc.regs.V[2] = (waited_pid & 0xFF) as u8;
+                        true
                     }
+                    _ => false,
+                };
+                if woken {
+                    set_exit_status(&mut entry.proc, code);
+                    entry.state = ProcState::Running;
+                    entry.waiting_for = None;
+                    reaped = true;
                 }
             }
+            reaped
         }
 
         fn unblock_readers(&mut self) {
@@ -473,6 +1203,17 @@ pub mod kernel {
                 if entry.proc.console_mode != ConsoleMode::Display {
                     continue;
                 }
+                if let Some(WaitTarget::ReadLine { buf, max, flags }) = entry.waiting_for {
+                    let Some(newline_idx) = Self::find_newline_in(&entry.proc.console_input) else {
+                        continue;
+                    };
+                    let (data, truncated) =
+                        Self::take_line(&mut entry.proc.console_input, newline_idx, max, flags);
+                    Self::finish_line_into(&mut entry.proc, buf, &data, truncated, flags);
+                    entry.state = ProcState::Running;
+                    entry.waiting_for = None;
+                    continue;
+                }
                 let Some(WaitTarget::Read { buf, len, mode: InputMode::Line }) = entry.waiting_for else {
                     continue;
                 };
@@ -532,6 +1273,16 @@ pub mod kernel {
                 if entry.proc.console_mode != ConsoleMode::Host {
                     continue;
                 }
+                if let Some(WaitTarget::ReadLine { buf, max, flags }) = entry.waiting_for {
+                    let Some(newline_idx) = Self::find_newline_in(input) else {
+                        continue;
+                    };
+                    let (data, truncated) = Self::take_line(input, newline_idx, max, flags);
+                    Self::finish_line_into(&mut entry.proc, buf, &data, truncated, flags);
+                    entry.state = ProcState::Running;
+                    entry.waiting_for = None;
+                    continue;
+                }
                 let Some(WaitTarget::Read { buf, len, mode: InputMode::Line }) = entry.waiting_for else {
                     continue;
                 };
@@ -602,6 +1353,43 @@ pub mod kernel {
             input.iter().position(|&b| b == b'\n')
         }
 
+        /// pop up to `max` bytes of the line ending at `newline_idx`.
+        /// Returns the bytes and whether the line was longer than `max`; with
+        /// READLINE_DISCARD the rest of the line is dropped, otherwise it stays
+        /// queued for the next read.
+        fn take_line(
+            input: &mut VecDeque<u8>,
+            newline_idx: usize,
+            max: u16,
+            flags: u16,
+        ) -> (Vec<u8>, bool) {
+            let line_len = newline_idx + 1;
+            let count = (max as usize).min(line_len);
+            let data = Self::pop_input(input, count);
+            let truncated = count < line_len;
+            if truncated && flags & READLINE_DISCARD != 0 {
+                Self::pop_input(input, line_len - count);
+            }
+            (data, truncated)
+        }
+
+        /// copy a taken line into guest memory and set readline_into results.
+        fn finish_line_into(proc: &mut Proc, buf: u16, data: &[u8], truncated: bool, flags: u16) {
+            if proc.write_bytes(buf as u32, data).is_err() {
+                proc.regs.V[0] = ERR_INVALID;
+                proc.regs.V[0xF] = 1;
+                return;
+            }
+            proc.regs.V[1] = truncated as u8;
+            if truncated && flags & READLINE_DISCARD == 0 {
+                proc.regs.V[0] = ERR_TRUNCATED;
+                proc.regs.V[0xF] = 1;
+                return;
+            }
+            proc.regs.V[0] = data.len().min(0xFF) as u8;
+            proc.regs.V[0xF] = 0;
+        }
+
         fn pop_input(input: &mut VecDeque<u8>, count: usize) -> Vec<u8> {
             let mut data = Vec::with_capacity(count);
             for _ in 0..count {
@@ -615,6 +1403,7 @@ pub mod kernel {
         }
 
         fn resolve_rom_path(&self, name: &str) -> Result<PathBuf, Error> {
+            self.reject_symlinks(Path::new(name))?;
             let candidate = self.root_dir.join(name);
             let canon = candidate
                 .canonicalize()
@@ -625,30 +1414,32 @@ pub mod kernel {
             Ok(canon)
         }
 
-        fn resolve_fs_path(&self, name: &str) -> Result<PathBuf, Error> {
-            if name.is_empty() || name == "." {
-                return Ok(self.root_dir.clone());
-            }
-
-            let rel = Path::new(name);
-            if rel.is_absolute() {
+        fn resolve_fs_path(&self, pid: u32, name: &str) -> Result<PathBuf, Error> {
+            let path = Path::new(name);
+            if path.is_absolute() {
                 return Err(Error::new(ErrorKind::InvalidInput, "absolute paths not allowed"));
             }
 
-            for comp in rel.components() {
+            // `..` is applied lexically against the cwd, so it can climb no
+            // higher than the root.
+            let mut rel = self.cwds.get(&pid).cloned().unwrap_or_default();
+            for comp in path.components() {
                 match comp {
                     Component::CurDir => {}
                     Component::ParentDir => {
-                        return Err(Error::new(ErrorKind::InvalidInput, "parent dir not allowed"));
+                        if !rel.pop() {
+                            return Err(Error::new(ErrorKind::InvalidInput, "path escapes root"));
+                        }
                     }
                     Component::Normal(seg) => {
                         let seg_len = seg.to_string_lossy().len();
                         if seg_len > MAX_FILENAME_LEN {
                             return Err(Error::new(
-                                ErrorKind::InvalidInput,
+                                ErrorKind::InvalidFilename,
                                 format!("path segment exceeds {MAX_FILENAME_LEN} bytes: {seg_len}"),
                             ));
                         }
+                        rel.push(seg);
                     }
                     _ => {
                         return Err(Error::new(ErrorKind::InvalidInput, "invalid path component"));
@@ -656,7 +1447,8 @@ pub mod kernel {
                 }
             }
 
-            let candidate = self.root_dir.join(rel);
+            self.reject_symlinks(&rel)?;
+            let candidate = self.root_dir.join(&rel);
             let canon = candidate
                 .canonicalize()
                 .map_err(|e| Error::new(ErrorKind::NotFound, format!("path not found: {e}")))?;
@@ -666,6 +1458,67 @@ pub mod kernel {
             Ok(canon)
         }
 
+        /// resolve a path that may not exist yet. Only the parent directory
+        /// is canonicalized, so it must exist and stay within the root; the
+        /// final segment is checked like any other and joined on.
+        fn resolve_fs_new_path(&self, pid: u32, name: &str) -> Result<PathBuf, Error> {
+            let rel = Path::new(name);
+            let Some(Component::Normal(leaf)) = rel.components().next_back() else {
+                return Err(Error::new(ErrorKind::InvalidInput, "path names no entry"));
+            };
+            let parent = rel.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+            let dir = self.resolve_fs_path(pid, &parent)?;
+            if !dir.is_dir() {
+                return Err(Error::new(ErrorKind::NotADirectory, "parent is not a directory"));
+            }
+
+            let leaf_len = leaf.to_string_lossy().len();
+            if leaf_len > MAX_FILENAME_LEN {
+                return Err(Error::new(
+                    ErrorKind::InvalidFilename,
+                    format!("path segment exceeds {MAX_FILENAME_LEN} bytes: {leaf_len}"),
+                ));
+            }
+            // the parent was checked above; only the leaf can still be a link.
+            let path = dir.join(leaf);
+            if fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink()) {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("symlink not allowed in root: {path:?}"),
+                ));
+            }
+            Ok(path)
+        }
+
+        /// fail if any existing component of `rel` under the root is a
+        /// symlink. validate_root_layout only runs at startup, so this is
+        /// re-checked on every resolve to catch links created since.
+        fn reject_symlinks(&self, rel: &Path) -> Result<(), Error> {
+            let mut path = self.root_dir.clone();
+            for comp in rel.components() {
+                match comp {
+                    Component::Normal(seg) => path.push(seg),
+                    Component::ParentDir => {
+                        path.pop();
+                        continue;
+                    }
+                    _ => continue,
+                }
+                match fs::symlink_metadata(&path) {
+                    Ok(meta) if meta.file_type().is_symlink() => {
+                        return Err(Error::new(
+                            ErrorKind::PermissionDenied,
+                            format!("symlink not allowed in root: {path:?}"),
+                        ));
+                    }
+                    Ok(_) => {}
+                    // missing components are reported by canonicalize.
+                    Err(_) => return Ok(()),
+                }
+            }
+            Ok(())
+        }
+
         fn validate_root_layout(root: &Path) -> Result<(), Error> {
             let mut stack = vec![root.to_path_buf()];
             while let Some(dir) = stack.pop() {
@@ -720,6 +1573,21 @@ pub mod kernel {
             Ok(())
         }
 
+        /// read the first `N` frame arguments, failing if the frame at I is
+        /// shorter than that. Optional trailing args go through syscall_arg.
+        fn read_args<const N: usize>(proc: &mut Proc) -> Result<[u16; N], Error> {
+            let base = proc.regs.I as u32;
+            let frame_len = proc.read_u8(base)? as usize;
+            if frame_len < 1 + N * 2 {
+                return Err(Error::new(ErrorKind::InvalidInput, "syscall frame too small"));
+            }
+            let mut args = [0u16; N];
+            for (index, arg) in args.iter_mut().enumerate() {
+                *arg = proc.read_u16(base + 1 + index as u32 * 2)?;
+            }
+            Ok(args)
+        }
+
         fn syscall_arg(proc: &mut Proc, index: usize) -> Result<u16, Error> {
             let base = proc.regs.I as u32;
             let frame_len = proc.read_u8(base)? as usize;
@@ -731,78 +1599,251 @@ pub mod kernel {
         }
     }
 
-    fn sys_spawn(kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let name_ptr = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+    /// fail the current syscall with `code` (V0) and VF = 1.
+    fn syscall_error(proc: &mut Proc, code: u8) -> SyscallOutcome {
+        proc.regs.V[0] = code;
+        proc.regs.V[0xF] = 1;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_spawn(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([name_ptr, name_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
-        let name_len = match Kernel::syscall_arg(proc, 1) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+        let pages = Kernel::syscall_arg(proc, 2).unwrap_or(1);
+        let flags = Kernel::syscall_arg(proc, 3).unwrap_or(0);
+        if flags & !(SPAWN_NEW_GROUP | SPAWN_THREAD) != 0 {
+            proc.regs.V[0] = ERR_INVALID;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
+        let new_group = flags & SPAWN_NEW_GROUP != 0;
+        let args_ptr = Kernel::syscall_arg(proc, 4).unwrap_or(0);
+        let args_len = Kernel::syscall_arg(proc, 5).unwrap_or(0);
+        if args_len > MAX_SPAWN_ARGS {
+            return syscall_error(proc, ERR_INVALID);
+        }
+        let Ok(args) = proc.read_bytes(args_ptr as u32, args_len as usize) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let spawned = if flags & SPAWN_THREAD != 0 {
+            // arg0/arg1 carry the entry address and stack top instead of a name.
+            spawn_thread_child(kernel, pid, proc, name_ptr, name_len, new_group)
+        } else {
+            spawn_child(kernel, pid, proc, name_ptr, name_len, pages, new_group)
+                .and_then(|child| pass_spawn_args(kernel, child, &args))
+        };
+        match spawned {
+            Ok(child) => {
+                proc.regs.V[0] = (child & 0xFF) as u8;
+                proc.regs.V[0xF] = 0;
+                SyscallOutcome::Completed
             }
+            Err(code) => syscall_error(proc, code),
+        }
+    }
+
+    /// spawn the ROM named in guest memory as a child of `pid`, in the
+    /// caller's process group unless `new_group` is set. Errors are codes
+    /// for V0.
+    fn spawn_child(
+        kernel: &mut Kernel,
+        pid: u32,
+        proc: &mut Proc,
+        name_ptr: u16,
+        name_len: u16,
+        pages: u16,
+        new_group: bool,
+    ) -> Result<u32, u8> {
+        let name_bytes = proc
+            .read_bytes(name_ptr as u32, name_len as usize)
+            .map_err(|_| ERR_INVALID)?;
+        let rom_name = String::from_utf8_lossy(&name_bytes).to_string();
+        let path = kernel.resolve_rom_path(&rom_name).map_err(|_| ERR_IO)?;
+        let display = DisplayWindow::from_env().map_err(|_| ERR_IO)?;
+        let child = kernel
+            .spawn_proc_with_rom(display, pages, &path)
+            .map_err(|_| ERR_IO)?;
+        if !new_group {
+            let pgid = kernel.pgids.get(&pid).copied().unwrap_or(pid);
+            kernel.pgids.insert(child, pgid);
+        }
+        kernel.parents.insert(child, pid);
+        kernel.inherit_cwd(pid, child);
+        Ok(child)
+    }
+
+    /// copy a fresh child's argument bytes just below its stack, pointing I
+    /// at them and leaving their length in V0. Nothing is copied for empty
+    /// args. A child that cannot take them is released again.
+    fn pass_spawn_args(kernel: &mut Kernel, child: u32, args: &[u8]) -> Result<u32, u8> {
+        if args.is_empty() {
+            return Ok(child);
+        }
+        let entry = kernel.procs.get_mut(&child).expect("just spawned");
+        let base = entry.proc.regs.SP.wrapping_sub(args.len() as u16);
+        if entry.proc.write_bytes(base as u32, args).is_err() {
+            kernel.release_proc(child);
+            return Err(ERR_INVALID);
+        }
+        entry.proc.regs.SP = base;
+        entry.proc.stack_top = base;
+        entry.proc.regs.I = base;
+        entry.proc.regs.V[0] = args.len() as u8;
+        Ok(child)
+    }
+
+    /// start a thread of the caller at `entry` with its stack at `sp`
+    /// (0 = THREAD_STACK_GAP below the top of the VM).
+    fn spawn_thread_child(
+        kernel: &mut Kernel,
+        pid: u32,
+        proc: &mut Proc,
+        entry: u16,
+        sp: u16,
+        new_group: bool,
+    ) -> Result<u32, u8> {
+        let top = proc.vm_size.min(u16::MAX as u32);
+        let sp = if sp == 0 {
+            top.checked_sub(THREAD_STACK_GAP).ok_or(ERR_INVALID)? as u16
+        } else {
+            sp
+        };
+        if entry as u32 >= proc.vm_size || sp as u32 > top {
+            return Err(ERR_INVALID);
+        }
+        let display = DisplayWindow::from_env().map_err(|_| ERR_IO)?;
+        let child = kernel.spawn_thread(pid, proc, display, entry, sp);
+        if new_group {
+            kernel.pgids.insert(child, child);
+        }
+        Ok(child)
+    }
+
+    fn sys_spawn_pipe(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([name_ptr, name_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
         let pages = Kernel::syscall_arg(proc, 2).unwrap_or(1);
-        let name_bytes = match proc.read_bytes(name_ptr as u32, name_len as usize) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+
+        // the caller needs two fds; check before a child exists to undo.
+        let Some(table) = kernel.fd_tables.get(&pid) else {
+            return syscall_error(proc, ERR_NOT_FOUND);
         };
-        let rom_name = String::from_utf8_lossy(&name_bytes).to_string();
-        let path = match kernel.resolve_rom_path(&rom_name) {
+        if table.fds.len() + 2 > kernel.max_open_files {
+            return syscall_error(proc, ERR_TOO_MANY_OPEN);
+        }
+
+        let child = match spawn_child(kernel, pid, proc, name_ptr, name_len, pages, false) {
             Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_IO;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+            Err(code) => return syscall_error(proc, code),
         };
-        let display = match DisplayWindow::from_env() {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_IO;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let stdin = kernel.new_pipe();
+        let stdout = kernel.new_pipe();
+        kernel.console_pipes.insert(child, ConsolePipes { stdin, stdout });
+        if let Some(entry) = kernel.procs.get_mut(&child) {
+            entry.proc.console_mode = ConsoleMode::Pipe;
+        }
+
+        let table = kernel.fd_tables.get_mut(&pid).expect("checked above");
+        let stdin_fd = next_free_handle(&table.fds, table.next_fd).expect("checked above");
+        table.fds.insert(stdin_fd, FdEntry::PipeWrite(stdin));
+        let stdout_fd = next_free_handle(&table.fds, stdin_fd).expect("checked above");
+        table.fds.insert(stdout_fd, FdEntry::PipeRead(stdout));
+        table.next_fd = stdout_fd.wrapping_add(1);
+
+        proc.regs.V[0] = (child & 0xFF) as u8;
+        proc.regs.V[1] = stdin_fd;
+        proc.regs.V[2] = stdout_fd;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// open an anonymous pipe in the caller's fd table. Threads started
+    /// afterwards share both ends (see `Kernel::spawn_thread`).
+    fn sys_pipe(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Some(table) = kernel.fd_tables.get(&pid) else {
+            return syscall_error(proc, ERR_NOT_FOUND);
         };
-        match kernel.spawn_proc_with_rom(display, pages, &path) {
-            Ok(pid) => {
-                proc.regs.V[0] = (pid & 0xFF) as u8;
-                proc.regs.V[0xF] = 0;
+        if table.fds.len() + 2 > kernel.max_open_files {
+            return syscall_error(proc, ERR_TOO_MANY_OPEN);
+        }
+
+        let pipe = kernel.new_pipe();
+        let table = kernel.fd_tables.get_mut(&pid).expect("checked above");
+        let read_fd = next_free_handle(&table.fds, table.next_fd).expect("checked above");
+        table.fds.insert(read_fd, FdEntry::PipeRead(pipe));
+        let write_fd = next_free_handle(&table.fds, read_fd).expect("checked above");
+        table.fds.insert(write_fd, FdEntry::PipeWrite(pipe));
+        table.next_fd = write_fd.wrapping_add(1);
+
+        proc.regs.V[0] = read_fd;
+        proc.regs.V[1] = write_fd;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// open a second fd for what `fd` refers to. A file copy shares the
+    /// host file's position; a pipe copy is one more reader or writer.
+    fn sys_dup(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([fd]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let Some(table) = kernel.fd_tables.get_mut(&pid) else {
+            return syscall_error(proc, ERR_NOT_FOUND);
+        };
+        if table.fds.len() >= kernel.max_open_files {
+            return syscall_error(proc, ERR_TOO_MANY_OPEN);
+        }
+
+        let copy = match table.fds.get(&(fd as u8)) {
+            Some(FdEntry::File { file, writable }) => match file.try_clone() {
+                Ok(file) => FdEntry::File { file, writable: *writable },
+                Err(_) => return syscall_error(proc, ERR_IO),
+            },
+            Some(FdEntry::PipeRead(id)) => FdEntry::PipeRead(*id),
+            Some(FdEntry::PipeWrite(id)) => FdEntry::PipeWrite(*id),
+            None => return syscall_error(proc, ERR_NOT_FOUND),
+        };
+        let Some(new_fd) = next_free_handle(&table.fds, table.next_fd) else {
+            return syscall_error(proc, ERR_TOO_MANY_OPEN);
+        };
+        match &copy {
+            FdEntry::PipeRead(id) => {
+                if let Some(pipe) = kernel.pipes.get_mut(id) {
+                    pipe.readers += 1;
+                }
             }
-            Err(_) => {
-                proc.regs.V[0] = ERR_IO;
-                proc.regs.V[0xF] = 1;
+            FdEntry::PipeWrite(id) => {
+                if let Some(pipe) = kernel.pipes.get_mut(id) {
+                    pipe.writers += 1;
+                }
             }
+            FdEntry::File { .. } => {}
         }
+        table.fds.insert(new_fd, copy);
+        table.next_fd = new_fd.wrapping_add(1);
+
+        proc.regs.V[0] = new_fd;
+        proc.regs.V[0xF] = 0;
         SyscallOutcome::Completed
     }
 
     fn sys_exit(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let code = Kernel::syscall_arg(proc, 0).unwrap_or(0) as u8;
+        let code = Kernel::syscall_arg(proc, 0).unwrap_or(0);
         kernel.pending_exit.insert(pid, code);
         proc.regs.V[0xF] = 0;
         SyscallOutcome::Completed
     }
 
     fn sys_wait(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let target = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val as u32,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let Ok([target]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
+        let target = target as u32;
+        if target == 0 {
+            return wait_any_child(kernel, pid, proc);
+        }
 
         let Some(target_entry) = kernel.procs.get(&target) else {
             proc.regs.V[0] = ERR_INVALID;
@@ -811,8 +1852,9 @@ pub mod kernel {
         };
 
         if target_entry.state == ProcState::Exited {
-            proc.regs.V[0] = target_entry.exit_code.unwrap_or(0);
-            proc.regs.V[0xF] = 0;
+            set_exit_status(proc, target_entry.exit_code.unwrap_or(0));
+            // collecting the exit code reaps the child.
+            kernel.release_proc(target);
             return SyscallOutcome::Completed;
         }
 
@@ -822,27 +1864,237 @@ pub mod kernel {
         SyscallOutcome::Blocked
     }
 
-    fn sys_yield(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
+    /// hand a collected exit code to a waiter: low byte in V0, high in V1.
+    fn set_exit_status(proc: &mut Proc, code: u16) {
+        let [hi, lo] = code.to_be_bytes();
+        proc.regs.V[0] = lo;
+        proc.regs.V[1] = hi;
         proc.regs.V[0xF] = 0;
-        SyscallOutcome::Yielded
     }
 
-    fn sys_write(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let buf = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+    /// wait(0): reap an already exited child of `pid` (the lowest pid if
+    /// several have), or block until one exits. A caller with no children
+    /// would never wake, so it fails with ERR_INVALID instead.
+    fn wait_any_child(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let children = kernel.parents.iter().filter(|&(_, &parent)| parent == pid);
+        let mut any_child = false;
+        let mut exited: Option<(u32, u16)> = None;
+        for (&child, _) in children {
+            let Some(entry) = kernel.procs.get(&child) else {
+                continue;
+            };
+            any_child = true;
+            if entry.state == ProcState::Exited && exited.is_none_or(|(first, _)| child < first) {
+                exited = Some((child, entry.exit_code.unwrap_or(0)));
+            }
+        }
+
+        if let Some((child, code)) = exited {
+            set_exit_status(proc, code);
+            proc.regs.V[2] = (child & 0xFF) as u8;
+            kernel.release_proc(child);
+            return SyscallOutcome::Completed;
+        }
+        if !any_child {
+            return syscall_error(proc, ERR_INVALID);
+        }
+        kernel.pending_block.insert(pid, WaitTarget::AnyChild);
+        SyscallOutcome::Blocked
+    }
+
+    fn sys_killpg(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([pgid]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let pgid = pgid as u32;
+
+        let members: Vec<u32> = kernel
+            .pgids
+            .iter()
+            .filter(|&(&member, &group)| group == pgid && member != pid)
+            .map(|(&member, _)| member)
+            .collect();
+        let mut killed = 0usize;
+        for member in members {
+            if kernel.kill_proc(member, KILLED_EXIT_CODE) {
+                killed += 1;
             }
+        }
+        // the caller is outside `procs` during the syscall; exit it the usual way.
+        if kernel.pgids.get(&pid) == Some(&pgid) {
+            kernel.pending_exit.insert(pid, KILLED_EXIT_CODE);
+            killed += 1;
+        }
+
+        if killed == 0 {
+            proc.regs.V[0] = ERR_NOT_FOUND;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
+        proc.regs.V[0] = killed.min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// give up the rest of the slice. A nonzero tick count (optional arg0)
+    /// backs off like `sleep` instead of being rescheduled next round.
+    fn sys_yield(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let ticks = Kernel::syscall_arg(proc, 0).unwrap_or(0);
+        proc.regs.V[0xF] = 0;
+        if ticks == 0 {
+            return SyscallOutcome::Yielded;
+        }
+        let wake_at = kernel.clock_ticks + ticks as u64;
+        kernel.pending_block.insert(pid, WaitTarget::Timer { wake_at });
+        SyscallOutcome::Blocked
+    }
+
+    /// block the caller for `ticks` 60Hz timer ticks. Zero returns at once.
+    fn sys_sleep(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([ticks]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        if ticks == 0 {
+            return SyscallOutcome::Completed;
+        }
+        let wake_at = kernel.clock_ticks + ticks as u64;
+        kernel.pending_block.insert(pid, WaitTarget::Timer { wake_at });
+        SyscallOutcome::Blocked
+    }
+
+    /// the pid of the caller's spawner, or 0 for procs the host spawned.
+    fn sys_getppid(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let parent = kernel.parents.get(&pid).copied().unwrap_or(0);
+        proc.regs.V[0] = (parent & 0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// replace the caller's program with the named ROM, keeping its pid and
+    /// pages. Open fds are closed; registers reset once the syscall returns
+    /// (see `Proc::exec`), so on success execution resumes at 0x200.
+    fn sys_exec(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([name_ptr, name_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
-        let len = match Kernel::syscall_arg(proc, 1) {
+        // a new image would land under threads still running the old one.
+        if kernel.shares_pages(pid) {
+            return syscall_error(proc, ERR_INVALID);
+        }
+        let name_bytes = match proc.read_bytes(name_ptr as u32, name_len as usize) {
             Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let rom_name = String::from_utf8_lossy(&name_bytes).to_string();
+        let Ok(path) = kernel.resolve_rom_path(&rom_name) else {
+            return syscall_error(proc, ERR_IO);
+        };
+        let program = match fs::read(&path) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_IO),
+        };
+        // check here, while the caller can still see the error.
+        if program.len() > proc.vm_size as usize - 0x200 {
+            return syscall_error(proc, ERR_IO);
+        }
+
+        kernel.clear_fds(pid);
+        kernel.pending_exec.insert(pid, program);
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// copy the caller into a new child. Both resume after the syscall: the
+    /// parent with the child's pid in V0, the child with V0 = 0.
+    fn sys_fork(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok(display) = DisplayWindow::from_env() else {
+            return syscall_error(proc, ERR_IO);
+        };
+        let child = match kernel.fork_proc(pid, proc, display) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_IO),
+        };
+        // the copy was taken mid-syscall; step the child past it by hand,
+        // as the engine will for the parent.
+        if let Some(entry) = kernel.procs.get_mut(&child) {
+            let regs = &mut entry.proc.regs;
+            regs.PC = regs.PC.wrapping_add(2);
+            regs.V[0] = 0;
+            regs.V[0xF] = 0;
+        }
+
+        proc.regs.V[0] = (child & 0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// grow the caller by arg0 pages (0 just queries) and return the last
+    /// address now mapped, big-endian in V0/V1. Procs sharing pages with a
+    /// thread are refused, since the other page tables would not follow.
+    fn sys_sbrk(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([pages]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        if pages != 0 && kernel.shares_pages(pid) {
+            return syscall_error(proc, ERR_INVALID);
+        }
+        let vm_size = match proc.grow(pages) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::InvalidInput => {
+                return syscall_error(proc, ERR_INVALID);
+            }
+            Err(_) => return syscall_error(proc, ERR_IO),
+        };
+
+        let top = (vm_size - 1) as u16;
+        proc.regs.V[0] = (top >> 8) as u8;
+        proc.regs.V[1] = top as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// write a record for up to `max` procs, in pid order, and return the
+    /// count. The caller is out of the table while this runs, so it is
+    /// listed from its own registers as running.
+    fn sys_ps(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([out_ptr, max]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let mut rows: Vec<(u32, ProcState, u16)> = kernel
+            .procs
+            .iter()
+            .map(|(&other, entry)| (other, entry.state, entry.exit_code.unwrap_or(0)))
+            .collect();
+        rows.push((pid, ProcState::Running, 0));
+        rows.sort_by_key(|&(other, _, _)| other);
+        rows.truncate((max as usize).min(0xFF));
+
+        for (idx, &(other, state, code)) in rows.iter().enumerate() {
+            let state = match state {
+                ProcState::Running => 0,
+                ProcState::Blocked => 1,
+                ProcState::Exited => 2,
+            };
+            let [pid_hi, pid_lo] = (other as u16).to_be_bytes();
+            let [code_hi, code_lo] = code.to_be_bytes();
+            let record = [pid_hi, pid_lo, state, code_hi, code_lo];
+            let addr = out_ptr as u32 + (idx * PS_ENTRY_SIZE) as u32;
+            if proc.write_bytes(addr, &record).is_err() {
+                return syscall_error(proc, ERR_INVALID);
             }
+        }
+
+        proc.regs.V[0] = rows.len() as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_write(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
 
         let data = match proc.read_bytes(buf as u32, len as usize) {
@@ -861,6 +2113,11 @@ pub mod kernel {
             return SyscallOutcome::Completed;
         }
 
+        if proc.console_mode == ConsoleMode::Pipe {
+            let stdout = kernel.console_pipes.get(&pid).map(|console| console.stdout);
+            return pipe_write(kernel, proc, stdout, &data);
+        }
+
         let mut stdout = io::stdout();
         if stdout.write_all(&data).is_err() {
             proc.regs.V[0] = ERR_IO;
@@ -874,25 +2131,16 @@ pub mod kernel {
     }
 
     fn sys_read(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let buf = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
-        };
-        let len = match Kernel::syscall_arg(proc, 1) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let Ok([buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
 
         let mode = proc.input_mode;
 
+        if proc.console_mode == ConsoleMode::Pipe {
+            return kernel.pipe_read(pid, proc, WaitTarget::Read { buf, len, mode });
+        }
+
         match mode {
             InputMode::Line => {
                 if proc.console_mode == ConsoleMode::Display {
@@ -971,20 +2219,42 @@ pub mod kernel {
         }
     }
 
+    fn sys_readline_into(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([buf, max]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let flags = Kernel::syscall_arg(proc, 2).unwrap_or(0);
+
+        if proc.console_mode == ConsoleMode::Pipe {
+            return kernel.pipe_read(pid, proc, WaitTarget::ReadLine { buf, max, flags });
+        }
+
+        let (data, truncated) = if proc.console_mode == ConsoleMode::Display {
+            let Some(newline_idx) = Kernel::find_newline_in(&proc.console_input) else {
+                kernel
+                    .pending_block
+                    .insert(pid, WaitTarget::ReadLine { buf, max, flags });
+                return SyscallOutcome::Blocked;
+            };
+            Kernel::take_line(&mut proc.console_input, newline_idx, max, flags)
+        } else {
+            let Some(newline_idx) = Kernel::find_newline_in(&kernel.input) else {
+                kernel
+                    .pending_block
+                    .insert(pid, WaitTarget::ReadLine { buf, max, flags });
+                return SyscallOutcome::Blocked;
+            };
+            Kernel::take_line(&mut kernel.input, newline_idx, max, flags)
+        };
+        Kernel::finish_line_into(proc, buf, &data, truncated, flags);
+        SyscallOutcome::Completed
+    }
+
     fn sys_input_mode(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let mode = match Kernel::syscall_arg(proc, 0) {
-            Ok(0) => InputMode::Line,
-            Ok(1) => InputMode::Byte,
-            Ok(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let mode = match Kernel::read_args(proc) {
+            Ok([0]) => InputMode::Line,
+            Ok([1]) => InputMode::Byte,
+            _ => return syscall_error(proc, ERR_INVALID),
         };
 
         proc.input_mode = mode;
@@ -993,19 +2263,10 @@ pub mod kernel {
     }
 
     fn sys_console_mode(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let mode = match Kernel::syscall_arg(proc, 0) {
-            Ok(0) => ConsoleMode::Host,
-            Ok(1) => ConsoleMode::Display,
-            Ok(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let mode = match Kernel::read_args(proc) {
+            Ok([0]) => ConsoleMode::Host,
+            Ok([1]) => ConsoleMode::Display,
+            _ => return syscall_error(proc, ERR_INVALID),
         };
 
         proc.console_mode = mode;
@@ -1014,7 +2275,7 @@ pub mod kernel {
             ConsoleMode::Display => {
                 proc.display.set_mode(DisplayMode::Console);
             }
-            ConsoleMode::Host => {
+            ConsoleMode::Host | ConsoleMode::Pipe => {
                 proc.display.set_mode(DisplayMode::Chip8);
             }
         }
@@ -1022,39 +2283,93 @@ pub mod kernel {
         SyscallOutcome::Completed
     }
 
-    fn sys_fs_list(kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let path_ptr = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+    /// find the first unused nonzero handle at or after `start`, wrapping.
+    fn next_free_handle<T>(used: &HashMap<u8, T>, start: u8) -> Option<u8> {
+        let mut handle = start;
+        for _ in 0..=u8::MAX {
+            if handle == 0 {
+                handle = 1;
+            }
+            if !used.contains_key(&handle) {
+                return Some(handle);
             }
+            handle = handle.wrapping_add(1);
+        }
+        None
+    }
+
+    /// kind byte (0 = file, 1 = dir) and big-endian size, as ending every
+    /// fs_list record and returned whole by fs_stat.
+    fn stat_record(meta: &fs::Metadata) -> [u8; 5] {
+        let kind = if meta.is_dir() { 1u8 } else { 0u8 };
+        let size = if meta.is_file() { meta.len() as u32 } else { 0u32 };
+        let [s0, s1, s2, s3] = size.to_be_bytes();
+        [kind, s0, s1, s2, s3]
+    }
+
+    /// encode one fixed-size directory record (see SYSCALLS.md fs_list).
+    fn dir_record(name: &str, meta: &fs::Metadata) -> Vec<u8> {
+        let mut record = Vec::with_capacity(DIR_ENTRY_SIZE);
+        record.push(name.len() as u8);
+        record.extend_from_slice(name.as_bytes());
+        if name.len() < MAX_FILENAME_LEN {
+            record.extend(std::iter::repeat(0u8).take(MAX_FILENAME_LEN - name.len()));
+        }
+        record.extend_from_slice(&stat_record(meta));
+        // whole seconds since the epoch; 0 when the host has no mtime.
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_secs());
+        record.extend_from_slice(&mtime.to_be_bytes());
+        record
+    }
+
+    fn sys_fs_stat(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len, out_ptr]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
-        let path_len = match Kernel::syscall_arg(proc, 1) {
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
             Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+            Err(_) => return syscall_error(proc, ERR_INVALID),
         };
-        let out_ptr = match Kernel::syscall_arg(proc, 2) {
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let path = match kernel.resolve_fs_path(pid, &path_str) {
             Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
             }
-        };
-        let max_entries = match Kernel::syscall_arg(proc, 3) {
-            Ok(val) => val as usize,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
             }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        let meta = match fs::metadata(&path) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_NOT_FOUND),
+        };
+        if proc.write_bytes(out_ptr as u32, &stat_record(&meta)).is_err() {
+            return syscall_error(proc, ERR_INVALID);
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// list a directory sorted by name, starting at entry `start` (arg4),
+    /// so a caller can page through it. V1 reports whether entries remain.
+    fn sys_fs_list(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len, out_ptr, max_entries]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
+        let max_entries = max_entries as usize;
+        let start = Kernel::syscall_arg(proc, 4).unwrap_or(0) as usize;
 
         let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
             Ok(val) => val,
@@ -1065,13 +2380,14 @@ pub mod kernel {
             }
         };
         let path_str = String::from_utf8_lossy(&path_bytes).to_string();
-        let dir_path = match kernel.resolve_fs_path(&path_str) {
+        let dir_path = match kernel.resolve_fs_path(pid, &path_str) {
             Ok(val) => val,
             Err(err) => {
-                proc.regs.V[0] = if err.kind() == ErrorKind::NotFound {
-                    ERR_NOT_FOUND
-                } else {
-                    ERR_PATH
+                proc.regs.V[0] = match err.kind() {
+                    ErrorKind::NotFound => ERR_NOT_FOUND,
+                    ErrorKind::InvalidFilename => ERR_NAME_TOO_LONG,
+                    ErrorKind::PermissionDenied => ERR_PERMISSION,
+                    _ => ERR_PATH,
                 };
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
@@ -1092,22 +2408,19 @@ pub mod kernel {
         }
 
         let mut count = 0usize;
-        let entries = match fs::read_dir(&dir_path) {
-            Ok(val) => val,
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(&dir_path) {
+            Ok(val) => val.filter_map(Result::ok).collect(),
             Err(_) => {
                 proc.regs.V[0] = ERR_IO;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
         };
-        for entry in entries {
-            if count >= max_entries {
-                break;
-            }
-            let entry = match entry {
-                Ok(val) => val,
-                Err(_) => continue,
-            };
+        // host order is arbitrary; sorting makes `start` mean the same entry
+        // from one call to the next.
+        entries.sort_by_key(|entry| entry.file_name());
+        let total = entries.len();
+        for entry in entries.into_iter().skip(start).take(max_entries) {
             let name = entry.file_name().to_string_lossy().to_string();
             if name.len() > MAX_FILENAME_LEN {
                 proc.regs.V[0] = ERR_NAME_TOO_LONG;
@@ -1118,17 +2431,7 @@ pub mod kernel {
                 Ok(val) => val,
                 Err(_) => continue,
             };
-            let kind = if meta.is_dir() { 1u8 } else { 0u8 };
-            let size = if meta.is_file() { meta.len() as u32 } else { 0u32 };
-
-            let mut record = Vec::with_capacity(DIR_ENTRY_SIZE);
-            record.push(name.len() as u8);
-            record.extend_from_slice(name.as_bytes());
-            if name.len() < MAX_FILENAME_LEN {
-                record.extend(std::iter::repeat(0u8).take(MAX_FILENAME_LEN - name.len()));
-            }
-            record.push(kind);
-            record.extend_from_slice(&size.to_be_bytes());
+            let record = dir_record(&name, &meta);
 
             let addr = out_ptr as u32 + (count * DIR_ENTRY_SIZE) as u32;
             if proc.write_bytes(addr, &record).is_err() {
@@ -1140,28 +2443,18 @@ pub mod kernel {
         }
 
         proc.regs.V[0] = count.min(0xFF) as u8;
+        proc.regs.V[1] = (start.saturating_add(max_entries) < total) as u8;
         proc.regs.V[0xF] = 0;
         SyscallOutcome::Completed
     }
 
     fn sys_fs_open(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let path_ptr = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
-        let path_len = match Kernel::syscall_arg(proc, 1) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
-                proc.regs.V[0xF] = 1;
-                return SyscallOutcome::Completed;
-            }
-        };
-        let _flags = Kernel::syscall_arg(proc, 2).unwrap_or(0);
+        let flags = Kernel::syscall_arg(proc, 2).unwrap_or(0);
+        let append = flags & FS_OPEN_APPEND != 0;
+        let writable = append || flags & FS_OPEN_WRITE != 0;
 
         let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
             Ok(val) => val,
@@ -1172,13 +2465,14 @@ pub mod kernel {
             }
         };
         let path_str = String::from_utf8_lossy(&path_bytes).to_string();
-        let file_path = match kernel.resolve_fs_path(&path_str) {
+        let file_path = match kernel.resolve_fs_path(pid, &path_str) {
             Ok(val) => val,
             Err(err) => {
-                proc.regs.V[0] = if err.kind() == ErrorKind::NotFound {
-                    ERR_NOT_FOUND
-                } else {
-                    ERR_PATH
+                proc.regs.V[0] = match err.kind() {
+                    ErrorKind::NotFound => ERR_NOT_FOUND,
+                    ErrorKind::InvalidFilename => ERR_NAME_TOO_LONG,
+                    ErrorKind::PermissionDenied => ERR_PERMISSION,
+                    _ => ERR_PATH,
                 };
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
@@ -1212,13 +2506,25 @@ pub mod kernel {
             }
         };
 
-        if table.fds.len() >= MAX_OPEN_FILES {
+        if table.fds.len() >= kernel.max_open_files {
             proc.regs.V[0] = ERR_TOO_MANY_OPEN;
             proc.regs.V[0xF] = 1;
             return SyscallOutcome::Completed;
         }
 
-        let file = match fs::File::open(&file_path) {
+        let file = match fs::OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .truncate(writable && !append)
+            .open(&file_path)
+            .and_then(|mut file| {
+                // append handles start at the end, so the size check in
+                // file_write sees where the bytes will land.
+                if append {
+                    file.seek(SeekFrom::End(0))?;
+                }
+                Ok(file)
+            }) {
             Ok(val) => val,
             Err(_) => {
                 proc.regs.V[0] = ERR_IO;
@@ -1227,23 +2533,13 @@ pub mod kernel {
             }
         };
 
-        let mut fd = table.next_fd;
-        for _ in 0..=u8::MAX {
-            if fd == 0 {
-                fd = 1;
-            }
-            if !table.fds.contains_key(&fd) {
-                break;
-            }
-            fd = fd.wrapping_add(1);
-        }
-        if table.fds.contains_key(&fd) {
+        let Some(fd) = next_free_handle(&table.fds, table.next_fd) else {
             proc.regs.V[0] = ERR_TOO_MANY_OPEN;
             proc.regs.V[0xF] = 1;
             return SyscallOutcome::Completed;
-        }
+        };
 
-        table.fds.insert(fd, file);
+        table.fds.insert(fd, FdEntry::File { file, writable });
         table.next_fd = fd.wrapping_add(1);
 
         proc.regs.V[0] = fd;
@@ -1252,15 +2548,397 @@ pub mod kernel {
     }
 
     fn sys_fs_read(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let fd = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val as u8,
+        let Ok([fd, buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let fd = fd as u8;
+        let len = len as usize;
+
+        let table = match kernel.fd_tables.get_mut(&pid) {
+            Some(val) => val,
+            None => {
+                proc.regs.V[0] = ERR_NOT_FOUND;
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+        };
+
+        let file = match table.fds.get_mut(&fd) {
+            Some(FdEntry::File { file, .. }) => file,
+            Some(FdEntry::PipeRead(pipe)) => {
+                let wait = WaitTarget::PipeRead { pipe: *pipe, buf, len: len as u16 };
+                return kernel.pipe_read(pid, proc, wait);
+            }
+            Some(FdEntry::PipeWrite(_)) => return syscall_error(proc, ERR_INVALID),
+            None => {
+                proc.regs.V[0] = ERR_NOT_FOUND;
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+        };
+
+        let max_len = len.min(0xFF);
+        let mut data = vec![0u8; max_len];
+        let read = match file.read(&mut data) {
+            Ok(val) => val,
             Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
+                proc.regs.V[0] = ERR_IO;
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+        };
+        data.truncate(read);
+        if proc.write_bytes(buf as u32, &data).is_err() {
+            proc.regs.V[0] = ERR_INVALID;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
+
+        proc.regs.V[0] = read.min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_close(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([fd]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let fd = fd as u8;
+        let table = match kernel.fd_tables.get_mut(&pid) {
+            Some(val) => val,
+            None => {
+                proc.regs.V[0] = ERR_NOT_FOUND;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
         };
-        let buf = match Kernel::syscall_arg(proc, 1) {
+        let Some(entry) = table.fds.remove(&fd) else {
+            proc.regs.V[0] = ERR_NOT_FOUND;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        };
+        kernel.close_pipe_end(&entry);
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_write(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([fd, buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let fd = fd as u8;
+        let data = match proc.read_bytes(buf as u32, len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+
+        let pipe = match kernel.fd_tables.get_mut(&pid).and_then(|table| table.fds.get_mut(&fd)) {
+            Some(FdEntry::PipeWrite(pipe)) => *pipe,
+            Some(FdEntry::File { file, writable: true }) => return file_write(proc, file, &data),
+            // read-only files and pipe read ends.
+            Some(FdEntry::File { .. }) | Some(FdEntry::PipeRead(_)) => {
+                return syscall_error(proc, ERR_INVALID);
+            }
+            None => return syscall_error(proc, ERR_NOT_FOUND),
+        };
+        pipe_write(kernel, proc, Some(pipe), &data)
+    }
+
+    /// move a file fd's cursor. The offset is unsigned from the start and
+    /// signed (i16) from the current position or the end.
+    fn sys_fs_seek(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([fd, whence, offset]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let target = match whence {
+            SEEK_START => SeekFrom::Start(offset as u64),
+            SEEK_CURRENT => SeekFrom::Current(offset as i16 as i64),
+            SEEK_END => SeekFrom::End(offset as i16 as i64),
+            _ => return syscall_error(proc, ERR_INVALID),
+        };
+
+        let file = match kernel.fd_tables.get_mut(&pid).and_then(|table| table.fds.get_mut(&(fd as u8))) {
+            Some(FdEntry::File { file, .. }) => file,
+            Some(FdEntry::PipeRead(_)) | Some(FdEntry::PipeWrite(_)) => {
+                return syscall_error(proc, ERR_INVALID);
+            }
+            None => return syscall_error(proc, ERR_NOT_FOUND),
+        };
+        // seeking before the start of the file fails without moving.
+        let pos = match file.seek(target) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+
+        proc.regs.V[0] = pos.min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// read from a file fd at an absolute 32-bit offset (arg1 high, arg2
+    /// low), then put the cursor back so sequential reads are undisturbed.
+    fn sys_fs_pread(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([fd, offset_hi, offset_lo, buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let offset = ((offset_hi as u64) << 16) | offset_lo as u64;
+
+        let file = match kernel.fd_tables.get_mut(&pid).and_then(|table| table.fds.get_mut(&(fd as u8))) {
+            Some(FdEntry::File { file, .. }) => file,
+            Some(FdEntry::PipeRead(_)) | Some(FdEntry::PipeWrite(_)) => {
+                return syscall_error(proc, ERR_INVALID);
+            }
+            None => return syscall_error(proc, ERR_NOT_FOUND),
+        };
+        let mut data = vec![0u8; (len as usize).min(0xFF)];
+        let read = file.stream_position().and_then(|pos| {
+            file.seek(SeekFrom::Start(offset))?;
+            let read = file.read(&mut data);
+            file.seek(SeekFrom::Start(pos))?;
+            read
+        });
+        let Ok(read) = read else {
+            return syscall_error(proc, ERR_IO);
+        };
+        data.truncate(read);
+        if proc.write_bytes(buf as u32, &data).is_err() {
+            return syscall_error(proc, ERR_INVALID);
+        }
+
+        proc.regs.V[0] = read as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// set the directory the caller's relative paths resolve against.
+    fn sys_chdir(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let dir_path = match kernel.resolve_fs_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
+            }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        if !dir_path.is_dir() {
+            return syscall_error(proc, ERR_NOT_DIR);
+        }
+        let Ok(rel) = dir_path.strip_prefix(&kernel.root_dir) else {
+            return syscall_error(proc, ERR_PATH);
+        };
+        if rel.as_os_str().is_empty() {
+            kernel.cwds.remove(&pid);
+        } else {
+            kernel.cwds.insert(pid, rel.to_path_buf());
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// copy the caller's working directory, relative to the root and
+    /// `/`-separated, into a buffer. The root is the empty string.
+    fn sys_getcwd(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let cwd = kernel
+            .cwds
+            .get(&pid)
+            .map(|rel| {
+                let segs: Vec<String> = rel.iter().map(|seg| seg.to_string_lossy().to_string()).collect();
+                segs.join("/")
+            })
+            .unwrap_or_default();
+        if cwd.len() > len as usize || cwd.len() > 0xFF {
+            return syscall_error(proc, ERR_NAME_TOO_LONG);
+        }
+        if proc.write_bytes(buf as u32, cwd.as_bytes()).is_err() {
+            return syscall_error(proc, ERR_INVALID);
+        }
+
+        proc.regs.V[0] = cwd.len() as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// write `data` at the file's cursor. A write that would grow the file
+    /// past MAX_FILE_SIZE fails with ERR_IO.
+    fn file_write(proc: &mut Proc, file: &mut fs::File, data: &[u8]) -> SyscallOutcome {
+        let end = match file.stream_position() {
+            Ok(pos) => pos + data.len() as u64,
+            Err(_) => return syscall_error(proc, ERR_IO),
+        };
+        if end > MAX_FILE_SIZE || file.write_all(data).is_err() {
+            return syscall_error(proc, ERR_IO);
+        }
+        proc.regs.V[0] = data.len().min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// append `data` to a pipe and wake its readers. Writing to a missing
+    /// pipe, or one with no reader left, fails with ERR_IO.
+    fn pipe_write(kernel: &mut Kernel, proc: &mut Proc, pipe: Option<u32>, data: &[u8]) -> SyscallOutcome {
+        let Some(buffer) = pipe.and_then(|id| kernel.pipes.get_mut(&id)) else {
+            return syscall_error(proc, ERR_IO);
+        };
+        if buffer.readers == 0 {
+            return syscall_error(proc, ERR_IO);
+        }
+        buffer.data.extend(data);
+        kernel.unblock_pipe_readers();
+        proc.regs.V[0] = data.len().min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// create or truncate a regular file. A new file in a parent already
+    /// holding MAX_DIR_ENTRIES entries is refused with ERR_IO.
+    fn sys_fs_create(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let file_path = match kernel.resolve_fs_new_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
+            }
+            Err(err) if err.kind() == ErrorKind::NotADirectory => {
+                return syscall_error(proc, ERR_NOT_DIR);
+            }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        if file_path.is_dir() {
+            return syscall_error(proc, ERR_IS_DIR);
+        }
+        // truncating an existing file adds no entry; a new one must fit.
+        let full = !file_path.exists() && file_path.parent().is_none_or(dir_is_full);
+        if full || fs::File::create(&file_path).is_err() {
+            return syscall_error(proc, ERR_IO);
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// remove a regular file. Directories are refused with ERR_IS_DIR.
+    fn sys_fs_delete(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let file_path = match kernel.resolve_fs_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
+            }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        match fs::metadata(&file_path) {
+            Ok(meta) if meta.is_dir() => return syscall_error(proc, ERR_IS_DIR),
+            Ok(_) => {}
+            Err(_) => return syscall_error(proc, ERR_NOT_FOUND),
+        }
+        if fs::remove_file(&file_path).is_err() {
+            return syscall_error(proc, ERR_IO);
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// true when `dir` already holds MAX_DIR_ENTRIES entries, or cannot be
+    /// read, so adding one would break the limit validate_root_layout checks.
+    fn dir_is_full(dir: &Path) -> bool {
+        !fs::read_dir(dir).is_ok_and(|entries| entries.count() < MAX_DIR_ENTRIES)
+    }
+
+    /// create a directory. A parent already holding MAX_DIR_ENTRIES entries
+    /// is refused with ERR_IO (see `dir_is_full`).
+    fn sys_fs_mkdir(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let dir_path = match kernel.resolve_fs_new_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
+            }
+            Err(err) if err.kind() == ErrorKind::NotADirectory => {
+                return syscall_error(proc, ERR_NOT_DIR);
+            }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        let Some(parent) = dir_path.parent() else {
+            return syscall_error(proc, ERR_PATH);
+        };
+        if dir_is_full(parent) || fs::create_dir(&dir_path).is_err() {
+            return syscall_error(proc, ERR_IO);
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_opendir(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
             Ok(val) => val,
             Err(_) => {
                 proc.regs.V[0] = ERR_INVALID;
@@ -1268,14 +2946,33 @@ pub mod kernel {
                 return SyscallOutcome::Completed;
             }
         };
-        let len = match Kernel::syscall_arg(proc, 2) {
-            Ok(val) => val as usize,
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let dir_path = match kernel.resolve_fs_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) => {
+                proc.regs.V[0] = match err.kind() {
+                    ErrorKind::NotFound => ERR_NOT_FOUND,
+                    ErrorKind::InvalidFilename => ERR_NAME_TOO_LONG,
+                    ErrorKind::PermissionDenied => ERR_PERMISSION,
+                    _ => ERR_PATH,
+                };
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+        };
+        let meta = match fs::metadata(&dir_path) {
+            Ok(val) => val,
             Err(_) => {
-                proc.regs.V[0] = ERR_INVALID;
+                proc.regs.V[0] = ERR_NOT_FOUND;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
         };
+        if !meta.is_dir() {
+            proc.regs.V[0] = ERR_NOT_DIR;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
 
         let table = match kernel.fd_tables.get_mut(&pid) {
             Some(val) => val,
@@ -1285,8 +2982,43 @@ pub mod kernel {
                 return SyscallOutcome::Completed;
             }
         };
+        if table.dirs.len() >= MAX_OPEN_DIRS {
+            proc.regs.V[0] = ERR_TOO_MANY_OPEN;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
+        let Some(handle) = next_free_handle(&table.dirs, table.next_dir) else {
+            proc.regs.V[0] = ERR_TOO_MANY_OPEN;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        };
 
-        let file = match table.fds.get_mut(&fd) {
+        let entries = match fs::read_dir(&dir_path) {
+            Ok(val) => val,
+            Err(_) => {
+                proc.regs.V[0] = ERR_IO;
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+        };
+        table.dirs.insert(handle, entries);
+        table.next_dir = handle.wrapping_add(1);
+
+        proc.regs.V[0] = handle;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_readdir(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([handle, out_ptr]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let handle = handle as u8;
+        let entries = match kernel
+            .fd_tables
+            .get_mut(&pid)
+            .and_then(|table| table.dirs.get_mut(&handle))
+        {
             Some(val) => val,
             None => {
                 proc.regs.V[0] = ERR_NOT_FOUND;
@@ -1295,47 +3027,195 @@ pub mod kernel {
             }
         };
 
-        let max_len = len.min(0xFF);
-        let mut data = vec![0u8; max_len];
-        let read = match file.read(&mut data) {
-            Ok(val) => val,
-            Err(_) => {
-                proc.regs.V[0] = ERR_IO;
+        // skip entries that vanish or cannot be stat'ed, as fs_list does.
+        for entry in entries.by_ref() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.len() > MAX_FILENAME_LEN {
+                proc.regs.V[0] = ERR_NAME_TOO_LONG;
+                proc.regs.V[0xF] = 1;
+                return SyscallOutcome::Completed;
+            }
+            if proc.write_bytes(out_ptr as u32, &dir_record(&name, &meta)).is_err() {
+                proc.regs.V[0] = ERR_INVALID;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
+            proc.regs.V[0] = 1;
+            proc.regs.V[0xF] = 0;
+            return SyscallOutcome::Completed;
+        }
+
+        proc.regs.V[0] = 0;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_closedir(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([handle]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
         };
-        data.truncate(read);
+        let handle = handle as u8;
+        let closed = kernel
+            .fd_tables
+            .get_mut(&pid)
+            .and_then(|table| table.dirs.remove(&handle));
+        if closed.is_none() {
+            proc.regs.V[0] = ERR_NOT_FOUND;
+            proc.regs.V[0xF] = 1;
+            return SyscallOutcome::Completed;
+        }
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_fs_closeall(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let (closed, ends) = match kernel.fd_tables.get_mut(&pid) {
+            Some(table) => {
+                let count = table.fds.len() + table.dirs.len();
+                table.dirs.clear();
+                (count, table.fds.drain().map(|(_, entry)| entry).collect())
+            }
+            None => (0, Vec::new()),
+        };
+        for entry in &ends {
+            kernel.close_pipe_end(entry);
+        }
+        proc.regs.V[0] = closed.min(0xFF) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// count a directory's entries (capped at MAX_DIR_ENTRIES) so the guest
+    /// can size an `fs_list` buffer. Returns the count big-endian in V0/V1.
+    fn sys_fs_count(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([path_ptr, path_len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        let path_bytes = match proc.read_bytes(path_ptr as u32, path_len as usize) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_INVALID),
+        };
+        let path_str = String::from_utf8_lossy(&path_bytes).to_string();
+        let dir_path = match kernel.resolve_fs_path(pid, &path_str) {
+            Ok(val) => val,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return syscall_error(proc, ERR_NOT_FOUND);
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidFilename => {
+                return syscall_error(proc, ERR_NAME_TOO_LONG);
+            }
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+                return syscall_error(proc, ERR_PERMISSION);
+            }
+            Err(_) => return syscall_error(proc, ERR_PATH),
+        };
+        match fs::metadata(&dir_path) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => return syscall_error(proc, ERR_NOT_DIR),
+            Err(_) => return syscall_error(proc, ERR_NOT_FOUND),
+        }
+        let entries = match fs::read_dir(&dir_path) {
+            Ok(val) => val,
+            Err(_) => return syscall_error(proc, ERR_IO),
+        };
+
+        let count = entries.filter(|entry| entry.is_ok()).take(MAX_DIR_ENTRIES).count();
+        proc.regs.V[0] = (count >> 8) as u8;
+        proc.regs.V[1] = count as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// fill a guest buffer with random bytes. Lengths past 0xFF are capped
+    /// so V0 always holds the full count.
+    fn sys_random(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let mut data = vec![0u8; (len as usize).min(0xFF)];
+        rand::rng().fill(&mut data[..]);
         if proc.write_bytes(buf as u32, &data).is_err() {
+            return syscall_error(proc, ERR_INVALID);
+        }
+
+        proc.regs.V[0] = data.len() as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    /// set a page's protection. Threads hold their own copy of the bits,
+    /// so a proc sharing its pages is refused rather than protected alone.
+    fn sys_mprotect(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([page_base, flags]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        if kernel.shares_pages(pid)
+            || flags & !(PROT_READ_ONLY as u16) != 0
+            || proc.protect_page(page_base as u32, flags as u8).is_err()
+        {
             proc.regs.V[0] = ERR_INVALID;
             proc.regs.V[0xF] = 1;
             return SyscallOutcome::Completed;
         }
-
-        proc.regs.V[0] = read.min(0xFF) as u8;
         proc.regs.V[0xF] = 0;
         SyscallOutcome::Completed
     }
 
-    fn sys_fs_close(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
-        let fd = match Kernel::syscall_arg(proc, 0) {
-            Ok(val) => val as u8,
+    fn sys_debug_print(kernel: &mut Kernel, pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([buf, len]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let data = match proc.read_bytes(buf as u32, len as usize) {
+            Ok(val) => val,
             Err(_) => {
                 proc.regs.V[0] = ERR_INVALID;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
         };
-        let table = match kernel.fd_tables.get_mut(&pid) {
-            Some(val) => val,
-            None => {
-                proc.regs.V[0] = ERR_NOT_FOUND;
+
+        // ignores console_mode on purpose: this is the host-side debug channel.
+        let text = String::from_utf8_lossy(&data);
+        kernel.log(&format!("[pid {pid}] {}", text.trim_end_matches('\n')));
+        proc.regs.V[0] = (data.len().min(0xFF)) as u8;
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_set_palette(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        // fg then bg, 3 bytes (R, G, B) each.
+        let Ok([buf, 6]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+        let rgb = match proc.read_bytes(buf as u32, 6) {
+            Ok(val) => val,
+            Err(_) => {
+                proc.regs.V[0] = ERR_INVALID;
                 proc.regs.V[0xF] = 1;
                 return SyscallOutcome::Completed;
             }
         };
-        if table.fds.remove(&fd).is_none() {
-            proc.regs.V[0] = ERR_NOT_FOUND;
+
+        let color = |c: &[u8]| ((c[0] as u32) << 16) | ((c[1] as u32) << 8) | c[2] as u32;
+        proc.display.set_palette(color(&rgb[0..3]), color(&rgb[3..6]));
+        proc.regs.V[0xF] = 0;
+        SyscallOutcome::Completed
+    }
+
+    fn sys_console_goto(_kernel: &mut Kernel, _pid: u32, proc: &mut Proc) -> SyscallOutcome {
+        let Ok([col, row]) = Kernel::read_args(proc) else {
+            return syscall_error(proc, ERR_INVALID);
+        };
+
+        if !proc.display.console_goto(col as usize, row as usize) {
+            proc.regs.V[0] = ERR_INVALID;
             proc.regs.V[0xF] = 1;
             return SyscallOutcome::Completed;
         }
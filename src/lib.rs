@@ -3,3 +3,4 @@ pub mod chip8_engine;
 pub mod proc;
 pub mod display;
 pub mod kernel;
+pub mod asm;
@@ -1,5 +1,8 @@
 pub mod proc {
-    pub use chip8_core::proc::proc::{ConsoleMode, InputMode, Registers};
+    #[allow(unused_imports)]
+    pub use chip8_core::proc::proc::{
+        ConsoleMode, Fault, InputMode, Quirks, Registers, BIG_FONT_ADDR, PROT_READ_ONLY,
+    };
 
     pub type Proc = chip8_core::proc::proc::Proc<crate::display::display::DisplayWindow>;
 }
@@ -0,0 +1,771 @@
+use std::fs;
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8_runtime::asm::asm::Assembler;
+
+fn temp_root(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mut path = std::env::temp_dir();
+    path.push(format!("chip8_runtime_{label}_{nanos}"));
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn assemble(source: &str, extra_args: &[&str]) -> (Output, PathBuf) {
+    let work = temp_root("c8asm");
+    let src_path = work.join("prog.c8s");
+    fs::write(&src_path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .args(extra_args)
+        .arg(&src_path)
+        .arg(work.join("prog.ch8"))
+        .output()
+        .unwrap();
+    (output, work)
+}
+
+// `odd` lands on 0x205: three bytes of data follow the two-byte jump.
+const ODD_TARGET: &str = "
+section code @ 0x200 {
+  label main {
+    jump odd
+  }
+  pad: byte 0x00 0x00 0x00
+  label odd {
+    return
+  }
+}
+";
+
+#[test]
+fn odd_jump_target_warns() {
+    let (output, work) = assemble(ODD_TARGET, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {stderr}");
+    assert!(
+        stderr.contains("warning: line 4: jump target 'odd' is at odd address 0x0205"),
+        "stderr: {stderr}"
+    );
+    assert!(work.join("prog.ch8").exists());
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn odd_jump_target_fails_under_strict() {
+    let (output, work) = assemble(ODD_TARGET, &["--strict"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("odd address 0x0205"), "stderr: {stderr}");
+    assert!(!work.join("prog.ch8").exists());
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn aligned_targets_assemble_quietly() {
+    let source = ODD_TARGET.replace("0x00 0x00 0x00", "0x00 0x00");
+    let (output, work) = assemble(&source, &["--strict"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn listing_shows_addresses_and_bytes() {
+    let source = "
+section code @ 0x200 {
+  label main {
+    v0 := 0x2A
+    jump main
+  }
+}
+";
+    let work = temp_root("c8asm_listing");
+    let listing = work.join("prog.lst");
+    let (output, asm_work) = assemble(source, &["--listing", listing.to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let text = fs::read_to_string(&listing).unwrap();
+    assert_eq!(
+        text,
+        "0200: 60 2A    ; v0 := 0x2A\n0202: 12 00    ; jump main\n"
+    );
+    let _ = fs::remove_dir_all(work);
+    let _ = fs::remove_dir_all(asm_work);
+}
+
+#[test]
+fn clear_emits_00e0_and_takes_no_operands() {
+    let source = "
+section code @ 0x200 {
+  clear
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0x00, 0xE0]);
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("clear", "clear v0"), &[]);
+    assert!(!output.status.success());
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn random_encodes_cxkk_and_checks_the_mask() {
+    let source = "
+section code @ 0x200 {
+  v3 := random 0x0F
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0xC3, 0x0F]);
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("0x0F", "0x100"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 3"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn draw_encodes_dxyn_and_checks_the_height() {
+    let source = "
+section code @ 0x200 {
+  draw v0, v1, 5
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0xD0, 0x15]);
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace(", 5", ", 16"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 3: value 0x0010 does not fit in a nibble"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn if_key_emits_skip_then_jump() {
+    // like `if vX == NN`, the skip opcode is the inverse of the condition.
+    let source = "
+section code @ 0x200 {
+  label loop {
+    if key v5 then jump loop
+    if -key vA then jump loop
+  }
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0xE5, 0xA1, 0x12, 0x00, 0xEA, 0x9E, 0x12, 0x00]
+    );
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("key v5 then", "key v5 than"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 4"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn timer_mnemonics_encode_fx07_fx15_fx18() {
+    let source = "
+section code @ 0x200 {
+  v2 := delay
+  delay := v3
+  buzzer := v4
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0xF2, 0x07, 0xF3, 0x15, 0xF4, 0x18]
+    );
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("delay := v3", "delay := 0x03"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 4"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn bcd_and_font_encode_fx33_fx29() {
+    let source = "
+section code @ 0x200 {
+  bcd v7
+  i := font v4
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0xF7, 0x33, 0xF4, 0x29]);
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("font v4", "font 0x04"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 4"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn register_alu_ops_encode_8xyn() {
+    let source = "
+section code @ 0x200 {
+  v1 |= v2
+  v3 &= v4
+  v5 ^= v6
+  v7 =- v8
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0x81, 0x21, 0x83, 0x42, 0x85, 0x63, 0x87, 0x87]
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn shifts_encode_8xy6_and_8xye() {
+    let source = "
+section code @ 0x200 {
+  v1 >>= v1
+  v3 <<= v3
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0x81, 0x16, 0x83, 0x3E]);
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn subtract_takes_a_register_or_an_immediate() {
+    let source = "
+section code @ 0x200 {
+  v1 -= v2
+  v1 -= 1
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0x81, 0x25, 0x71, 0xFF]);
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn const_names_a_number() {
+    let source = "
+const KEY = 0x0A
+section code @ 0x200 {
+  v0 := KEY
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0x60, 0x0A]);
+    let _ = fs::remove_dir_all(work);
+
+    // used before its definition.
+    let forward = "
+section code @ 0x200 {
+  v0 := KEY
+}
+const KEY = 0x0A
+";
+    let (output, work) = assemble(forward, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("constant 'KEY' used before its definition"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+
+    // collides with a label.
+    let clash = "
+const main = 1
+section code @ 0x200 {
+  label main {
+    return
+  }
+}
+";
+    let (output, work) = assemble(clash, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("collides with a constant"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn org_moves_forward_and_rejects_moving_back() {
+    let source = "
+section code @ 0x200 {
+  return
+  org 0x208
+  byte 0xAB
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0x00, 0xEE, 0, 0, 0, 0, 0, 0, 0xAB]
+    );
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("0x208", "0x201"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 4: org 0x0201 is behind"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn include_splices_another_file() {
+    let work = temp_root("c8asm_include");
+    fs::write(
+        work.join("main.c8s"),
+        "section code @ 0x200 {\n  call helper\n  include \"lib.c8s\"\n}\n",
+    )
+    .unwrap();
+    fs::write(work.join("lib.c8s"), "label helper {\n  return\n}\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg(work.join("main.c8s"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("main.ch8")).unwrap(), [0x22, 0x02, 0x00, 0xEE]);
+
+    // errors name the included file and its own line.
+    fs::write(work.join("lib.c8s"), "label helper {\n  bogus\n}\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg(work.join("main.c8s"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("lib.c8s line 2:"), "stderr: {stderr}");
+
+    fs::write(work.join("lib.c8s"), "include \"main.c8s\"\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg(work.join("main.c8s"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("lib.c8s line 1: include cycle through main.c8s"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn macros_expand_with_arguments() {
+    let source = "
+macro bump reg {
+  reg += 1
+  save reg
+}
+macro twice reg {
+  bump reg
+  bump reg
+}
+section code @ 0x200 {
+  bump v2
+  twice v3
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0x72, 0x01, 0xF2, 0x55, 0x73, 0x01, 0xF3, 0x55, 0x73, 0x01, 0xF3, 0x55]
+    );
+    let _ = fs::remove_dir_all(work);
+
+    let recursive = "
+macro forever reg {
+  forever reg
+}
+section code @ 0x200 {
+  forever v0
+}
+";
+    let (output, work) = assemble(recursive, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 6: macro 'forever' nests deeper than 16"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn short_list_flag_matches_listing() {
+    let source = "
+section code @ 0x200 {
+  clear
+  byte 0x01 0x02 0x03
+}
+";
+    let work = temp_root("c8asm_list");
+    let listing = work.join("prog.lst");
+    let (output, asm_work) = assemble(source, &["-l", listing.to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let text = fs::read_to_string(&listing).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines, ["0200: 00 E0    ; clear", "0202: 01 02 03    ; byte 0x01 0x02 0x03"]);
+    let _ = fs::remove_dir_all(work);
+    let _ = fs::remove_dir_all(asm_work);
+}
+
+#[test]
+fn map_lists_labels_and_section_ends() {
+    let source = "
+section code @ 0x200 {
+  label loop {
+    jump loop
+  }
+  label done {
+    return
+  }
+}
+";
+    let work = temp_root("c8asm_map");
+    let map = work.join("prog.map");
+    let (output, asm_work) = assemble(source, &["--map", map.to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read_to_string(&map).unwrap(),
+        "loop 0x0200\ndone 0x0202\nsection code 0x0204\n"
+    );
+    let _ = fs::remove_dir_all(work);
+    let _ = fs::remove_dir_all(asm_work);
+}
+
+#[test]
+fn incbin_embeds_file_bytes_at_the_current_address() {
+    let work = temp_root("c8asm_incbin");
+    fs::write(work.join("sprite.bin"), [0xF0, 0x90, 0x90, 0xF0]).unwrap();
+    fs::write(
+        work.join("main.c8s"),
+        "section code @ 0x200 {\n  return\n  incbin \"sprite.bin\"\n  byte 0x01\n}\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg(work.join("main.c8s"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("main.ch8")).unwrap(),
+        [0x00, 0xEE, 0xF0, 0x90, 0x90, 0xF0, 0x01]
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn character_literals_are_numbers() {
+    let source = "
+section code @ 0x200 {
+  v0 := 'A'
+  v1 := ';'
+  v2 := '\\n'
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0x60, 0x41, 0x61, b';', 0x62, b'\n']
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn binary_literals_parse() {
+    let source = "
+section code @ 0x200 {
+  byte 0b11110000 0B1
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(work.join("prog.ch8")).unwrap(), [0xF0, 0x01]);
+    let _ = fs::remove_dir_all(work);
+
+    let (output, work) = assemble(&source.replace("0B1", "0b102"), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("line 3, col 19: invalid binary literal '0b102'"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn label_offsets_resolve_after_the_label() {
+    let source = "
+section code @ 0x200 {
+  i := data + 4
+  jump data - 2
+  data: byte 1 2 3 4 5
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0xA2, 0x08, 0x12, 0x02, 1, 2, 3, 4, 5]
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn disasm_decodes_an_assembled_program() {
+    let source = "
+section code @ 0x200 {
+  clear
+  v3 := random 0x0F
+  draw v0, v1, 5
+  v1 |= v2
+  bcd v7
+  if v1 == 0x02 then jump 0x200
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg("--disasm")
+        .arg(work.join("prog.ch8"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "0200: clear",
+            "0202: v3 := random 0x0F",
+            "0204: draw v0, v1, 5",
+            "0206: v1 |= v2",
+            "0208: bcd v7",
+            "020A: word 0x4102    ; skip if v1 != 0x02",
+            "020C: jump 0x200",
+        ]
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn ihex_output_decodes_to_the_raw_rom() {
+    let source = "
+section code @ 0x200 {
+  clear
+  ascii \"intel hex records hold sixteen bytes\"
+  return
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success());
+    let raw = fs::read(work.join("prog.ch8")).unwrap();
+    let (output, hex_work) = assemble(source, &["--format", "ihex"]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let text = fs::read_to_string(hex_work.join("prog.ch8")).unwrap();
+
+    let mut decoded = Vec::new();
+    let mut next_addr = 0x200;
+    let mut saw_eof = false;
+    for line in text.lines() {
+        let hex = line.strip_prefix(':').unwrap();
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect();
+        let sum = bytes.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+        assert_eq!(sum, 0, "bad checksum in {line}");
+        let len = bytes[0] as usize;
+        match bytes[3] {
+            0x00 => {
+                assert_eq!(u16::from_be_bytes([bytes[1], bytes[2]]), next_addr);
+                decoded.extend_from_slice(&bytes[4..4 + len]);
+                next_addr += len as u16;
+            }
+            0x01 => saw_eof = true,
+            other => panic!("unexpected record type {other}"),
+        }
+    }
+    assert!(saw_eof);
+    assert_eq!(decoded, raw);
+    let _ = fs::remove_dir_all(work);
+    let _ = fs::remove_dir_all(hex_work);
+}
+
+#[test]
+fn repeat_emits_its_body_n_times() {
+    let source = "
+const COUNT = 2
+section code @ 0x200 {
+  repeat 3 { byte 0xFF }
+  repeat COUNT {
+    byte 0x01
+    byte 0x02
+  }
+  repeat 0 {
+    byte 0xEE
+  }
+  byte 0x00
+}
+";
+    let (output, work) = assemble(source, &[]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read(work.join("prog.ch8")).unwrap(),
+        [0xFF, 0xFF, 0xFF, 0x01, 0x02, 0x01, 0x02, 0x00]
+    );
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn align_pads_to_the_next_boundary() {
+    let source = "section code @ 0x200 {\n  byte 1, 2\n  align 16\nhere:\n  byte 3\n}\n";
+    let mut assembler = Assembler::new();
+    let rom = assembler.assemble(source).unwrap();
+    assert_eq!(rom.len(), 17);
+    assert_eq!(rom[..2], [1, 2]);
+    assert!(rom[2..16].iter().all(|&byte| byte == 0));
+    assert_eq!(rom[16], 3);
+    assert!(assembler.symbol_map().contains("here 0x0210"), "{}", assembler.symbol_map());
+
+    let err = Assembler::new()
+        .assemble(&source.replace("align 16", "align 12"))
+        .unwrap_err();
+    assert!(err.contains("line 3: align 12 is not a power of two"), "{err}");
+}
+
+#[test]
+fn conditional_assembly_follows_constants() {
+    let source = "const DEBUG = 1
+section code @ 0x200 {
+.if DEBUG
+  v0 := 0xDB
+  .if DEBUG == 2
+    not even assembly
+  .else
+    v1 := 1
+  .endif
+.else
+  v0 := 0x00
+.endif
+  return
+}
+";
+    let debug = Assembler::new().assemble(source).unwrap();
+    assert_eq!(debug, [0x60, 0xDB, 0x61, 0x01, 0x00, 0xEE]);
+
+    let release = Assembler::new()
+        .assemble(&source.replace("DEBUG = 1", "DEBUG = 0"))
+        .unwrap();
+    assert_eq!(release, [0x60, 0x00, 0x00, 0xEE]);
+
+    let err = Assembler::new()
+        .assemble(&source.replace("  return\n", "  return\n.if 1\n"))
+        .unwrap_err();
+    assert!(err.contains("unclosed .if at end of file"), "{err}");
+}
+
+#[test]
+fn inactive_branches_skip_includes_and_macros() {
+    let source = "const FANCY = 0
+.if FANCY
+include \"missing.c8s\"
+macro twice reg {
+  reg += 2
+}
+.else
+macro twice reg {
+  reg += 1
+  reg += 1
+}
+.endif
+section code @ 0x200 {
+.if FANCY
+  twice v0, v1, v2
+.endif
+  twice v3
+}
+";
+    let rom = Assembler::new().assemble(source).unwrap();
+    assert_eq!(rom, [0x73, 0x01, 0x73, 0x01]);
+
+    let err = Assembler::new()
+        .assemble(&source.replace("FANCY = 0", "FANCY = 1"))
+        .unwrap_err();
+    assert!(err.contains("failed to include missing.c8s"), "{err}");
+}
+
+#[test]
+fn errors_name_the_column_of_the_bad_token() {
+    let (output, work) = assemble(
+        "section code @ 0x200 {\n  v0 := 1\n  v1 |= 5\n  draw v0, v1, 3\n}\n",
+        &[],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 3, col 9: expected register like v0..vF"), "stderr: {stderr}");
+    let _ = fs::remove_dir_all(work);
+
+    let err = Assembler::new().assemble("section code @ 0x200 {\n    v2 := ?\n}\n").unwrap_err();
+    assert!(err.starts_with("line 2, col 11: unexpected character '?'"), "{err}");
+}
+
+#[test]
+fn library_assembles_a_source_string() {
+    let mut assembler = Assembler::new();
+    let rom = assembler
+        .assemble("section code @ 0x200 {\n  v0 := 0x2A\n  return\n}\n")
+        .unwrap();
+    assert_eq!(rom, [0x60, 0x2A, 0x00, 0xEE]);
+    assert!(assembler.warnings.is_empty());
+
+    let err = Assembler::new().assemble("section code @ 0x200 {\n  bogus\n}\n").unwrap_err();
+    assert!(err.starts_with("line 2:"), "{err}");
+}
+
+#[test]
+fn stdin_source_matches_the_library() {
+    let source = "section code @ 0x200 {\n  v1 := 'A'\n  draw v0, v1, 3\n}\n";
+    let expected = Assembler::new().assemble(source).unwrap();
+
+    let work = temp_root("c8asm_stdin");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .args(["-", "-o"])
+        .arg(work.join("out.ch8"))
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(source.as_bytes()).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(fs::read(work.join("out.ch8")).unwrap(), expected);
+
+    // no output path to derive from stdin.
+    let output = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("requires -o/--out"));
+    let _ = fs::remove_dir_all(work);
+}
@@ -0,0 +1,143 @@
+use chip8_runtime::display::display::{
+    DisplayConfig, DisplayMode, DisplayWindow, CHIP8_PIXEL_SCALE, SCALE,
+};
+use chip8_runtime::proc::proc::Registers;
+
+fn console_display() -> DisplayWindow {
+    let mut display = DisplayWindow::headless();
+    display.set_mode(DisplayMode::Console);
+    display
+}
+
+#[test]
+fn console_tab_advances_to_next_stop() {
+    let mut display = console_display();
+    display.console_write(b"ab\tc\td");
+
+    assert_eq!(display.console_cell(0, 0), Some(b'a'));
+    assert_eq!(display.console_cell(1, 0), Some(b'b'));
+    assert_eq!(display.console_cell(8, 0), Some(b'c'));
+    assert_eq!(display.console_cell(16, 0), Some(b'd'));
+    assert_eq!(display.console_cursor(), (17, 0));
+}
+
+#[test]
+fn console_tab_width_is_configurable() {
+    let mut display = console_display();
+    display.set_tab_width(4);
+    display.console_write(b"x\ty\n\tz");
+
+    assert_eq!(display.console_cell(4, 0), Some(b'y'));
+    assert_eq!(display.console_cell(4, 1), Some(b'z'));
+}
+
+#[test]
+fn console_carriage_return_and_control_bytes() {
+    let mut display = console_display();
+    display.console_write(b"abc\rX\x07Y");
+
+    assert_eq!(display.console_cell(0, 0), Some(b'X'));
+    assert_eq!(display.console_cell(1, 0), Some(b'Y'));
+    assert_eq!(display.console_cell(2, 0), Some(b'c'));
+}
+
+#[test]
+fn palette_change_keeps_lit_pixels_and_collisions() {
+    let mut display = DisplayWindow::headless();
+    let mut regs = Registers::default();
+    display.draw_sprite(&mut regs, &[0x80], 0, 0);
+    display.set_palette(0x00FF00, 0x101010);
+
+    assert_eq!(display.buf[0], 0x00FF00);
+    assert_eq!(display.buf[display.buf.len() - 1], 0x101010);
+
+    // erasing the recolored pixel still reports a collision.
+    display.draw_sprite(&mut regs, &[0x80], 0, 0);
+    assert_eq!(regs.V[0xF], 1);
+    assert_eq!(display.buf[0], 0x101010);
+}
+
+#[test]
+fn logical_pixels_report_the_chip8_grid() {
+    let mut display = DisplayWindow::headless();
+    let mut regs = Registers::default();
+    // 0xA0 lights columns 0 and 2 of the sprite's one row.
+    display.draw_sprite(&mut regs, &[0xA0], 10, 5);
+
+    let pixels = display.logical_pixels();
+    assert_eq!(pixels.len(), 64 * 32);
+    let lit: Vec<(usize, usize)> = (0..pixels.len())
+        .filter(|&pos| pixels[pos])
+        .map(|pos| (pos % 64, pos / 64))
+        .collect();
+    assert_eq!(lit, [(10, 5), (12, 5)]);
+
+    display.set_hires(true);
+    display.draw_sprite(&mut regs, &[0x80], 127, 63);
+    let pixels = display.logical_pixels();
+    assert_eq!(pixels.len(), 128 * 64);
+    assert_eq!(pixels.iter().filter(|&&lit| lit).count(), 1);
+    assert!(pixels[63 * 128 + 127]);
+}
+
+#[test]
+fn configured_scale_and_colors_drive_drawing() {
+    let config = DisplayConfig { scale: 1, fg: 0x33CC66, bg: 0x202020 };
+    let mut display = DisplayWindow::headless_with_config(config);
+    let mut regs = Registers::default();
+    assert_eq!(display.window_size(), (640, 320));
+    assert_eq!(display.buf.len(), 640 * 320);
+
+    display.clear_screen();
+    assert!(display.buf.iter().all(|&px| px == 0x202020));
+
+    // scale 1: buffer index is the console pixel index.
+    display.draw_sprite(&mut regs, &[0x80], 1, 1);
+    let at = |x: usize, y: usize| y * 640 + x;
+    let (x0, y0) = (CHIP8_PIXEL_SCALE, CHIP8_PIXEL_SCALE);
+    assert_eq!(display.buf[at(x0, y0)], 0x33CC66);
+    assert_eq!(display.buf[at(x0 + CHIP8_PIXEL_SCALE - 1, y0 + CHIP8_PIXEL_SCALE - 1)], 0x33CC66);
+    assert_eq!(display.buf[at(x0 + CHIP8_PIXEL_SCALE, y0)], 0x202020);
+    assert_eq!(display.palette(), (0x33CC66, 0x202020));
+}
+
+// index into the scaled buffer for a logical (640x320) pixel.
+fn buf_index(x: usize, y: usize) -> usize {
+    y * SCALE * 640 * SCALE + x * SCALE
+}
+
+#[test]
+fn debug_overlay_marks_only_the_changed_pixel() {
+    let mut display = DisplayWindow::headless();
+    let mut regs = Registers::default();
+    display.draw_sprite(&mut regs, &[0x80], 0, 0);
+    display.set_debug_overlay(true);
+    assert!(display.overlay_changes().is_empty());
+
+    // changes are reported per chip-8 pixel, not per window pixel.
+    display.draw_sprite(&mut regs, &[0x80], 3, 2);
+    assert_eq!(display.overlay_changes(), [(3, 2)]);
+    let (x0, y0) = (3 * CHIP8_PIXEL_SCALE, 2 * CHIP8_PIXEL_SCALE);
+    assert_eq!(display.buf[buf_index(x0, y0)], 0x00FF00);
+    // the pixel lit before the overlay came on keeps the normal color.
+    assert_eq!(display.buf[buf_index(0, 0)], 0xFFFFFF);
+
+    // a second draw in the same frame adds to the first.
+    display.draw_sprite(&mut regs, &[0x80], 10, 5);
+    assert_eq!(display.overlay_changes(), [(3, 2), (10, 5)]);
+    assert_eq!(display.buf[buf_index(x0, y0)], 0x00FF00);
+
+    // present ends the frame: nothing has changed in the next one yet.
+    display.present();
+    assert!(display.overlay_changes().is_empty());
+    assert_eq!(display.buf[buf_index(x0, y0)], 0xFFFFFF);
+
+    // erasing it is a change too, tinted differently.
+    display.draw_sprite(&mut regs, &[0x80], 3, 2);
+    assert_eq!(display.overlay_changes(), [(3, 2)]);
+    assert_eq!(display.buf[buf_index(x0, y0)], 0xFF0000);
+
+    display.set_debug_overlay(false);
+    assert!(display.overlay_changes().is_empty());
+    assert_eq!(display.buf[buf_index(x0, y0)], 0x000000);
+}
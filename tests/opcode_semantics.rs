@@ -1,10 +1,13 @@
 use std::sync::{Arc, Mutex};
 
-use chip8_runtime::display::display::{DisplayWindow, CHIP8_PIXEL_SCALE, SCALE};
+use chip8_core::device::device::{DisplayDevice, MmioDevice};
+use chip8_core::proc::proc::Proc as CoreProc;
+
+use chip8_runtime::display::display::{DisplayMode, DisplayWindow, CHIP8_PIXEL_SCALE, SCALE};
 use std::io::{Error, ErrorKind};
 
 use chip8_runtime::kernel::kernel::SyscallOutcome;
-use chip8_runtime::proc::proc::Proc;
+use chip8_runtime::proc::proc::{Fault, Proc, Registers, BIG_FONT_ADDR};
 use chip8_runtime::shared_memory::shared_memory::SharedMemory;
 
 fn make_headless_display() -> DisplayWindow {
@@ -271,6 +274,27 @@ fn opcode_bnnn_adds_v0_to_jump() {
     assert_eq!(proc.regs.PC, 0x205);
 }
 
+#[test]
+fn opcode_bnnn_wraps_to_12_bits() {
+    let mut proc = new_headless_proc();
+    proc.regs.V[0] = 0xFF;
+    exec_opcode(&mut proc, 0xBFFF);
+    assert_eq!(proc.regs.PC, 0x0FE);
+}
+
+#[test]
+fn opcode_bnnn_jump_quirk_uses_vx() {
+    for (quirk, target) in [(false, 0x244), (true, 0x248)] {
+        let mut proc = new_headless_proc();
+        proc.quirks.jump_uses_vx = quirk;
+        proc.regs.V[0] = 4;
+        proc.regs.V[2] = 8;
+        // X = 2, the high nibble of NNN = 0x240.
+        exec_opcode(&mut proc, 0xB240);
+        assert_eq!(proc.regs.PC, target, "jump_uses_vx = {quirk}");
+    }
+}
+
 #[test]
 fn opcode_cxkk_masks_random() {
     let mut proc = new_headless_proc();
@@ -299,6 +323,87 @@ fn opcode_dxyn_draws_and_collides() {
     assert_eq!(proc.regs.V[0xF], 1);
 }
 
+#[test]
+fn opcode_00cn_and_00fb_00fc_scroll_the_screen() {
+    let mut proc = new_headless_proc();
+    proc.regs.I = 0x300;
+    write_byte(&mut proc, 0x300, 0x80);
+    proc.regs.V[0] = 10;
+    proc.regs.V[1] = 5;
+    exec_opcode(&mut proc, 0xD011);
+
+    // buffer index of the top-left corner of a chip-8 pixel.
+    let cell = CHIP8_PIXEL_SCALE * SCALE;
+    let at = |x: usize, y: usize| y * cell * 640 * SCALE + x * cell;
+    let lit = count_on_pixels(&proc);
+
+    exec_opcode(&mut proc, 0x00C1);
+    assert_eq!(proc.regs.PC, 0x204);
+    assert_eq!(proc.display.buf[at(10, 5)], 0);
+    assert_ne!(proc.display.buf[at(10, 6)], 0);
+    assert_eq!(count_on_pixels(&proc), lit);
+
+    exec_opcode(&mut proc, 0x00FB);
+    assert_ne!(proc.display.buf[at(14, 6)], 0);
+    exec_opcode(&mut proc, 0x00FC);
+    exec_opcode(&mut proc, 0x00FC);
+    assert_ne!(proc.display.buf[at(6, 6)], 0);
+    assert_eq!(count_on_pixels(&proc), lit);
+
+    // scrolling off the edge clears the pixel rather than wrapping.
+    exec_opcode(&mut proc, 0x00CF);
+    exec_opcode(&mut proc, 0x00CF);
+    assert_eq!(count_on_pixels(&proc), 0);
+}
+
+#[test]
+fn opcode_00ff_switches_to_hires() {
+    let mut proc = new_headless_proc();
+    exec_opcode(&mut proc, 0x00FF);
+    assert!(proc.display.hires());
+    assert_eq!(proc.display.resolution(), (128, 64));
+
+    proc.regs.I = 0x300;
+    write_byte(&mut proc, 0x300, 0x80);
+    proc.regs.V[0] = 100;
+    proc.regs.V[1] = 40;
+    exec_opcode(&mut proc, 0xD011);
+
+    // one hi-res pixel is half a lo-res one; x=100 would wrap in lo-res.
+    let cell = CHIP8_PIXEL_SCALE / 2 * SCALE;
+    assert_eq!(count_on_pixels(&proc), cell * cell);
+    assert_ne!(proc.display.buf[40 * cell * 640 * SCALE + 100 * cell], 0);
+    assert_eq!(proc.regs.V[0xF], 0);
+
+    // back to lo-res clears the screen.
+    exec_opcode(&mut proc, 0x00FE);
+    assert!(!proc.display.hires());
+    assert_eq!(count_on_pixels(&proc), 0);
+}
+
+#[test]
+fn opcode_dxy0_draws_a_16x16_sprite_in_hires() {
+    let mut proc = new_headless_proc();
+    exec_opcode(&mut proc, 0x00FF);
+    proc.regs.I = 0x300;
+    // 16 rows of 0xFFFF: every bit lit.
+    for offset in 0..32 {
+        write_byte(&mut proc, 0x300 + offset, 0xFF);
+    }
+    proc.regs.V[0] = 120;
+    proc.regs.V[1] = 0;
+
+    exec_opcode(&mut proc, 0xD010);
+    let cell = CHIP8_PIXEL_SCALE / 2 * SCALE;
+    assert_eq!(count_on_pixels(&proc), 16 * 16 * cell * cell);
+    assert_eq!(proc.regs.V[0xF], 0);
+
+    proc.regs.PC = 0x202;
+    exec_opcode(&mut proc, 0xD010);
+    assert_eq!(count_on_pixels(&proc), 0);
+    assert_eq!(proc.regs.V[0xF], 1);
+}
+
 #[test]
 fn opcode_ex9e_skips_if_key_pressed() {
     let mut proc = new_headless_proc();
@@ -326,15 +431,29 @@ fn opcode_fx07_reads_delay_timer() {
 }
 
 #[test]
-fn opcode_fx0a_blocks_until_key() {
+fn opcode_fx0a_waits_for_press_then_release() {
     let mut proc = new_headless_proc();
     exec_opcode(&mut proc, 0xF20A);
     assert_eq!(proc.regs.PC, 0x200);
 
+    // down: the key is latched but the wait continues while it is held.
     proc.display.key_down[0x5] = true;
     exec_opcode(&mut proc, 0xF20A);
+    assert_eq!(proc.regs.PC, 0x200);
+    exec_opcode(&mut proc, 0xF20A);
+    assert_eq!(proc.regs.PC, 0x200);
+
+    // up: the wait completes with the latched key.
+    proc.display.key_down[0x5] = false;
+    exec_opcode(&mut proc, 0xF20A);
     assert_eq!(proc.regs.V[2], 0x5);
     assert_eq!(proc.regs.PC, 0x202);
+
+    // a key held when the next FX0A starts is only taken after its release.
+    proc.display.key_down[0x5] = true;
+    exec_opcode(&mut proc, 0xF30A);
+    exec_opcode(&mut proc, 0xF30A);
+    assert_eq!(proc.regs.PC, 0x202);
 }
 
 #[test]
@@ -370,6 +489,19 @@ fn opcode_fx29_sets_sprite_address() {
     assert_eq!(proc.regs.I, 0x32);
 }
 
+#[test]
+fn opcode_fx30_sets_big_font_address() {
+    let mut proc = new_headless_proc();
+    proc.load_program_bytes(&[]).unwrap();
+    proc.regs.V[2] = 3;
+    exec_opcode(&mut proc, 0xF230);
+    assert_eq!(proc.regs.I, BIG_FONT_ADDR + 30);
+    assert_eq!(
+        proc.read_bytes(proc.regs.I as u32, 10).unwrap(),
+        [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF]
+    );
+}
+
 #[test]
 fn opcode_fx33_stores_bcd() {
     let mut proc = new_headless_proc();
@@ -419,6 +551,58 @@ fn opcode_fx65_loads_registers_and_increments_i() {
     assert_eq!(proc.regs.I, 0x303);
 }
 
+#[test]
+fn opcode_fx75_fx85_save_and_restore_rpl_flags() {
+    let mut proc = new_headless_proc();
+    proc.regs.V[..4].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+    exec_opcode(&mut proc, 0xF375);
+    assert_eq!(proc.flags[..4], [0x11, 0x22, 0x33, 0x44]);
+    assert_eq!(proc.flags[4..], [0; 4]);
+
+    proc.regs.V[..4].fill(0xEE);
+    exec_opcode(&mut proc, 0xF385);
+    assert_eq!(proc.regs.V[..4], [0x11, 0x22, 0x33, 0x44]);
+    assert_eq!(proc.regs.PC, 0x204);
+
+    // X past 7 is clamped to the eight flags.
+    proc.regs.V[8] = 0x99;
+    exec_opcode(&mut proc, 0xFF75);
+    assert_eq!(proc.flags[..4], [0x11, 0x22, 0x33, 0x44]);
+}
+
+#[test]
+fn shift_quirk_reads_vy() {
+    for (quirk, shifted_right, shifted_left) in [(false, 0x40, 0x00), (true, 0x07, 0x1E)] {
+        let mut proc = new_headless_proc();
+        proc.quirks.shift_uses_vy = quirk;
+        proc.regs.V[1] = 0x81;
+        proc.regs.V[2] = 0x0F;
+        exec_opcode(&mut proc, 0x8126);
+        assert_eq!(proc.regs.V[1], shifted_right, "shift_uses_vy = {quirk}");
+        assert_eq!(proc.regs.V[0xF], 1);
+
+        proc.regs.V[1] = 0x80;
+        exec_opcode(&mut proc, 0x812E);
+        assert_eq!(proc.regs.V[1], shifted_left, "shift_uses_vy = {quirk}");
+        assert_eq!(proc.regs.V[0xF], u8::from(!quirk));
+    }
+}
+
+#[test]
+fn load_store_quirk_can_leave_i_unchanged() {
+    for (quirk, end) in [(true, 0x303), (false, 0x300)] {
+        let mut proc = new_headless_proc();
+        proc.quirks.load_store_increments_i = quirk;
+        proc.regs.I = 0x300;
+        exec_opcode(&mut proc, 0xF255);
+        assert_eq!(proc.regs.I, end, "load_store_increments_i = {quirk}");
+
+        proc.regs.I = 0x300;
+        exec_opcode(&mut proc, 0xF265);
+        assert_eq!(proc.regs.I, end, "load_store_increments_i = {quirk}");
+    }
+}
+
 #[test]
 fn virtual_translation_spans_pages() {
     let mut proc = new_headless_proc_with_pages(2);
@@ -429,3 +613,290 @@ fn virtual_translation_spans_pages() {
     assert_eq!(proc.read_u8(0x0FFF).unwrap(), 0xAA);
     assert_eq!(proc.read_u8(0x1000).unwrap(), 0x55);
 }
+
+#[test]
+fn aligned_pc_policy_faults_after_odd_jump() {
+    let mut proc = new_headless_proc();
+    proc.require_aligned_pc = true;
+    exec_opcode(&mut proc, 0x1203);
+    assert_eq!(proc.regs.PC, 0x203);
+
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Faulted);
+    assert_eq!(proc.fault, Some(Fault::MisalignedPc { pc: 0x203 }));
+    assert_eq!(proc.regs.PC, 0x203);
+}
+
+#[test]
+fn runaway_recursion_faults_at_the_stack_floor() {
+    let mut proc = new_headless_proc();
+    proc.stack_floor = 0x0F00;
+    write_opcode(&mut proc, 0x200, 0x2200); // call itself forever
+
+    let mut calls = 0;
+    while proc.step(0, |_, _| Ok(SyscallOutcome::Completed)) == SyscallOutcome::Completed {
+        calls += 1;
+        assert!(calls <= 0x80, "stack ran past its floor");
+    }
+    assert_eq!(calls, 0x80);
+    assert_eq!(proc.fault, Some(Fault::StackOverflow { sp: 0x0F00 }));
+    assert_eq!((proc.regs.SP, proc.regs.PC), (0x0F00, 0x200));
+}
+
+#[test]
+fn return_on_an_empty_stack_faults() {
+    let mut proc = new_headless_proc();
+    write_opcode(&mut proc, 0x200, 0x00EE);
+
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Faulted);
+    assert_eq!(proc.fault, Some(Fault::StackUnderflow { sp: 0x1000 }));
+    assert_eq!(proc.regs.PC, 0x200);
+}
+
+#[test]
+fn return_on_an_empty_stack_faults_after_growing() {
+    let mut proc = new_headless_proc();
+    proc.grow(1).unwrap();
+    write_opcode(&mut proc, 0x200, 0x00EE);
+
+    // the new page above the stack holds zeros, not return addresses.
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Faulted);
+    assert_eq!(proc.fault, Some(Fault::StackUnderflow { sp: 0x1000 }));
+    assert_eq!(proc.regs.PC, 0x200);
+}
+
+#[test]
+fn trap_unknown_faults_on_an_undefined_opcode() {
+    let mut proc = new_headless_proc();
+    proc.trap_unknown = true;
+    proc.regs.V[1] = 0x12;
+    write_opcode(&mut proc, 0x200, 0x812F);
+
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Faulted);
+    assert_eq!(proc.fault, Some(Fault::IllegalOpcode { pc: 0x200, opcode: 0x812F }));
+    assert_eq!(proc.regs.PC, 0x200);
+    assert_eq!(proc.regs.V[1], 0x12);
+
+    // permissive by default: the same opcode is skipped.
+    proc.trap_unknown = false;
+    proc.fault = None;
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Completed);
+    assert_eq!(proc.fault, None);
+}
+
+#[test]
+fn odd_pc_is_fetched_when_policy_is_off() {
+    let mut proc = new_headless_proc();
+    exec_opcode(&mut proc, 0x1203);
+    write_opcode(&mut proc, 0x203, 0x6A42);
+
+    let outcome = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    assert_eq!(outcome, SyscallOutcome::Completed);
+    assert_eq!(proc.regs.V[0xA], 0x42);
+    assert_eq!(proc.fault, None);
+}
+
+#[test]
+fn step_back_restores_registers_and_pc() {
+    let mut proc = new_headless_proc();
+    proc.enable_undo(4);
+    proc.regs.V[3] = 0x11;
+    exec_opcode(&mut proc, 0x6342);
+    assert_eq!(proc.regs.V[3], 0x42);
+    assert_eq!(proc.regs.PC, 0x202);
+
+    assert!(proc.step_back());
+    assert_eq!(proc.regs.V[3], 0x11);
+    assert_eq!(proc.regs.PC, 0x200);
+    assert!(!proc.step_back());
+}
+
+#[test]
+fn step_back_restores_memory_written_by_the_step() {
+    let mut proc = new_headless_proc();
+    proc.enable_undo(4);
+    proc.regs.I = 0x300;
+    write_byte(&mut proc, 0x300, 0xAA);
+    write_byte(&mut proc, 0x301, 0xBB);
+    proc.regs.V[0] = 1;
+    proc.regs.V[1] = 2;
+    exec_opcode(&mut proc, 0xF155);
+    assert_eq!(proc.read_bytes(0x300, 2).unwrap(), vec![1, 2]);
+
+    assert!(proc.step_back());
+    assert_eq!(proc.read_bytes(0x300, 2).unwrap(), vec![0xAA, 0xBB]);
+    assert_eq!(proc.regs.I, 0x300);
+}
+
+struct RecordingDevice {
+    writes: Arc<Mutex<Vec<(u32, u8)>>>,
+}
+
+impl MmioDevice for RecordingDevice {
+    fn read(&mut self, offset: u32) -> u8 {
+        0xC0 | offset as u8
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        self.writes.lock().unwrap().push((offset, value));
+    }
+}
+
+#[test]
+fn mmio_write_reaches_handler_not_memory() {
+    let mut proc = new_headless_proc();
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let device = RecordingDevice { writes: Arc::clone(&writes) };
+    proc.map_mmio(0x800..0x804, device).unwrap();
+
+    proc.regs.I = 0x801;
+    proc.regs.V[0] = 0x5A;
+    exec_opcode(&mut proc, 0xF055);
+    assert_eq!(*writes.lock().unwrap(), [(1, 0x5A)]);
+
+    let phys = proc.translate(0x801).unwrap();
+    assert_eq!(proc.mem.lock().unwrap().read(phys, 1).unwrap(), vec![0]);
+    assert_eq!(proc.read_u8(0x801).unwrap(), 0xC1);
+    assert!(proc.map_mmio(0x803..0x808, RecordingDevice { writes }).is_err());
+}
+
+#[test]
+fn checksum_survives_copy_round_trip_and_detects_a_flipped_byte() {
+    let mut original = new_headless_proc_with_pages(2);
+    original.load_program_bytes(&[0x6A, 0x42, 0x12, 0x00]).unwrap();
+    exec_opcode(&mut original, 0x6A42);
+
+    // save: memory image plus registers; load: into a proc on a fresh arena.
+    let image = original.read_bytes(0, original.vm_size as usize).unwrap();
+    let mut restored = new_headless_proc_with_pages(2);
+    restored.write_bytes(0, &image).unwrap();
+    restored.regs = original.regs;
+    assert_eq!(restored.checksum(), original.checksum());
+
+    let byte = restored.read_u8(0x1234).unwrap();
+    restored.write_u8(0x1234, byte ^ 0x01).unwrap();
+    assert_ne!(restored.checksum(), original.checksum());
+
+    // registers are covered too.
+    restored.write_u8(0x1234, byte).unwrap();
+    restored.regs.V[0xA] ^= 0x01;
+    assert_ne!(restored.checksum(), original.checksum());
+}
+
+// headless display that counts how often the window would be updated.
+struct CountingDisplay {
+    inner: DisplayWindow,
+    presents: Arc<Mutex<usize>>,
+}
+
+impl DisplayDevice for CountingDisplay {
+    fn poll_input(&mut self, capture_text: bool) {
+        self.inner.poll_input(capture_text);
+    }
+
+    fn clear_screen(&mut self) {
+        self.inner.clear_screen();
+    }
+
+    fn draw_sprite(&mut self, regs: &mut Registers, sprite: &[u8], x_pos: u32, y_pos: u32) {
+        self.inner.draw_sprite(regs, sprite, x_pos, y_pos);
+    }
+
+    fn scroll_down(&mut self, rows: u8) {
+        DisplayDevice::scroll_down(&mut self.inner, rows);
+    }
+
+    fn scroll_right(&mut self) {
+        DisplayDevice::scroll_right(&mut self.inner);
+    }
+
+    fn scroll_left(&mut self) {
+        DisplayDevice::scroll_left(&mut self.inner);
+    }
+
+    fn set_hires(&mut self, on: bool) {
+        self.inner.set_hires(on);
+    }
+
+    fn set_buzzer(&mut self, on: bool) {
+        self.inner.set_buzzer(on);
+    }
+
+    fn hires(&self) -> bool {
+        self.inner.hires()
+    }
+
+    fn logical_pixels(&self) -> Vec<bool> {
+        self.inner.logical_pixels()
+    }
+
+    fn is_key_down(&self, key: u8) -> bool {
+        DisplayDevice::is_key_down(&self.inner, key)
+    }
+
+    fn last_key(&self) -> Option<u8> {
+        DisplayDevice::last_key(&self.inner)
+    }
+
+    fn drain_text_input(&mut self) -> Vec<u8> {
+        self.inner.drain_text_input()
+    }
+
+    fn console_write(&mut self, data: &[u8]) {
+        self.inner.console_write(data);
+    }
+
+    fn console_backspace(&mut self) {
+        self.inner.console_backspace();
+    }
+
+    fn set_mode(&mut self, mode: DisplayMode) {
+        self.inner.set_mode(mode);
+    }
+
+    fn mode(&self) -> DisplayMode {
+        DisplayDevice::mode(&self.inner)
+    }
+
+    fn set_palette(&mut self, fg: u32, bg: u32) {
+        self.inner.set_palette(fg, bg);
+    }
+
+    fn present(&mut self) {
+        *self.presents.lock().unwrap() += 1;
+        self.inner.present();
+    }
+}
+
+#[test]
+fn drawing_sprites_leaves_presenting_to_the_scheduler() {
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let presents = Arc::new(Mutex::new(0));
+    let display = CountingDisplay {
+        inner: DisplayWindow::headless(),
+        presents: Arc::clone(&presents),
+    };
+    let mut proc = CoreProc::new_with_display_and_pages(mem, display, 1).unwrap();
+
+    // a frame's worth of sprites: 100 x D011 (draw one row at V0, V1).
+    for n in 0..100u16 {
+        proc.write_bytes(0x200 + n as u32 * 2, &[0xD0, 0x11]).unwrap();
+    }
+    proc.write_u8(0x300, 0x80).unwrap();
+    proc.regs.I = 0x300;
+    for _ in 0..100 {
+        let _ = proc.step(0, |_, _| Ok(SyscallOutcome::Completed));
+    }
+
+    assert_eq!(proc.regs.PC, 0x200 + 200);
+    assert_eq!(*presents.lock().unwrap(), 0);
+    assert!(proc.display.inner.needs_present());
+
+    proc.display.present();
+    assert_eq!(*presents.lock().unwrap(), 1);
+    assert!(!proc.display.inner.needs_present());
+}
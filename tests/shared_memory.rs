@@ -23,3 +23,121 @@ fn write_and_read_bounds_checked() {
     let read = mem.read(last, 1).unwrap();
     assert_eq!(read[0], 0xAA);
 }
+
+#[test]
+fn munmap_frees_and_zeroes_pages() {
+    let mut mem = SharedMemory::new().unwrap();
+    let pages = mem.mmap(2).unwrap();
+    mem.write(pages[0] as usize, &vec![0xAA], 1).unwrap();
+    assert_eq!(mem.used_page_count(), 2);
+
+    mem.munmap(&pages).unwrap();
+    assert_eq!(mem.used_page_count(), 0);
+    assert_eq!(mem.read(pages[0] as usize, 1).unwrap(), vec![0]);
+
+    // a second free of the same pages is rejected.
+    assert!(mem.munmap(&pages).is_err());
+}
+
+#[test]
+fn page_counts_track_mmap_and_munmap() {
+    let mut mem = SharedMemory::new().unwrap();
+    assert_eq!(mem.total_pages(), PHYS_MEM_SIZE / 0x1000);
+    assert_eq!((mem.used_page_count(), mem.free_page_count()), (0, mem.total_pages()));
+
+    let pages = mem.mmap(3).unwrap();
+    assert_eq!(mem.used_page_count(), 3);
+    assert_eq!(mem.free_page_count(), mem.total_pages() - 3);
+
+    mem.munmap(&pages).unwrap();
+    assert_eq!((mem.used_page_count(), mem.free_page_count()), (0, mem.total_pages()));
+}
+
+#[test]
+fn mmap_hands_out_zeroed_pages() {
+    let mut mem = SharedMemory::new().unwrap();
+    let pages = mem.mmap(1).unwrap();
+    mem.write(pages[0] as usize, &vec![0xAA; 16], 16).unwrap();
+    mem.munmap(&pages).unwrap();
+    // bytes written straight into a free page must not survive either.
+    mem.write(pages[0] as usize + 0x800, &vec![0xBB], 1).unwrap();
+
+    let again = mem.mmap(1).unwrap();
+    assert_eq!(again, pages);
+    assert!(mem.read(again[0] as usize, 0x1000).unwrap().iter().all(|&b| b == 0));
+}
+
+#[test]
+fn mmap_contiguous_returns_adjacent_pages_from_the_tightest_run() {
+    let mut mem = SharedMemory::new().unwrap();
+    let pages: Vec<Vec<u32>> = (0..256).map(|_| mem.mmap(1).unwrap()).collect();
+    // free runs: 6 pages at 10..16, 4 pages at 40..44, single pages elsewhere.
+    for idx in (10..16).chain(40..44).chain([60, 62, 64, 66]) {
+        mem.munmap(&pages[idx]).unwrap();
+    }
+
+    let block = mem.mmap_contiguous(4).unwrap();
+    assert_eq!(block, [40, 41, 42, 43].map(|page| page * 0x1000));
+
+    // four free single pages and one six-page run: a seven-page block fails
+    // even though ten pages are free.
+    assert_eq!(mem.free_page_count(), 10);
+    assert!(mem.mmap_contiguous(7).is_err());
+    assert_eq!(mem.free_page_count(), 10);
+}
+
+#[test]
+fn munmap_double_free_names_the_pages_and_frees_nothing() {
+    let mut mem = SharedMemory::new().unwrap();
+    let first = mem.mmap(2).unwrap();
+    let second = mem.mmap(1).unwrap();
+    mem.munmap(&first).unwrap();
+
+    let mixed = [second[0], first[0], first[1]];
+    let err = mem.munmap(&mixed).unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains(&format!("{:#x}", first[0])), "{msg}");
+    assert!(msg.contains(&format!("{:#x}", first[1])), "{msg}");
+    assert!(!msg.contains(&format!("{:#x}", second[0])), "{msg}");
+    // the valid page in the list stays allocated.
+    assert_eq!(mem.used_page_count(), 1);
+}
+
+#[test]
+fn small_arena_runs_out_after_its_last_page() {
+    let mut mem = SharedMemory::with_capacity(4).unwrap();
+    assert_eq!(mem.total_pages(), 4);
+    let pages: Vec<Vec<u32>> = (0..4).map(|_| mem.mmap(1).unwrap()).collect();
+    assert_eq!(mem.free_page_count(), 0);
+    assert!(mem.mmap(1).is_err());
+
+    // the arena ends at its last page, so reads past it fail.
+    assert!(mem.read(4 * 0x1000, 1).is_err());
+    mem.munmap(&pages[2]).unwrap();
+    assert_eq!(mem.mmap(1).unwrap(), pages[2]);
+    assert!(SharedMemory::with_capacity(0).is_err());
+}
+
+#[test]
+fn fragmentation_diagnostics_track_free_runs() {
+    let mut mem = SharedMemory::new().unwrap();
+    assert_eq!(mem.largest_free_run(), 256);
+    assert_eq!(mem.fragmentation_ratio(), 0.0);
+
+    let pages: Vec<Vec<u32>> = (0..256).map(|_| mem.mmap(1).unwrap()).collect();
+    assert_eq!(mem.largest_free_run(), 0);
+    assert_eq!(mem.fragmentation_ratio(), 0.0);
+
+    // five single-page holes, then one ten-page run.
+    for idx in [0, 2, 4, 6, 8] {
+        mem.munmap(&pages[idx]).unwrap();
+    }
+    for page in &pages[100..110] {
+        mem.munmap(page).unwrap();
+    }
+    assert_eq!(mem.largest_free_run(), 10);
+    assert!((mem.fragmentation_ratio() - 5.0 / 15.0).abs() < 1e-9);
+
+    // scattered pages still satisfy a request for every free page.
+    assert_eq!(mem.mmap(15).unwrap().len(), 15);
+}
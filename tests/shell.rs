@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8_runtime::display::display::DisplayWindow;
+use chip8_runtime::kernel::kernel::{Kernel, ProcState};
+use chip8_runtime::shared_memory::shared_memory::SharedMemory;
+
+// upper bound on instructions per scripted command; the shell blocks on input
+// long before this in a passing run.
+const STEP_BUDGET: usize = 100_000;
+
+static INIT: Once = Once::new();
+
+fn set_headless() {
+    INIT.call_once(|| {
+        // set_var is unsafe on this toolchain; tests run single-process here.
+        unsafe {
+            std::env::set_var("CHIP8_HEADLESS", "1");
+        }
+    });
+}
+
+fn temp_root(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mut path = std::env::temp_dir();
+    path.push(format!("chip8_runtime_{label}_{nanos}"));
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// assemble roms/cli the same way roms/cli/build.sh does.
+fn build_cli_rom(work: &Path) -> Vec<u8> {
+    let cli_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/cli");
+    let mut source = String::new();
+    for part in ["cli.c8s", "lib/sys.c8s", "lib/data.c8s"] {
+        source.push_str(&fs::read_to_string(cli_dir.join(part)).unwrap());
+        source.push('\n');
+    }
+    let src_path = work.join("cli_combined.c8s");
+    let out_path = work.join("cli.ch8");
+    fs::write(&src_path, source).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_c8asm"))
+        .arg(&src_path)
+        .arg(&out_path)
+        .status()
+        .unwrap();
+    assert!(status.success(), "c8asm failed on the cli rom");
+    fs::read(out_path).unwrap()
+}
+
+/// step every runnable pid until all of them are blocked or exited.
+fn run_until_idle(kernel: &mut Kernel, pids: &[u32]) {
+    for _ in 0..STEP_BUDGET {
+        let mut ran_any = false;
+        for &pid in pids {
+            if kernel.proc_state(pid) == Some(ProcState::Running) {
+                kernel.step_proc(pid).unwrap();
+                ran_any = true;
+            }
+        }
+        if !ran_any {
+            return;
+        }
+    }
+    panic!("shell did not settle within {STEP_BUDGET} steps");
+}
+
+fn console_lines(kernel: &Kernel, pid: u32) -> Vec<String> {
+    let display = &kernel.proc(pid).unwrap().display;
+    let (_, cursor_row) = display.console_cursor();
+    (0..=cursor_row)
+        .map(|row| {
+            let line: String = (0..80)
+                .map(|col| display.console_cell(col, row).unwrap_or(b' ') as char)
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn cli_rom_lists_runs_and_reaps_a_child() {
+    set_headless();
+    let root = temp_root("shell_session");
+    let work = temp_root("shell_build");
+
+    // child.ch8: exit(7) via a frame at 0x208, then spin.
+    let child = [
+        0xA2, 0x08, // I := 0x208
+        0x01, 0x02, // sys exit
+        0x12, 0x04, // jump self
+        0x00, 0x00, // pad
+        0x03, 0x00, 0x07, // frame: arg0 = 7
+    ];
+    fs::write(root.join("child.ch8"), child).unwrap();
+    let rom = build_cli_rom(&work);
+
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = Kernel::new(mem, root.clone()).unwrap();
+    kernel.register_base_syscalls().unwrap();
+    let shell = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(shell).unwrap().load_program_bytes(&rom).unwrap();
+    let child_pid = shell + 1;
+    let pids = [shell, child_pid];
+
+    run_until_idle(&mut kernel, &pids);
+    assert_eq!(kernel.proc_state(shell), Some(ProcState::Blocked));
+    assert_eq!(console_lines(&kernel, shell), ["chip8 cli ready", ">"]);
+
+    kernel.push_console_input(shell, b"ls\n");
+    run_until_idle(&mut kernel, &pids);
+    assert_eq!(console_lines(&kernel, shell)[1..], ["> child.ch8", ">"]);
+
+    kernel.push_console_input(shell, b"run child.ch8\n");
+    run_until_idle(&mut kernel, &pids);
+    // the child ran, exited, and was reaped by the shell's wait.
+    assert_eq!(kernel.proc_state(child_pid), None);
+    assert_eq!(kernel.proc_state(shell), Some(ProcState::Blocked));
+    assert_eq!(kernel.proc(shell).unwrap().regs.V[0xF], 0);
+
+    kernel.push_console_input(shell, b"exit\n");
+    run_until_idle(&mut kernel, &pids);
+    assert_eq!(kernel.proc_state(shell), Some(ProcState::Exited));
+
+    let _ = fs::remove_dir_all(root);
+    let _ = fs::remove_dir_all(work);
+}
+
+#[test]
+fn cli_rom_ls_pages_past_one_buffer() {
+    set_headless();
+    let root = temp_root("shell_ls_pages");
+    let work = temp_root("shell_ls_build");
+    // more entries than DIR_BUF holds (3), plus a directory.
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"] {
+        fs::write(root.join(name), b"x").unwrap();
+    }
+    fs::create_dir(root.join("f")).unwrap();
+    let rom = build_cli_rom(&work);
+
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = Kernel::new(mem, root.clone()).unwrap();
+    kernel.register_base_syscalls().unwrap();
+    let shell = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(shell).unwrap().load_program_bytes(&rom).unwrap();
+
+    run_until_idle(&mut kernel, &[shell]);
+    kernel.push_console_input(shell, b"ls\n");
+    run_until_idle(&mut kernel, &[shell]);
+    assert_eq!(
+        console_lines(&kernel, shell)[1..],
+        ["> a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f/", ">"]
+    );
+
+    let _ = fs::remove_dir_all(root);
+    let _ = fs::remove_dir_all(work);
+}
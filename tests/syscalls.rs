@@ -1,15 +1,16 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, Once};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use chip8_runtime::display::display::DisplayWindow;
 use chip8_runtime::kernel::kernel::{Kernel, ProcState, SyscallOutcome};
-use chip8_runtime::proc::proc::Proc;
+use chip8_runtime::proc::proc::{Fault, Proc};
 use chip8_runtime::shared_memory::shared_memory::SharedMemory;
 
 const MAX_FILENAME_LEN: usize = 64;
-const DIR_ENTRY_SIZE: usize = 1 + MAX_FILENAME_LEN + 1 + 4;
+const DIR_ENTRY_SIZE: usize = 1 + MAX_FILENAME_LEN + 1 + 4 + 8;
+const MAX_OPEN_DIRS: usize = 8;
 
 static INIT: Once = Once::new();
 
@@ -35,11 +36,23 @@ fn temp_root(label: &str) -> PathBuf {
 
 fn make_kernel(root: &Path) -> Kernel {
     let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    make_kernel_with_mem(root, mem)
+}
+
+fn make_kernel_with_mem(root: &Path, mem: Arc<Mutex<SharedMemory>>) -> Kernel {
     let mut kernel = Kernel::new(mem, root.to_path_buf()).unwrap();
     kernel.register_base_syscalls().unwrap();
     kernel
 }
 
+/// run `f` and assert it left physical page usage where it found it.
+fn assert_pages_balanced<F: FnOnce()>(mem: &Arc<Mutex<SharedMemory>>, f: F) {
+    let before = mem.lock().unwrap().used_page_count();
+    f();
+    let after = mem.lock().unwrap().used_page_count();
+    assert_eq!(after, before, "physical pages leaked");
+}
+
 fn write_opcode(proc: &mut Proc, addr: u16, opcode: u16) {
     let hi = (opcode >> 8) as u8;
     let lo = opcode as u8;
@@ -84,6 +97,184 @@ fn read_dir_entries(proc: &mut Proc, base: u16, count: usize) -> Vec<(String, u8
     entries
 }
 
+#[test]
+fn timers_tick_for_every_proc_as_time_passes() {
+    set_headless();
+    let root = temp_root("timers");
+    let mut kernel = make_kernel(&root);
+    let pids = [
+        kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap(),
+        kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap(),
+    ];
+    for pid in pids {
+        let proc = kernel.proc_mut(pid).unwrap();
+        // jump-to-self, so stepping only spends time.
+        write_opcode(proc, 0x200, 0x1200);
+        proc.regs.DT = 30;
+        proc.regs.ST = 30;
+    }
+
+    // ten 60Hz ticks; only the first proc is stepped.
+    kernel.advance_clock(Duration::from_micros(16_666 * 10));
+    kernel.step_proc(pids[0]).unwrap();
+    let first = kernel.proc(pids[0]).unwrap().regs;
+    assert!(first.DT <= 20, "DT = {}", first.DT);
+    assert_eq!(first.ST, first.DT);
+    assert_eq!(kernel.proc(pids[1]).unwrap().regs.DT, first.DT);
+
+    // the ticks were consumed once, not again for the second proc.
+    kernel.step_proc(pids[1]).unwrap();
+    assert!(kernel.proc(pids[1]).unwrap().regs.DT >= first.DT.saturating_sub(1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sound_timer_drives_the_buzzer() {
+    set_headless();
+    let root = temp_root("buzzer");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_opcode(proc, 0x200, 0x6A05); // VA := 5
+        write_opcode(proc, 0x202, 0xFA18); // ST := VA
+        write_opcode(proc, 0x204, 0x1204); // spin
+    }
+
+    kernel.step_proc(pid).unwrap();
+    assert!(!kernel.proc(pid).unwrap().display.buzzer());
+    kernel.step_proc(pid).unwrap();
+    assert!(kernel.proc(pid).unwrap().display.buzzer());
+
+    // five 60Hz ticks run ST down to zero.
+    kernel.advance_clock(Duration::from_micros(16_666 * 5));
+    kernel.step_proc(pid).unwrap();
+    let proc = kernel.proc(pid).unwrap();
+    assert_eq!(proc.regs.ST, 0);
+    assert!(!proc.display.buzzer());
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sleeping_proc_wakes_once_its_ticks_pass() {
+    set_headless();
+    let root = temp_root("sleep");
+    let mut kernel = make_kernel(&root);
+    kernel.set_max_instructions_per_slice(1);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_frame(proc, 0x300, &[60]);
+        proc.regs.I = 0x300;
+        write_opcode(proc, 0x200, 0x0105); // sleep one second
+        write_opcode(proc, 0x202, 0x1202); // spin
+    }
+
+    assert_eq!(kernel.step_proc(pid).unwrap(), SyscallOutcome::Blocked);
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Blocked));
+
+    kernel.advance_clock(Duration::from_micros(16_666 * 30));
+    assert!(!kernel.run_round().unwrap());
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Blocked));
+
+    kernel.advance_clock(Duration::from_micros(16_667 * 30));
+    assert!(kernel.run_round().unwrap());
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Running));
+    let proc = kernel.proc(pid).unwrap();
+    assert_eq!((proc.regs.V[0], proc.regs.V[0xF]), (0, 0));
+    assert_eq!(proc.regs.PC, 0x202);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn scheduler_presents_drawing_once_a_frame() {
+    set_headless();
+    let root = temp_root("present_frame");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.write_u8(0x300, 0x80).unwrap();
+        proc.regs.I = 0x300;
+        write_opcode(proc, 0x200, 0xD011);
+        write_opcode(proc, 0x202, 0x1202); // spin
+    }
+
+    kernel.step_proc(pid).unwrap();
+    kernel.advance_clock(Duration::from_micros(16_667));
+    kernel.step_proc(pid).unwrap();
+    assert!(!kernel.proc(pid).unwrap().display.needs_present());
+    assert!(kernel.proc(pid).unwrap().display.logical_pixels()[0]);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn yield_with_ticks_backs_off_until_the_clock_moves() {
+    set_headless();
+    let root = temp_root("yield_for");
+    let mut kernel = make_kernel(&root);
+    kernel.set_max_instructions_per_slice(1);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_frame(proc, 0x300, &[6]);
+        proc.regs.I = 0x300;
+        write_opcode(proc, 0x200, 0x0104);
+        write_opcode(proc, 0x202, 0x1202); // spin
+    }
+
+    assert_eq!(kernel.step_proc(pid).unwrap(), SyscallOutcome::Blocked);
+    assert!(!kernel.run_round().unwrap());
+    assert_eq!(kernel.proc(pid).unwrap().regs.PC, 0x202);
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Blocked));
+
+    kernel.advance_clock(Duration::from_micros(16_667 * 6));
+    assert!(kernel.run_round().unwrap());
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Running));
+
+    // without ticks it is a plain yield.
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_frame(proc, 0x300, &[]);
+        write_opcode(proc, 0x202, 0x0104);
+        proc.regs.PC = 0x202;
+    }
+    assert_eq!(kernel.step_proc(pid).unwrap(), SyscallOutcome::Yielded);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn instruction_budget_shares_time_between_tight_loops() {
+    set_headless();
+    let root = temp_root("slice");
+    let mut kernel = make_kernel(&root);
+    kernel.set_max_instructions_per_slice(100);
+    let pids = [
+        kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap(),
+        kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap(),
+    ];
+    for pid in pids {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_opcode(proc, 0x200, 0x7001); // V0 += 1
+        write_opcode(proc, 0x202, 0x1200); // no syscall, never yields
+    }
+
+    // without the budget the first proc would spin here forever.
+    assert!(kernel.run_round().unwrap());
+    for pid in pids {
+        let proc = kernel.proc(pid).unwrap();
+        assert_eq!(proc.regs.V[0], 50);
+        assert_eq!(kernel.proc_state(pid), Some(ProcState::Running));
+    }
+
+    let _ = fs::remove_dir_all(root);
+}
+
 #[test]
 fn sys_write_sets_v0_and_vf() {
     set_headless();
@@ -308,6 +499,210 @@ fn sys_wait_unblocks_on_exit() {
     let _ = fs::remove_dir_all(root);
 }
 
+#[test]
+fn wait_zero_wakes_on_any_child() {
+    set_headless();
+    let root = temp_root("wait_any");
+    fs::write(root.join("spin.ch8"), [0x12, 0x00]).unwrap();
+    let mut kernel = make_kernel(&root);
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(parent).unwrap().write_bytes(0x340, b"spin.ch8").unwrap();
+    let children = [
+        step_syscall(&mut kernel, parent, 0x0101, &[0x0340, 8]).0 as u32,
+        step_syscall(&mut kernel, parent, 0x0101, &[0x0340, 8]).0 as u32,
+    ];
+
+    step_syscall(&mut kernel, parent, 0x0103, &[0]);
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Blocked));
+
+    {
+        let proc = kernel.proc_mut(children[1]).unwrap();
+        write_frame(proc, 0x320, &[0x0009]);
+        proc.regs.I = 0x320;
+        write_opcode(proc, 0x200, 0x0102);
+    }
+    kernel.step_proc(children[1]).unwrap();
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Running));
+    let regs = kernel.proc(parent).unwrap().regs;
+    assert_eq!((regs.V[0], regs.V[2], regs.V[0xF]), (9, children[1] as u8, 0));
+    assert_eq!(kernel.proc_state(children[1]), None);
+    assert_eq!(kernel.proc_state(children[0]), Some(ProcState::Running));
+
+    // a proc with no children would never wake.
+    let lonely = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    assert_eq!(step_syscall(&mut kernel, lonely, 0x0103, &[0]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn getppid_reports_the_spawner() {
+    set_headless();
+    let root = temp_root("getppid");
+    fs::write(root.join("spin.ch8"), [0x12, 0x00]).unwrap();
+    let mut kernel = make_kernel(&root);
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(parent).unwrap().write_bytes(0x340, b"spin.ch8").unwrap();
+    let child = step_syscall(&mut kernel, parent, 0x0101, &[0x0340, 8]).0 as u32;
+
+    assert_eq!(step_syscall(&mut kernel, child, 0x0106, &[]), (parent as u8, 0));
+    // the host spawned the parent.
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0106, &[]), (0, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn exec_replaces_the_program_in_place() {
+    set_headless();
+    let root = temp_root("exec");
+    fs::write(root.join("first.ch8"), [0x12, 0x00]).unwrap();
+    fs::write(root.join("second.ch8"), [0x60, 0x42, 0x12, 0x02]).unwrap();
+    fs::write(root.join("data.txt"), b"data").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel
+        .spawn_proc_from_name(DisplayWindow::headless(), 1, "first.ch8")
+        .unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"data.txt").unwrap();
+    let (fd, _) = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 8]);
+    let page_table = kernel.proc(pid).unwrap().page_table.clone();
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"second.ch8").unwrap();
+    kernel.proc_mut(pid).unwrap().regs.V[5] = 0x55;
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0107, &[0x0340, 10]).1, 0);
+
+    let proc = kernel.proc_mut(pid).unwrap();
+    assert_eq!(proc.regs.PC, 0x200);
+    assert_eq!(proc.regs.V[5], 0);
+    assert_eq!(proc.read_bytes(0x200, 4).unwrap(), [0x60, 0x42, 0x12, 0x02]);
+    assert_eq!(proc.page_table, page_table);
+    kernel.step_proc(pid).unwrap();
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[0], 0x42);
+
+    // files opened by the old image are closed.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd as u16, 0x0500, 4]), (0x04, 1));
+
+    // a missing ROM leaves the caller running its current image.
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"missing").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0107, &[0x0340, 7]), (0x03, 1));
+    assert_eq!(kernel.proc(pid).unwrap().regs.PC, 0x206);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn exec_is_refused_while_pages_are_shared_with_a_thread() {
+    set_headless();
+    let root = temp_root("exec_shared");
+    fs::write(root.join("first.ch8"), [0x12, 0x00]).unwrap();
+    fs::write(root.join("second.ch8"), [0x60, 0x42, 0x12, 0x02]).unwrap();
+    let mut kernel = make_kernel(&root);
+    let owner = kernel
+        .spawn_proc_from_name(DisplayWindow::headless(), 1, "first.ch8")
+        .unwrap();
+    write_opcode(kernel.proc_mut(owner).unwrap(), 0x240, 0x1240);
+    let thread = step_syscall(&mut kernel, owner, 0x0101, &[0x0240, 0, 0, 2]).0 as u32;
+    kernel.proc_mut(owner).unwrap().write_bytes(0x340, b"second.ch8").unwrap();
+
+    // neither the thread nor its owner may replace the shared image.
+    kernel.proc_mut(thread).unwrap().regs.PC = 0x250;
+    assert_eq!(step_syscall(&mut kernel, thread, 0x0107, &[0x0340, 10]), (0x02, 1));
+    assert_eq!(step_syscall(&mut kernel, owner, 0x0107, &[0x0340, 10]), (0x02, 1));
+    assert_eq!(kernel.proc(owner).unwrap().regs.PC, 0x204);
+
+    // once the thread is gone the owner may exec again.
+    step_syscall(&mut kernel, thread, 0x0102, &[0]);
+    kernel.reap_exited();
+    assert_eq!(step_syscall(&mut kernel, owner, 0x0107, &[0x0340, 10]).1, 0);
+    assert_eq!(kernel.proc(owner).unwrap().regs.PC, 0x200);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn ps_lists_every_proc() {
+    set_headless();
+    let root = temp_root("ps");
+    let mut kernel = make_kernel(&root);
+    let first = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let second = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, first, 0x0108, &[0x0500, 8]), (2, 0));
+    let records = kernel.proc_mut(first).unwrap().read_bytes(0x0500, 10).unwrap();
+    let [hi, lo] = (first as u16).to_be_bytes();
+    assert_eq!(records[..5], [hi, lo, 0, 0, 0]);
+    let [hi, lo] = (second as u16).to_be_bytes();
+    assert_eq!(records[5..], [hi, lo, 0, 0, 0]);
+
+    // max caps the listing.
+    assert_eq!(step_syscall(&mut kernel, first, 0x0108, &[0x0500, 1]), (1, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn wait_returns_sixteen_bit_exit_codes() {
+    set_headless();
+    let root = temp_root("wait_wide");
+    let mut kernel = make_kernel(&root);
+    let child = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let waiter = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    step_syscall(&mut kernel, child, 0x0102, &[0x1234]);
+    step_syscall(&mut kernel, waiter, 0x0103, &[child as u16]);
+    let regs = kernel.proc(waiter).unwrap().regs;
+    assert_eq!((regs.V[0], regs.V[1], regs.V[0xF]), (0x34, 0x12, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn waited_child_is_reaped_and_its_pages_freed() {
+    set_headless();
+    let root = temp_root("reap");
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, mem.clone());
+    let waiter = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let before = mem.lock().unwrap().used_page_count();
+
+    let child = kernel.spawn_proc(DisplayWindow::headless(), 2).unwrap();
+    {
+        let proc = kernel.proc_mut(child).unwrap();
+        write_frame(proc, 0x320, &[0x0007]);
+        proc.regs.I = 0x320;
+        write_opcode(proc, 0x200, 0x0102);
+    }
+    kernel.step_proc(child).unwrap();
+    // exited but not yet waited on: the exit code is kept.
+    assert_eq!(kernel.proc_state(child), Some(ProcState::Exited));
+
+    {
+        let proc = kernel.proc_mut(waiter).unwrap();
+        write_frame(proc, 0x300, &[child as u16]);
+        proc.regs.I = 0x300;
+        write_opcode(proc, 0x200, 0x0103);
+    }
+    kernel.step_proc(waiter).unwrap();
+    assert_eq!(kernel.proc(waiter).unwrap().regs.V[0], 7);
+    assert_eq!(kernel.proc_state(child), None);
+    assert_eq!(mem.lock().unwrap().used_page_count(), before);
+
+    // nobody waits for this one; reap_exited drops it anyway.
+    let orphan = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(orphan).unwrap();
+        write_frame(proc, 0x320, &[0x0001]);
+        proc.regs.I = 0x320;
+        write_opcode(proc, 0x200, 0x0102);
+    }
+    kernel.step_proc(orphan).unwrap();
+    kernel.reap_exited();
+    assert_eq!(kernel.proc_state(orphan), None);
+    assert_eq!(mem.lock().unwrap().used_page_count(), before);
+
+    let _ = fs::remove_dir_all(root);
+}
+
 #[test]
 fn sys_spawn_creates_process() {
     set_headless();
@@ -337,6 +732,46 @@ fn sys_spawn_creates_process() {
     let _ = fs::remove_dir_all(root);
 }
 
+#[test]
+fn sys_spawn_hands_args_to_the_child() {
+    set_headless();
+    let root = temp_root("spawn_args");
+    // LD V1, V0; LD V0, [I]; spin
+    fs::write(root.join("echo.ch8"), [0x81, 0x00, 0xF0, 0x65, 0x12, 0x04]).unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.write_bytes(0x340, b"echo.ch8").unwrap();
+        proc.write_bytes(0x380, b"-v notes.txt").unwrap();
+    }
+
+    let (child, err) = step_syscall(&mut kernel, pid, 0x0101, &[0x0340, 8, 1, 0, 0x0380, 12]);
+    assert_eq!(err, 0);
+    let child = child as u32;
+    {
+        let proc = kernel.proc_mut(child).unwrap();
+        let base = proc.regs.I;
+        assert_eq!(proc.regs.SP, base);
+        // a return must not pop the argument bytes as an address.
+        assert_eq!(proc.stack_top, base);
+        assert_eq!(proc.read_bytes(base as u32, 12).unwrap(), b"-v notes.txt");
+    }
+
+    kernel.step_proc(child).unwrap();
+    kernel.step_proc(child).unwrap();
+    let proc = kernel.proc(child).unwrap();
+    assert_eq!((proc.regs.V[0], proc.regs.V[1]), (b'-', 12));
+
+    // an args block longer than V0 can describe is refused.
+    assert_eq!(
+        step_syscall(&mut kernel, pid, 0x0101, &[0x0340, 8, 1, 0, 0x0380, 0x100]),
+        (0x02, 1)
+    );
+
+    let _ = fs::remove_dir_all(root);
+}
+
 #[test]
 fn sys_fs_list_reads_root_entries() {
     set_headless();
@@ -367,6 +802,49 @@ fn sys_fs_list_reads_root_entries() {
     let _ = fs::remove_dir_all(root);
 }
 
+#[test]
+fn sys_fs_list_pages_through_sorted_entries() {
+    set_headless();
+    let root = temp_root("fs_list_pages");
+    for name in ["e.txt", "c.txt", "a.txt", "d.txt", "b.txt"] {
+        fs::write(root.join(name), b"x").unwrap();
+    }
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    let mut names = Vec::new();
+    let mut more = Vec::new();
+    for start in [0, 2, 4] {
+        let (count, err) = step_syscall(&mut kernel, pid, 0x0120, &[0x0340, 0, 0x0400, 2, start]);
+        assert_eq!(err, 0);
+        let proc = kernel.proc_mut(pid).unwrap();
+        more.push(proc.regs.V[1]);
+        names.extend(read_dir_entries(proc, 0x0400, count as usize).into_iter().map(|(name, _, _)| name));
+    }
+    assert_eq!(names, ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"]);
+    assert_eq!(more, [1, 1, 0]);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_list_reports_mtime() {
+    set_headless();
+    let root = temp_root("fs_list_mtime");
+    fs::write(root.join("new.txt"), b"x").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0120, &[0x0340, 0, 0x0400, 1]), (1, 0));
+    let offset = 1 + MAX_FILENAME_LEN as u32 + 1 + 4;
+    let bytes = kernel.proc_mut(pid).unwrap().read_bytes(0x0400 + offset, 8).unwrap();
+    let mtime = u64::from_be_bytes(bytes.try_into().unwrap());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!(mtime > 0 && now.abs_diff(mtime) < 60, "mtime {mtime}, now {now}");
+
+    let _ = fs::remove_dir_all(root);
+}
+
 #[test]
 fn sys_fs_open_read_close_roundtrip() {
     set_headless();
@@ -414,3 +892,1115 @@ fn sys_fs_open_read_close_roundtrip() {
 
     let _ = fs::remove_dir_all(root);
 }
+
+#[test]
+fn sys_fs_write_honors_open_flags() {
+    set_headless();
+    let root = temp_root("fs_write");
+    fs::write(root.join("out.txt"), b"stale contents").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"out.txt").unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x360, b"hello world").unwrap();
+
+    // bit0 truncates and opens for writing.
+    let (fd, err) = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 7, 1]);
+    assert_eq!(err, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0124, &[fd as u16, 0x0360, 5]), (5, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0123, &[fd as u16]).1, 0);
+
+    // bit1 keeps the contents and writes at the end.
+    let (fd, err) = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 7, 2]);
+    assert_eq!(err, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0124, &[fd as u16, 0x0365, 6]), (6, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0123, &[fd as u16]).1, 0);
+
+    // a read-only handle reads the bytes back but refuses writes.
+    let (fd, err) = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 7]);
+    assert_eq!(err, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd as u16, 0x0500, 32]), (11, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 11).unwrap(), b"hello world");
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0124, &[fd as u16, 0x0360, 5]), (0x02, 1));
+    assert_eq!(fs::read(root.join("out.txt")).unwrap(), b"hello world");
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_create_and_delete_show_in_fs_list() {
+    set_headless();
+    let root = temp_root("fs_create");
+    fs::create_dir(root.join("subdir")).unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let list = |kernel: &mut Kernel| {
+        let (count, err) = step_syscall(kernel, pid, 0x0120, &[0x0340, 0, 0x0400, 8]);
+        assert_eq!(err, 0);
+        read_dir_entries(kernel.proc_mut(pid).unwrap(), 0x0400, count as usize)
+    };
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"new.txt").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0125, &[0x0340, 7]).1, 0);
+    assert!(list(&mut kernel).contains(&("new.txt".to_string(), 0, 0)));
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0126, &[0x0340, 7]).1, 0);
+    assert!(!list(&mut kernel).iter().any(|(name, _, _)| name == "new.txt"));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0126, &[0x0340, 7]), (0x04, 1));
+
+    // directories, escapes and missing parents are refused.
+    for (name, code) in [("subdir", 0x06), ("../out.txt", 0x09), ("nope/a.txt", 0x04)] {
+        kernel.proc_mut(pid).unwrap().write_bytes(0x340, name.as_bytes()).unwrap();
+        let len = name.len() as u16;
+        assert_eq!(step_syscall(&mut kernel, pid, 0x0125, &[0x0340, len]), (code, 1), "created {name}");
+    }
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"subdir").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0126, &[0x0340, 6]), (0x06, 1));
+    assert!(root.join("subdir").is_dir());
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_mkdir_creates_a_listed_directory() {
+    set_headless();
+    let root = temp_root("fs_mkdir");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"games").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0127, &[0x0340, 5]).1, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0127, &[0x0340, 5]), (0x03, 1));
+
+    let (count, err) = step_syscall(&mut kernel, pid, 0x0120, &[0x0340, 0, 0x0400, 8]);
+    assert_eq!((count, err), (1, 0));
+    let entries = read_dir_entries(kernel.proc_mut(pid).unwrap(), 0x0400, 1);
+    assert_eq!((entries[0].0.as_str(), entries[0].1), ("games", 1));
+
+    // nested directories go through the same parent check.
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"games/saves").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0127, &[0x0340, 11]).1, 0);
+    assert!(root.join("games/saves").is_dir());
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_create_refuses_a_full_directory() {
+    set_headless();
+    let root = temp_root("fs_create_full");
+    fs::create_dir(root.join("full")).unwrap();
+    for n in 0..256 {
+        fs::write(root.join("full").join(format!("f{n:03}")), b"x").unwrap();
+    }
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"full/new").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0125, &[0x0340, 8]), (0x03, 1));
+    assert!(!root.join("full/new").exists());
+
+    // an existing name adds no entry, so it may still be truncated.
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"full/f000").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0125, &[0x0340, 9]).1, 0);
+    assert_eq!(fs::read(root.join("full/f000")).unwrap(), b"");
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn over_long_path_segments_report_name_too_long() {
+    set_headless();
+    let root = temp_root("fs_name_too_long");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    let name = "a".repeat(100);
+    kernel.proc_mut(pid).unwrap().write_bytes(0x400, name.as_bytes()).unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0121, &[0x0400, 100]), (0x07, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0120, &[0x0400, 100, 0x0600, 1]), (0x07, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0127, &[0x0400, 100]), (0x07, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_pread_leaves_the_cursor_alone() {
+    set_headless();
+    let root = temp_root("fs_pread");
+    fs::write(root.join("data.bin"), b"abcde").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"data.bin").unwrap();
+    let fd = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 8]).0 as u16;
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0500, 1]), (1, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012A, &[fd, 0, 2, 0x0510, 8]), (3, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0510, 3).unwrap(), b"cde");
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0500, 2]), (2, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 2).unwrap(), b"bc");
+
+    // the high half of the offset counts too: this is past the end.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012A, &[fd, 1, 0, 0x0510, 8]), (0, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn chdir_moves_where_relative_paths_resolve() {
+    set_headless();
+    let root = temp_root("chdir");
+    fs::create_dir(root.join("games")).unwrap();
+    fs::write(root.join("games/pong.ch8"), b"").unwrap();
+    fs::write(root.join("top.txt"), b"").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"games..").unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012B, &[0x0340, 5]), (0, 0));
+    kernel.proc_mut(pid).unwrap().write_bytes(0x360, b".").unwrap();
+    let (count, err) = step_syscall(&mut kernel, pid, 0x0120, &[0x0360, 1, 0x0400, 8]);
+    assert_eq!((count, err), (1, 0));
+    let entries = read_dir_entries(kernel.proc_mut(pid).unwrap(), 0x0400, 1);
+    assert_eq!(entries[0].0, "pong.ch8");
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012C, &[0x0500, 16]), (5, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 5).unwrap(), b"games");
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012C, &[0x0500, 4]), (0x07, 1));
+
+    // `..` leads back to the root but no further.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012B, &[0x0345, 2]), (0, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012C, &[0x0500, 16]), (0, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012B, &[0x0345, 2]), (0x09, 1));
+    kernel.proc_mut(pid).unwrap().write_bytes(0x360, b"top.txt").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x012B, &[0x0360, 7]), (0x05, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_seek_rewinds_and_skips() {
+    set_headless();
+    let root = temp_root("fs_seek");
+    fs::write(root.join("data.bin"), b"abcdefgh").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"data.bin").unwrap();
+    let (fd, _) = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 8]);
+    let fd = fd as u16;
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0500, 3]), (3, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0128, &[fd, 0, 0]), (0, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0510, 3]), (3, 0));
+    let proc = kernel.proc_mut(pid).unwrap();
+    assert_eq!(proc.read_bytes(0x0500, 3).unwrap(), proc.read_bytes(0x0510, 3).unwrap());
+
+    // relative offsets are signed; the end is past the last byte.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0128, &[fd, 1, 2]), (5, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0128, &[fd, 2, 0xFFFF]), (7, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0520, 4]), (1, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_u8(0x0520).unwrap(), b'h');
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0128, &[fd, 1, 0xFFF0]), (0x02, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0128, &[fd, 3, 0]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_stat_reports_kind_and_size() {
+    set_headless();
+    let root = temp_root("fs_stat");
+    fs::write(root.join("rom.ch8"), [0u8; 300]).unwrap();
+    fs::create_dir(root.join("subdir")).unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"rom.ch8").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0129, &[0x0340, 7, 0x0500]).1, 0);
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 5).unwrap(), [0, 0, 0, 0x01, 0x2C]);
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"subdir").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0129, &[0x0340, 6, 0x0500]).1, 0);
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 5).unwrap(), [1, 0, 0, 0, 0]);
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"missing").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0129, &[0x0340, 7, 0x0500]), (0x04, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_random_fills_a_buffer() {
+    set_headless();
+    let root = temp_root("random");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x015E, &[0x0500, 8]), (8, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x015E, &[0x0500, 0x1000]), (0xFF, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x015E, &[0x0FFC, 8]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn open_file_limit_is_configurable() {
+    set_headless();
+    let root = temp_root("max_open");
+    fs::write(root.join("a.txt"), b"a").unwrap();
+    let mut kernel = make_kernel(&root);
+    kernel.set_max_open_files(1);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"a.txt").unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 5]).1, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 5]), (0x08, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x016B, &[]), (0x08, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn spawn_with_zero_pages_is_rejected() {
+    set_headless();
+    let root = temp_root("spawn_zero_pages");
+    let mut kernel = make_kernel(&root);
+
+    let err = kernel.spawn_proc(DisplayWindow::headless(), 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "a process needs at least one page");
+
+    let _ = fs::remove_dir_all(root);
+}
+
+fn readline_into(proc: &mut Proc, max: u16, flags: u16) {
+    write_frame(proc, 0x300, &[0x0340, max, flags]);
+    proc.regs.I = 0x300;
+    write_opcode(proc, proc.regs.PC, 0x0114);
+}
+
+#[test]
+fn sys_readline_into_discards_rest_of_long_line() {
+    set_headless();
+    let root = temp_root("readline_discard");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel.push_input(b"0123456789\n");
+    readline_into(kernel.proc_mut(pid).unwrap(), 4, 1);
+    let outcome = kernel.step_proc(pid).unwrap();
+    assert_eq!(outcome, SyscallOutcome::Completed);
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        assert_eq!(proc.read_bytes(0x340, 4).unwrap(), b"0123");
+        assert_eq!(proc.regs.V[0], 4);
+        assert_eq!(proc.regs.V[1], 1);
+        assert_eq!(proc.regs.V[0xF], 0);
+    }
+
+    // the queue is empty, so the next read blocks until a fresh line arrives.
+    readline_into(kernel.proc_mut(pid).unwrap(), 4, 1);
+    let outcome = kernel.step_proc(pid).unwrap();
+    assert_eq!(outcome, SyscallOutcome::Blocked);
+
+    kernel.push_input(b"ab\n");
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Running));
+    let proc = kernel.proc_mut(pid).unwrap();
+    assert_eq!(proc.read_bytes(0x340, 3).unwrap(), b"ab\n");
+    assert_eq!(proc.regs.V[0], 3);
+    assert_eq!(proc.regs.V[1], 0);
+    assert_eq!(proc.regs.V[0xF], 0);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_readline_into_reports_truncation() {
+    set_headless();
+    let root = temp_root("readline_report");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel.push_input(b"abcdef\n");
+    readline_into(kernel.proc_mut(pid).unwrap(), 4, 0);
+    let _ = kernel.step_proc(pid).unwrap();
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        assert_eq!(proc.read_bytes(0x340, 4).unwrap(), b"abcd");
+        assert_eq!(proc.regs.V[0], 0x0E);
+        assert_eq!(proc.regs.V[1], 1);
+        assert_eq!(proc.regs.V[0xF], 1);
+    }
+
+    // without the discard flag the remainder is delivered by the next read.
+    readline_into(kernel.proc_mut(pid).unwrap(), 4, 0);
+    let _ = kernel.step_proc(pid).unwrap();
+    let proc = kernel.proc_mut(pid).unwrap();
+    assert_eq!(proc.read_bytes(0x340, 3).unwrap(), b"ef\n");
+    assert_eq!(proc.regs.V[0], 3);
+    assert_eq!(proc.regs.V[0xF], 0);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_closeall_closes_every_fd() {
+    set_headless();
+    let root = temp_root("fs_closeall");
+    fs::write(root.join("a.txt"), b"a").unwrap();
+    fs::write(root.join("b.txt"), b"b").unwrap();
+    fs::write(root.join("c.txt"), b"c").unwrap();
+
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    let mut fds = Vec::new();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        {
+            let proc = kernel.proc_mut(pid).unwrap();
+            proc.write_bytes(0x340, name.as_bytes()).unwrap();
+            write_frame(proc, 0x300, &[0x0340, name.len() as u16, 0]);
+            proc.regs.I = 0x300;
+            write_opcode(proc, proc.regs.PC, 0x0121);
+        }
+        let _ = kernel.step_proc(pid).unwrap();
+        let proc = kernel.proc(pid).unwrap();
+        assert_eq!(proc.regs.V[0xF], 0);
+        fds.push(proc.regs.V[0]);
+    }
+
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_opcode(proc, proc.regs.PC, 0x0139);
+    }
+    let _ = kernel.step_proc(pid).unwrap();
+    let proc = kernel.proc(pid).unwrap();
+    assert_eq!(proc.regs.V[0], 3);
+    assert_eq!(proc.regs.V[0xF], 0);
+
+    for fd in fds {
+        {
+            let proc = kernel.proc_mut(pid).unwrap();
+            write_frame(proc, 0x320, &[fd as u16, 0x0500, 1]);
+            proc.regs.I = 0x320;
+            write_opcode(proc, proc.regs.PC, 0x0122);
+        }
+        let _ = kernel.step_proc(pid).unwrap();
+        let proc = kernel.proc(pid).unwrap();
+        assert_eq!(proc.regs.V[0xF], 1);
+        assert_eq!(proc.regs.V[0], 0x04);
+    }
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_mprotect_faults_writes_but_allows_reads() {
+    set_headless();
+    let root = temp_root("mprotect");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 2).unwrap();
+
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.write_bytes(0x1000, &[0xAB]).unwrap();
+        write_frame(proc, 0x300, &[0x1000, 1]);
+        proc.regs.I = 0x300;
+        write_opcode(proc, proc.regs.PC, 0x015C);
+    }
+    let _ = kernel.step_proc(pid).unwrap();
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[0xF], 0);
+
+    // Fx65 from the protected page still works.
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.regs.I = 0x1000;
+        write_opcode(proc, proc.regs.PC, 0xF065);
+    }
+    let outcome = kernel.step_proc(pid).unwrap();
+    assert_eq!(outcome, SyscallOutcome::Completed);
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[0], 0xAB);
+
+    // Fx55 into it faults and stops the proc on that instruction.
+    let pc = {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.regs.I = 0x1000;
+        write_opcode(proc, proc.regs.PC, 0xF055);
+        proc.regs.PC
+    };
+    let outcome = kernel.step_proc(pid).unwrap();
+    assert_eq!(outcome, SyscallOutcome::Faulted);
+    assert_eq!(kernel.proc_state(pid), Some(ProcState::Exited));
+    let proc = kernel.proc_mut(pid).unwrap();
+    assert_eq!(proc.fault, Some(Fault::WriteProtected { addr: 0x1000 }));
+    assert_eq!(proc.regs.PC, pc);
+    assert_eq!(proc.read_u8(0x1000).unwrap(), 0xAB);
+
+    // a thread's protection bits are its own, so sharers may not change them.
+    let owner = kernel.spawn_proc(DisplayWindow::headless(), 2).unwrap();
+    write_opcode(kernel.proc_mut(owner).unwrap(), 0x240, 0x1240);
+    let thread = step_syscall(&mut kernel, owner, 0x0101, &[0x0240, 0, 0, 2]).0 as u32;
+    assert_eq!(step_syscall(&mut kernel, owner, 0x015C, &[0x1000, 1]), (0x02, 1));
+    kernel.proc_mut(thread).unwrap().regs.PC = 0x250;
+    assert_eq!(step_syscall(&mut kernel, thread, 0x015C, &[0x1000, 1]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn protect_code_makes_the_loaded_program_read_only() {
+    set_headless();
+    let root = temp_root("protect_code");
+    // I = 0x200; store V0 over the first instruction.
+    let rom = [0xA2, 0x00, 0xF0, 0x55];
+    fs::write(root.join("selfmod.ch8"), rom).unwrap();
+    let mut kernel = make_kernel(&root);
+
+    let plain = kernel
+        .spawn_proc_from_name(DisplayWindow::headless(), 1, "selfmod.ch8")
+        .unwrap();
+    assert_eq!(kernel.proc(plain).unwrap().protected_code, None);
+    for _ in 0..2 {
+        assert_eq!(kernel.step_proc(plain).unwrap(), SyscallOutcome::Completed);
+    }
+
+    kernel.set_protect_code(true);
+    let pid = kernel
+        .spawn_proc_from_name(DisplayWindow::headless(), 1, "selfmod.ch8")
+        .unwrap();
+    assert_eq!(kernel.proc(pid).unwrap().protected_code, Some(0x200..0x204));
+    assert_eq!(kernel.step_proc(pid).unwrap(), SyscallOutcome::Completed);
+    assert_eq!(kernel.step_proc(pid).unwrap(), SyscallOutcome::Faulted);
+    assert_eq!(
+        kernel.proc(pid).unwrap().fault,
+        Some(Fault::WriteProtected { addr: 0x200 })
+    );
+
+    // only the program bytes are protected; the rest of its page is not.
+    let proc = kernel.proc_mut(plain).unwrap();
+    proc.protect_program(rom.len());
+    assert!(proc.write_u8(0x1FF, 1).is_ok());
+    assert!(proc.write_u8(0x204, 1).is_ok());
+    assert!(proc.write_u8(0x203, 1).is_err());
+
+    // exec protects the new image the same way.
+    fs::write(root.join("next.ch8"), [0x00, 0xE0, 0x12, 0x02, 0x00, 0x00]).unwrap();
+    kernel.proc_mut(plain).unwrap().write_bytes(0x340, b"next.ch8").unwrap();
+    step_syscall(&mut kernel, plain, 0x0107, &[0x0340, 8]);
+    assert_eq!(kernel.proc(plain).unwrap().protected_code, Some(0x200..0x206));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_debug_print_reaches_logger_in_display_mode() {
+    set_headless();
+    let root = temp_root("debug_print");
+    let mut kernel = make_kernel(&root);
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&lines);
+    kernel.set_logger(move |line| sink.lock().unwrap().push(line.to_string()));
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    set_console_mode(kernel.proc_mut(pid).unwrap(), 1);
+    let _ = kernel.step_proc(pid).unwrap();
+
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        proc.write_bytes(0x340, b"x=42\n").unwrap();
+        write_frame(proc, 0x300, &[0x0340, 5]);
+        proc.regs.I = 0x300;
+        write_opcode(proc, proc.regs.PC, 0x015D);
+    }
+    let _ = kernel.step_proc(pid).unwrap();
+
+    let proc = kernel.proc(pid).unwrap();
+    assert_eq!(proc.regs.V[0], 5);
+    assert_eq!(proc.regs.V[0xF], 0);
+    // nothing reached the console; the text went to the log only.
+    assert_eq!(proc.display.console_cursor(), (0, 0));
+    assert_eq!(*lines.lock().unwrap(), [format!("[pid {pid}] x=42")]);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn spawn_with_failed_rom_load_frees_its_pages() {
+    set_headless();
+    let root = temp_root("spawn_leak");
+    // one page leaves 0xE00 bytes for the program; this rom does not fit.
+    fs::write(root.join("big.ch8"), vec![0u8; 0x1000]).unwrap();
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, Arc::clone(&mem));
+
+    assert_pages_balanced(&mem, || {
+        let err = kernel
+            .spawn_proc_from_name(DisplayWindow::headless(), 1, "big.ch8")
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+    });
+    assert_eq!(kernel.proc_state(1), None);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+fn step_syscall(kernel: &mut Kernel, pid: u32, id: u16, args: &[u16]) -> (u8, u8) {
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_frame(proc, 0x300, args);
+        proc.regs.I = 0x300;
+        write_opcode(proc, proc.regs.PC, id);
+    }
+    let _ = kernel.step_proc(pid).unwrap();
+    let proc = kernel.proc(pid).unwrap();
+    (proc.regs.V[0], proc.regs.V[0xF])
+}
+
+#[test]
+fn sys_fs_opendir_enforces_cap_and_closedir_frees_a_slot() {
+    set_headless();
+    let root = temp_root("opendir_cap");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    let mut handles = Vec::new();
+    for _ in 0..MAX_OPEN_DIRS {
+        let (handle, err) = step_syscall(&mut kernel, pid, 0x0136, &[0x0340, 0]);
+        assert_eq!(err, 0);
+        handles.push(handle);
+    }
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0136, &[0x0340, 0]), (0x08, 1));
+
+    let (_, err) = step_syscall(&mut kernel, pid, 0x0138, &[handles[0] as u16]);
+    assert_eq!(err, 0);
+    let (_, err) = step_syscall(&mut kernel, pid, 0x0136, &[0x0340, 0]);
+    assert_eq!(err, 0);
+
+    // closeall releases directory handles along with fds.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0139, &[]), (MAX_OPEN_DIRS as u8, 0));
+    let (_, err) = step_syscall(&mut kernel, pid, 0x0136, &[0x0340, 0]);
+    assert_eq!(err, 0);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_readdir_streams_entries_then_reports_end() {
+    set_headless();
+    let root = temp_root("readdir");
+    fs::write(root.join("only.txt"), b"abc").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    let (handle, err) = step_syscall(&mut kernel, pid, 0x0136, &[0x0340, 0]);
+    assert_eq!(err, 0);
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0137, &[handle as u16, 0x0400]), (1, 0));
+    let entries = read_dir_entries(kernel.proc_mut(pid).unwrap(), 0x0400, 1);
+    assert_eq!(entries, [("only.txt".to_string(), 0, 3)]);
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0137, &[handle as u16, 0x0400]), (0, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_set_palette_delivers_rgb_to_display() {
+    set_headless();
+    let root = temp_root("set_palette");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    kernel
+        .proc_mut(pid)
+        .unwrap()
+        .write_bytes(0x340, &[0x12, 0x34, 0x56, 0xAB, 0xCD, 0xEF])
+        .unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0173, &[0x0340, 6]).1, 0);
+    let display = &kernel.proc(pid).unwrap().display;
+    assert_eq!(display.palette(), (0x123456, 0xABCDEF));
+    assert!(display.buf.iter().all(|px| *px == 0xABCDEF));
+
+    // anything but exactly two 3-byte colors is rejected.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0173, &[0x0340, 5]), (0x02, 1));
+    assert_eq!(kernel.proc(pid).unwrap().display.palette(), (0x123456, 0xABCDEF));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_killpg_terminates_whole_group_only() {
+    set_headless();
+    let root = temp_root("killpg");
+    // spin.ch8: jump to itself.
+    fs::write(root.join("spin.ch8"), [0x12, 0x00]).unwrap();
+    let mut kernel = make_kernel(&root);
+    let leader = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let unrelated = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(leader).unwrap().write_bytes(0x340, b"spin.ch8").unwrap();
+
+    let mut children = Vec::new();
+    for _ in 0..2 {
+        let (child, err) = step_syscall(&mut kernel, leader, 0x0101, &[0x0340, 8, 1]);
+        assert_eq!(err, 0);
+        children.push(child as u32);
+    }
+    // the new-group flag detaches this child from the leader's group.
+    let (detached, err) = step_syscall(&mut kernel, leader, 0x0101, &[0x0340, 8, 1, 1]);
+    assert_eq!(err, 0);
+    let detached = detached as u32;
+    assert_eq!(kernel.pgid(children[0]), Some(leader));
+    assert_eq!(kernel.pgid(detached), Some(detached));
+
+    assert_eq!(step_syscall(&mut kernel, unrelated, 0x0167, &[leader as u16]), (3, 0));
+    for pid in [leader, children[0], children[1]] {
+        assert_eq!(kernel.proc_state(pid), Some(ProcState::Exited));
+    }
+    assert_eq!(kernel.proc_state(unrelated), Some(ProcState::Running));
+    assert_eq!(kernel.proc_state(detached), Some(ProcState::Running));
+
+    // nothing left alive in the group.
+    assert_eq!(step_syscall(&mut kernel, unrelated, 0x0167, &[leader as u16]), (0x04, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn spawn_exit_cycles_do_not_run_out_of_pages() {
+    set_headless();
+    let root = temp_root("spawn_cycles");
+    // bye.ch8: exit(0) through a frame at 0x210.
+    fs::write(root.join("bye.ch8"), [0xA2, 0x10, 0x01, 0x02]).unwrap();
+    fs::write(root.join("spin.ch8"), [0x12, 0x00]).unwrap();
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, mem.clone());
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let killer = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(parent).unwrap().write_bytes(0x340, b"bye.ch8spin.ch8").unwrap();
+
+    // 8 pages a child: leaking on either path would use up all 256 pages
+    // well before the last round.
+    assert_pages_balanced(&mem, || {
+        for round in 0..70 {
+            kernel.proc_mut(parent).unwrap().regs.PC = 0x200;
+            if round % 2 == 0 {
+                let (child, err) = step_syscall(&mut kernel, parent, 0x0101, &[0x0340, 7, 8]);
+                assert_eq!(err, 0, "spawn failed in round {round}");
+                let child = child as u32;
+                write_frame(kernel.proc_mut(child).unwrap(), 0x210, &[0]);
+                kernel.step_proc(child).unwrap();
+                kernel.step_proc(child).unwrap();
+                assert_eq!(step_syscall(&mut kernel, parent, 0x0103, &[child as u16]), (0, 0));
+            } else {
+                // a killed child reaped by its blocked parent.
+                let (child, err) = step_syscall(&mut kernel, parent, 0x0101, &[0x0347, 8, 8, 1]);
+                assert_eq!(err, 0, "spawn failed in round {round}");
+                step_syscall(&mut kernel, parent, 0x0103, &[child as u16]);
+                assert_eq!(kernel.proc_state(parent), Some(ProcState::Blocked));
+                assert_eq!(step_syscall(&mut kernel, killer, 0x0167, &[child as u16]), (1, 0));
+                assert_eq!(kernel.proc_state(child as u32), None);
+            }
+        }
+    });
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn fork_copies_registers_and_memory_then_diverges() {
+    set_headless();
+    let root = temp_root("fork");
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, mem.clone());
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 2).unwrap();
+    {
+        let proc = kernel.proc_mut(parent).unwrap();
+        proc.regs.V[3] = 0x33;
+        proc.write_bytes(0x1400, b"shared").unwrap();
+        write_opcode(proc, 0x202, 0x6377); // V3 = 0x77
+    }
+    let before = mem.lock().unwrap().used_page_count();
+
+    let (child, err) = step_syscall(&mut kernel, parent, 0x0109, &[]);
+    assert_eq!(err, 0);
+    let child = child as u32;
+    assert_ne!(child, 0);
+    assert_eq!(mem.lock().unwrap().used_page_count(), before + 2);
+    {
+        let parent_regs = kernel.proc(parent).unwrap().regs;
+        let child_regs = kernel.proc(child).unwrap().regs;
+        assert_eq!(child_regs.V[0], 0);
+        assert_eq!(child_regs.V[1..], parent_regs.V[1..]);
+        assert_eq!(
+            (child_regs.I, child_regs.SP, child_regs.PC),
+            (parent_regs.I, parent_regs.SP, 0x202)
+        );
+    }
+    assert_eq!(kernel.proc_mut(child).unwrap().read_bytes(0x1400, 6).unwrap(), b"shared");
+
+    // from here on each has its own copy.
+    kernel.step_proc(child).unwrap();
+    kernel.proc_mut(child).unwrap().write_bytes(0x1400, b"forked").unwrap();
+    assert_eq!(kernel.proc(child).unwrap().regs.V[3], 0x77);
+    assert_eq!(kernel.proc(parent).unwrap().regs.V[3], 0x33);
+    assert_eq!(kernel.proc_mut(parent).unwrap().read_bytes(0x1400, 6).unwrap(), b"shared");
+    assert_eq!(step_syscall(&mut kernel, child, 0x0106, &[]), (parent as u8, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sbrk_maps_more_pages_above_the_old_top() {
+    set_headless();
+    let root = temp_root("sbrk");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    assert!(kernel.proc_mut(pid).unwrap().write_bytes(0x1800, b"x").is_err());
+
+    step_syscall(&mut kernel, pid, 0x010A, &[1]);
+    let regs = kernel.proc(pid).unwrap().regs;
+    assert_eq!((regs.V[0], regs.V[1], regs.V[0xF]), (0x1F, 0xFF, 0));
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        assert_eq!(proc.vm_size, 0x2000);
+        assert_eq!(proc.read_bytes(0x1800, 4).unwrap(), [0; 4]);
+        proc.write_bytes(0x1800, b"grown").unwrap();
+        assert_eq!(proc.read_bytes(0x1800, 5).unwrap(), b"grown");
+        assert_eq!(proc.regs.SP, 0x1000);
+    }
+
+    // 0 queries; more than 64K in all is refused.
+    step_syscall(&mut kernel, pid, 0x010A, &[0]);
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[..2], [0x1F, 0xFF]);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x010A, &[15]), (0x02, 1));
+
+    // a thread's page table would not see the new pages.
+    write_opcode(kernel.proc_mut(pid).unwrap(), 0x240, 0x1240);
+    let thread = step_syscall(&mut kernel, pid, 0x0101, &[0x0240, 0, 0, 2]).0 as u32;
+    assert_eq!(step_syscall(&mut kernel, thread, 0x010A, &[1]), (0x02, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x010A, &[1]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn pipe_carries_bytes_from_a_thread_to_its_parent() {
+    set_headless();
+    let root = temp_root("pipe");
+    let mut kernel = make_kernel(&root);
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(parent).unwrap().write_bytes(0x360, b"hello").unwrap();
+    write_opcode(kernel.proc_mut(parent).unwrap(), 0x240, 0x1240);
+
+    step_syscall(&mut kernel, parent, 0x016B, &[]);
+    let regs = kernel.proc(parent).unwrap().regs;
+    assert_eq!(regs.V[0xF], 0);
+    let (read_fd, write_fd) = (regs.V[0] as u16, regs.V[1] as u16);
+    let thread = step_syscall(&mut kernel, parent, 0x0101, &[0x0240, 0, 0, 2]).0 as u32;
+
+    // the thread holds the same fds and writes; the parent reads.
+    assert_eq!(step_syscall(&mut kernel, thread, 0x0124, &[write_fd, 0x0360, 5]), (5, 0));
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0122, &[read_fd, 0x0500, 16]), (5, 0));
+    assert_eq!(kernel.proc_mut(parent).unwrap().read_bytes(0x0500, 5).unwrap(), b"hello");
+
+    // an empty pipe blocks until every write end is closed, then reads 0.
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0123, &[write_fd]).1, 0);
+    step_syscall(&mut kernel, parent, 0x0122, &[read_fd, 0x0500, 16]);
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Blocked));
+    assert_eq!(step_syscall(&mut kernel, thread, 0x0123, &[write_fd]).1, 0);
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Running));
+    let regs = kernel.proc(parent).unwrap().regs;
+    assert_eq!((regs.V[0], regs.V[0xF]), (0, 0));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn dup_shares_a_files_position_and_a_pipes_write_end() {
+    set_headless();
+    let root = temp_root("dup");
+    fs::write(root.join("data.bin"), b"abcdef").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"data.bin").unwrap();
+
+    let fd = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 8]).0 as u16;
+    let (copy, err) = step_syscall(&mut kernel, pid, 0x016C, &[fd]);
+    assert_eq!(err, 0);
+    assert_ne!(copy as u16, fd);
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[fd, 0x0500, 2]), (2, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[copy as u16, 0x0500, 2]), (2, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 2).unwrap(), b"cd");
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0123, &[fd]).1, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[copy as u16, 0x0500, 2]), (2, 0));
+    assert_eq!(kernel.proc_mut(pid).unwrap().read_bytes(0x0500, 2).unwrap(), b"ef");
+
+    // a pipe stays open for writing until every copy of its write end closes.
+    step_syscall(&mut kernel, pid, 0x016B, &[]);
+    let regs = kernel.proc(pid).unwrap().regs;
+    let (read_fd, write_fd) = (regs.V[0] as u16, regs.V[1] as u16);
+    let write_copy = step_syscall(&mut kernel, pid, 0x016C, &[write_fd]).0 as u16;
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0123, &[write_fd]).1, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0124, &[write_copy, 0x0340, 4]), (4, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0123, &[write_copy]).1, 0);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[read_fd, 0x0500, 16]), (4, 0));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0122, &[read_fd, 0x0500, 16]), (0, 0));
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x016C, &[0x42]), (0x04, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_spawn_thread_shares_the_parents_pages() {
+    set_headless();
+    let root = temp_root("spawn_thread");
+    let mem = Arc::new(Mutex::new(SharedMemory::new().unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, Arc::clone(&mem));
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    {
+        let proc = kernel.proc_mut(parent).unwrap();
+        // thread body: V0 = 0x77; I = 0x400; store V0; spin.
+        write_opcode(proc, 0x240, 0x6077);
+        write_opcode(proc, 0x242, 0xA400);
+        write_opcode(proc, 0x244, 0xF055);
+        write_opcode(proc, 0x246, 0x1246);
+    }
+
+    let before = mem.lock().unwrap().used_page_count();
+    let (thread, err) = step_syscall(&mut kernel, parent, 0x0101, &[0x0240, 0, 0, 2]);
+    assert_eq!(err, 0);
+    let thread = thread as u32;
+    // no pages allocated: the thread aliases the parent's.
+    assert_eq!(mem.lock().unwrap().used_page_count(), before);
+    assert_eq!(kernel.pgid(thread), Some(parent));
+    {
+        let proc = kernel.proc(thread).unwrap();
+        assert_eq!(proc.page_table, kernel.proc(parent).unwrap().page_table);
+        assert_eq!(proc.regs.PC, 0x240);
+        assert_eq!(proc.regs.SP as u32, proc.vm_size - 0x40);
+    }
+
+    for _ in 0..3 {
+        kernel.step_proc(thread).unwrap();
+    }
+    assert_eq!(kernel.proc_mut(parent).unwrap().read_u8(0x400).unwrap(), 0x77);
+
+    // an entry outside the VM is rejected.
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0101, &[0xFFFE, 0, 0, 2]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn owner_pages_outlive_it_while_a_thread_runs() {
+    set_headless();
+    let root = temp_root("thread_owner_exit");
+    let mem = Arc::new(Mutex::new(SharedMemory::with_capacity(2).unwrap()));
+    let mut kernel = make_kernel_with_mem(&root, Arc::clone(&mem));
+    let owner = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    write_opcode(kernel.proc_mut(owner).unwrap(), 0x240, 0x1240); // thread spins
+    let thread = step_syscall(&mut kernel, owner, 0x0101, &[0x0240, 0, 0, 2]).0 as u32;
+    kernel.proc_mut(owner).unwrap().write_u8(0x400, 0x5A).unwrap();
+
+    step_syscall(&mut kernel, owner, 0x0102, &[0]);
+    kernel.reap_exited();
+    assert!(kernel.proc(owner).is_none());
+    assert_eq!(mem.lock().unwrap().used_page_count(), 1);
+
+    // the new proc gets the arena's other page, not the thread's.
+    let other = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    let thread_pages = kernel.proc(thread).unwrap().page_table.clone();
+    assert!(kernel.proc(other).unwrap().page_table.iter().all(|page| !thread_pages.contains(page)));
+    assert_eq!(kernel.proc_mut(other).unwrap().read_u8(0x400).unwrap(), 0);
+    kernel.proc_mut(thread).unwrap().write_u8(0x400, 0xA5).unwrap();
+    assert_eq!(kernel.proc_mut(other).unwrap().read_u8(0x400).unwrap(), 0);
+    assert!(kernel.spawn_proc(DisplayWindow::headless(), 1).is_err());
+
+    // the thread going frees the owner's pages.
+    kernel.proc_mut(thread).unwrap().regs.PC = 0x250;
+    step_syscall(&mut kernel, thread, 0x0102, &[0]);
+    kernel.reap_exited();
+    assert_eq!(mem.lock().unwrap().used_page_count(), 1);
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_fs_count_counts_dir_entries_and_rejects_files() {
+    set_headless();
+    let root = temp_root("fs_count");
+    fs::create_dir(root.join("five")).unwrap();
+    for name in ["a", "b", "c", "d"] {
+        fs::write(root.join("five").join(name), b"x").unwrap();
+    }
+    fs::create_dir(root.join("five").join("sub")).unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"five/a").unwrap();
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x013A, &[0x0340, 4]), (0, 0));
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[1], 5);
+    assert_eq!(step_syscall(&mut kernel, pid, 0x013A, &[0x0340, 6]), (0x05, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x013A, &[0x0340]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn sys_console_goto_positions_next_write() {
+    set_headless();
+    let root = temp_root("console_goto");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0113, &[1]).1, 0);
+
+    assert_eq!(step_syscall(&mut kernel, pid, 0x011F, &[5, 2]).1, 0);
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"X").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0110, &[0x0340, 1]), (1, 0));
+    let display = &kernel.proc(pid).unwrap().display;
+    assert_eq!(display.console_cell(5, 2), Some(b'X'));
+    assert_eq!(display.console_cursor(), (6, 2));
+
+    // cells outside the grid are rejected and the cursor stays put.
+    let (cols, rows) = display.console_dimensions();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x011F, &[cols as u16, 0]), (0x02, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x011F, &[0, rows as u16]), (0x02, 1));
+    assert_eq!(kernel.proc(pid).unwrap().display.console_cursor(), (6, 2));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn converted_handlers_reject_short_frames_like_before() {
+    set_headless();
+    let root = temp_root("short_frames");
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0113, &[1]).1, 0);
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"hi").unwrap();
+
+    // one arg short, and a length byte that stops half-way through arg1.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0110, &[0x0340]), (0x02, 1));
+    {
+        let proc = kernel.proc_mut(pid).unwrap();
+        write_frame(proc, 0x300, &[0x0340, 2]);
+        proc.write_u8(0x300, 4).unwrap();
+        write_opcode(proc, proc.regs.PC, 0x0110);
+    }
+    let _ = kernel.step_proc(pid).unwrap();
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[0], 0x02);
+    assert_eq!(kernel.proc(pid).unwrap().regs.V[0xF], 1);
+    assert_eq!(kernel.proc(pid).unwrap().display.console_cursor(), (0, 0));
+
+    // the same call with a complete frame succeeds.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0110, &[0x0340, 2]), (2, 0));
+    assert_eq!(kernel.proc(pid).unwrap().display.console_cursor(), (2, 0));
+
+    // value checks folded into the pattern still reject bad values.
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0112, &[2]), (0x02, 1));
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0112, &[]), (0x02, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+/// echo.ch8: read one line from the console, write it back, exit(0).
+fn echo_rom() -> Vec<u8> {
+    let mut rom = vec![0u8; 0x3B];
+    let code = [
+        0xA2, 0x20, // I := 0x220 (read frame)
+        0x01, 0x11, // sys read
+        0xA2, 0x34, // I := 0x234 (write frame, arg1 low byte)
+        0xF0, 0x55, // store V0 = bytes read
+        0xA2, 0x30, // I := 0x230 (write frame)
+        0x01, 0x10, // sys write
+        0xA2, 0x38, // I := 0x238 (exit frame)
+        0x01, 0x02, // sys exit
+        0x12, 0x10, // jump self
+    ];
+    rom[..code.len()].copy_from_slice(&code);
+    rom[0x20..0x25].copy_from_slice(&[0x05, 0x02, 0x40, 0x00, 0x40]);
+    rom[0x30..0x35].copy_from_slice(&[0x05, 0x02, 0x40, 0x00, 0x00]);
+    rom[0x38..0x3B].copy_from_slice(&[0x03, 0x00, 0x00]);
+    rom
+}
+
+#[test]
+fn sys_spawn_pipe_drives_an_echo_child() {
+    set_headless();
+    let root = temp_root("spawn_pipe");
+    fs::write(root.join("echo.ch8"), echo_rom()).unwrap();
+    let mut kernel = make_kernel(&root);
+    let parent = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+    kernel.proc_mut(parent).unwrap().write_bytes(0x340, b"echo.ch8").unwrap();
+
+    let (child, err) = step_syscall(&mut kernel, parent, 0x016A, &[0x0340, 8]);
+    assert_eq!(err, 0);
+    let child = child as u32;
+    let (stdin_fd, stdout_fd) = {
+        let regs = &kernel.proc(parent).unwrap().regs;
+        (regs.V[1] as u16, regs.V[2] as u16)
+    };
+
+    // the child blocks on its empty stdin pipe until the parent writes.
+    kernel.step_proc(child).unwrap();
+    kernel.step_proc(child).unwrap();
+    assert_eq!(kernel.proc_state(child), Some(ProcState::Blocked));
+    kernel.proc_mut(parent).unwrap().write_bytes(0x360, b"hi\n").unwrap();
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0124, &[stdin_fd, 0x0360, 3]), (3, 0));
+    assert_eq!(kernel.proc_state(child), Some(ProcState::Running));
+
+    // the parent blocks on the child's stdout until the echo arrives.
+    step_syscall(&mut kernel, parent, 0x0122, &[stdout_fd, 0x0380, 0x10]);
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Blocked));
+    while kernel.proc_state(child) == Some(ProcState::Running) {
+        kernel.step_proc(child).unwrap();
+    }
+    assert_eq!(kernel.proc_state(child), Some(ProcState::Exited));
+    assert_eq!(kernel.proc_state(parent), Some(ProcState::Running));
+    let proc = kernel.proc_mut(parent).unwrap();
+    assert_eq!((proc.regs.V[0], proc.regs.V[0xF]), (3, 0));
+    assert_eq!(proc.read_bytes(0x0380, 3).unwrap(), b"hi\n");
+
+    // with the child gone its stdout reads as end-of-file and stdin is broken.
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0122, &[stdout_fd, 0x0380, 0x10]), (0, 0));
+    assert_eq!(step_syscall(&mut kernel, parent, 0x0124, &[stdin_fd, 0x0360, 3]), (0x03, 1));
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_created_after_init_are_rejected() {
+    use std::os::unix::fs::symlink;
+
+    set_headless();
+    let root = temp_root("symlink_root");
+    let outside = temp_root("symlink_outside");
+    fs::write(outside.join("secret.txt"), b"secret").unwrap();
+    fs::write(root.join("real.txt"), b"real").unwrap();
+    let mut kernel = make_kernel(&root);
+    let pid = kernel.spawn_proc(DisplayWindow::headless(), 1).unwrap();
+
+    // links appear only after validate_root_layout ran at construction.
+    symlink(outside.join("secret.txt"), root.join("escape")).unwrap();
+    symlink(&outside, root.join("dirlink")).unwrap();
+    symlink(root.join("real.txt"), root.join("alias")).unwrap();
+
+    for name in ["escape", "dirlink/secret.txt", "alias"] {
+        kernel.proc_mut(pid).unwrap().write_bytes(0x340, name.as_bytes()).unwrap();
+        let result = step_syscall(&mut kernel, pid, 0x0121, &[0x0340, name.len() as u16]);
+        assert_eq!(result, (0x0D, 1), "opened {name}");
+    }
+    // spawn resolves ROM names through the same check.
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"escape").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0101, &[0x0340, 6]).1, 1);
+
+    kernel.proc_mut(pid).unwrap().write_bytes(0x340, b"real.txt").unwrap();
+    assert_eq!(step_syscall(&mut kernel, pid, 0x0121, &[0x0340, 8]).1, 0);
+
+    let _ = fs::remove_dir_all(root);
+    let _ = fs::remove_dir_all(outside);
+}